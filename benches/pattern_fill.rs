@@ -0,0 +1,32 @@
+//! Compares the pattern fill used by Gutmann passes with a byte-by-byte fill
+//! over a 64 MB buffer: `cargo bench --bench pattern_fill`
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+#[path = "../src/pattern.rs"]
+mod pattern;
+
+const BUFFER_LEN: usize = 64 * 1024 * 1024;
+
+fn fill_naive(buffer: &mut [u8], pattern: &[u8]) {
+    for (i, byte) in buffer.iter_mut().enumerate() {
+        *byte = pattern[i % pattern.len()];
+    }
+}
+
+fn pattern_fill(c: &mut Criterion) {
+    let pattern = [0x92, 0x49, 0x24];
+    let mut buffer = vec![0u8; BUFFER_LEN];
+
+    let mut group = c.benchmark_group("gutmann_3_byte_pattern");
+    group.throughput(Throughput::Bytes(BUFFER_LEN as u64));
+    group.sample_size(10);
+    group.bench_function("naive", |b| b.iter(|| fill_naive(&mut buffer, &pattern)));
+    group.bench_function("doubling", |b| {
+        b.iter(|| pattern::fill_repeating(&mut buffer, &pattern))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, pattern_fill);
+criterion_main!(benches);
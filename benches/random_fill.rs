@@ -0,0 +1,67 @@
+//! Compares the generators of random passes filling 4 MB buffers, and the
+//! worker pool with generating on the writer thread:
+//! `cargo bench --bench random_fill`
+
+use aligned_vec::avec_rt;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+#[allow(dead_code)]
+#[path = "../src/generator.rs"]
+mod generator;
+
+use generator::{RandomPool, RandomStream, RngKind};
+
+const BUFFER_LEN: usize = 4 * 1024 * 1024;
+
+fn rng_fill(c: &mut Criterion) {
+    let mut buffer = vec![0u8; BUFFER_LEN];
+
+    let mut group = c.benchmark_group("rng_fill");
+    group.throughput(Throughput::Bytes(BUFFER_LEN as u64));
+    group.sample_size(10);
+    for rng in [RngKind::Os, RngKind::Chacha, RngKind::AesCtr, RngKind::Fast] {
+        let stream = RandomStream::new(rng, 1);
+        let mut offset = 0u64;
+        group.bench_function(rng.name(), |b| {
+            b.iter(|| {
+                stream.fill(offset, &mut buffer);
+                offset += BUFFER_LEN as u64;
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Buffers of a pass taken from a pool of workers, against the same data
+/// generated on the writer thread
+fn pool_exchange(c: &mut Criterion) {
+    const PASS_BUFFERS: u64 = 1 << 20;
+    let stream = RandomStream::new(RngKind::Chacha, 1);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get().min(4));
+    let mut buffer = avec_rt!([4096]| 0u8; BUFFER_LEN);
+
+    let mut group = c.benchmark_group("random_pool");
+    group.throughput(Throughput::Bytes(BUFFER_LEN as u64));
+    group.sample_size(10);
+    let mut offset = 0u64;
+    group.bench_function("single_thread", |b| {
+        b.iter(|| {
+            stream.fill(offset, &mut buffer);
+            offset += BUFFER_LEN as u64;
+        })
+    });
+    let mut pool = RandomPool::new(
+        threads,
+        &stream,
+        vec![(0, PASS_BUFFERS * BUFFER_LEN as u64)],
+        BUFFER_LEN,
+        4096,
+    );
+    group.bench_function(format!("pool_{}_threads", threads), |b| {
+        b.iter(|| pool.exchange(&mut buffer))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, rng_fill, pool_exchange);
+criterion_main!(benches);
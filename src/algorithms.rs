@@ -1,12 +1,54 @@
-use crate::args::WipeAlgorithm;
+use anyhow::Result;
+use clap::ValueEnum;
+use rand::{thread_rng, RngCore};
+use serde::Serialize;
+use std::panic::{self, AssertUnwindSafe};
 
-#[derive(Debug)]
+use crate::{args::WipeAlgorithm, pattern::fill_repeating, progress::to_json};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum WipePattern {
     Fixed(u8),
     Random,
     Gutmann(Vec<Vec<u8>>),
 }
 
+impl WipePattern {
+    /// Random patterns must be regenerated for every write to avoid repeating data
+    pub fn is_random(&self) -> bool {
+        matches!(self, WipePattern::Random)
+    }
+
+    /// Name of the pattern in progress output and events, e.g. `0xFF` or `RAND`
+    pub fn name(&self) -> String {
+        match self {
+            WipePattern::Fixed(byte) => format!("0x{:02X}", byte),
+            WipePattern::Random => "RAND".to_string(),
+            WipePattern::Gutmann(_) => "GUTM".to_string(),
+        }
+    }
+
+    /// Bytes repeated across the target on the given pass, `None` for random data
+    pub fn unit(&self, pass: usize) -> Option<&[u8]> {
+        match self {
+            WipePattern::Fixed(byte) => Some(std::slice::from_ref(byte)),
+            WipePattern::Gutmann(patterns) => Some(&patterns[(pass - 1) % patterns.len()]),
+            WipePattern::Random => None,
+        }
+    }
+
+    /// Fill `buffer` with the data this pattern writes on the given pass
+    pub fn fill(&self, buffer: &mut [u8], pass: usize) {
+        match self {
+            WipePattern::Fixed(byte) => buffer.fill(*byte),
+            WipePattern::Gutmann(patterns) => {
+                fill_repeating(buffer, &patterns[(pass - 1) % patterns.len()]);
+            }
+            WipePattern::Random => thread_rng().fill_bytes(buffer),
+        }
+    }
+}
+
 // Gutmann method patterns
 pub const GUTMANN_PATTERNS: &[&[u8]] = &[
     &[0x00],
@@ -40,13 +82,148 @@ pub const GUTMANN_PATTERNS: &[&[u8]] = &[
     &[0xDB, 0x6D, 0xB6],
 ];
 
-pub fn get_algorithm_pass_count(algorithm: &WipeAlgorithm, custom_passes: usize) -> usize {
-    match algorithm {
-        WipeAlgorithm::Zero | WipeAlgorithm::Random => 1,
-        WipeAlgorithm::Dod5220 => 3,
-        WipeAlgorithm::Gutmann => 35,
-        WipeAlgorithm::Custom => custom_passes,
+/// Parse a `--passes-spec` list such as `00,FF,random,AA` into the pattern of
+/// each pass: a hex byte, with or without `0x`, is written as a fixed pattern
+/// and `random` as random data
+pub fn parse_passes_spec(spec: &str) -> Result<Vec<WipePattern>, String> {
+    spec.split(',')
+        .map(|token| {
+            let token = token.trim();
+            if token.eq_ignore_ascii_case("random") {
+                return Ok(WipePattern::Random);
+            }
+            let digits = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .unwrap_or(token);
+            if digits.len() == 2 && digits.chars().all(|digit| digit.is_ascii_hexdigit()) {
+                Ok(WipePattern::Fixed(u8::from_str_radix(digits, 16).unwrap()))
+            } else {
+                Err(format!(
+                    "'{}' is neither a hex byte such as 00 or 0xFF nor 'random'",
+                    token
+                ))
+            }
+        })
+        .collect()
+}
+
+/// What an algorithm writes: the wipe takes its pass count and pattern names
+/// from here, and `--list-algorithms` prints it, so the two cannot disagree
+#[derive(Debug, Clone, Serialize)]
+pub struct AlgorithmDescriptor {
+    /// Value given to `--algorithm`
+    pub name: String,
+    /// Other values `--algorithm` accepts for it
+    pub aliases: Vec<&'static str>,
+    pub passes: usize,
+    /// Name of the pattern of each pass, as in the progress output
+    pub patterns: Vec<&'static str>,
+    /// Whether the algorithm reads the target back by itself, without `--verify`
+    pub implies_verification: bool,
+    /// Estimated runtime as a multiple of a single-pass wipe of the same target
+    pub relative_runtime: f64,
+}
+
+impl AlgorithmDescriptor {
+    /// Pattern names with runs of the same pattern collapsed, e.g. `0x00, RAND x2`
+    pub fn pattern_summary(&self) -> String {
+        let mut runs: Vec<(&str, usize)> = Vec::new();
+        for &pattern in &self.patterns {
+            match runs.last_mut() {
+                Some((last, count)) if *last == pattern => *count += 1,
+                _ => runs.push((pattern, 1)),
+            }
+        }
+        runs.iter()
+            .map(|&(pattern, count)| match count {
+                1 => pattern.to_string(),
+                _ => format!("{} x{}", pattern, count),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+pub fn describe_algorithm(algorithm: &WipeAlgorithm, custom_passes: usize) -> AlgorithmDescriptor {
+    let patterns = match algorithm {
+        WipeAlgorithm::Zero => vec!["0x00"],
+        WipeAlgorithm::Random => vec!["RAND"],
+        WipeAlgorithm::Dod5220 => vec!["0x00", "0xFF", "RAND"],
+        WipeAlgorithm::Gutmann => vec!["GUTM"; 35],
+        WipeAlgorithm::Custom => vec!["RAND"; custom_passes],
+    };
+    let value = algorithm
+        .to_possible_value()
+        .expect("algorithms are never skipped");
+
+    AlgorithmDescriptor {
+        name: value.get_name().to_string(),
+        aliases: match algorithm {
+            WipeAlgorithm::Random => vec!["prng"],
+            WipeAlgorithm::Dod5220 => vec!["dod"],
+            _ => Vec::new(),
+        },
+        passes: patterns.len(),
+        // Each pass writes the whole target once
+        relative_runtime: patterns.len() as f64,
+        patterns,
+        // No algorithm reads its passes back; that takes --verify or --verify-each-pass
+        implies_verification: false,
+    }
+}
+
+/// Print the descriptor of every algorithm, as a table or as one JSON object
+pub fn list_algorithms(custom_passes: usize, json: bool) -> Result<()> {
+    let algorithms: Vec<AlgorithmDescriptor> = WipeAlgorithm::value_variants()
+        .iter()
+        .map(|algorithm| describe_algorithm(algorithm, custom_passes))
+        .collect();
+
+    if json {
+        let json_output = serde_json::json!({
+            "type": "algorithm_list",
+            "algorithms": algorithms
+        });
+        println!("{}", to_json(&json_output)?);
+        return Ok(());
     }
+
+    println!(
+        "{:<10} {:<8} {:>6}  {:<20} {:<9} {:>8}",
+        "ALGORITHM", "ALIASES", "PASSES", "PATTERNS", "VERIFIES", "RUNTIME"
+    );
+    for algorithm in &algorithms {
+        let aliases = if algorithm.aliases.is_empty() {
+            "-".to_string()
+        } else {
+            algorithm.aliases.join(", ")
+        };
+        println!(
+            "{:<10} {:<8} {:>6}  {:<20} {:<9} {:>7}x",
+            algorithm.name,
+            aliases,
+            algorithm.passes,
+            algorithm.pattern_summary(),
+            if algorithm.implies_verification {
+                "yes"
+            } else {
+                "no"
+            },
+            algorithm.relative_runtime
+        );
+    }
+    println!();
+    println!(
+        "RUNTIME is relative to a single-pass wipe; custom is shown with {} passes.",
+        custom_passes
+    );
+
+    Ok(())
+}
+
+pub fn get_algorithm_pass_count(algorithm: &WipeAlgorithm, custom_passes: usize) -> usize {
+    describe_algorithm(algorithm, custom_passes).passes
 }
 
 pub fn get_pass_pattern(algorithm: &WipeAlgorithm, pass: usize) -> WipePattern {
@@ -67,17 +244,320 @@ pub fn get_pass_pattern(algorithm: &WipeAlgorithm, pass: usize) -> WipePattern {
     }
 }
 
+/// One pass of a wipe: what it writes, what it is called in the progress
+/// output and whether it is read back before the next pass starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassSpec {
+    pub pass: usize,
+    pub pattern: WipePattern,
+    pub name: String,
+    pub verify: bool,
+}
+
+/// Passes of an algorithm in the order they are written; the wipe iterates
+/// this plan and `--show-passes` prints it
+pub fn get_pass_plan(algorithm: &WipeAlgorithm, custom_passes: usize) -> Vec<PassSpec> {
+    let descriptor = describe_algorithm(algorithm, custom_passes);
+    (1..=descriptor.passes)
+        .map(|pass| PassSpec {
+            pass,
+            pattern: get_pass_pattern(algorithm, pass),
+            name: get_pattern_name(algorithm, pass).to_string(),
+            verify: descriptor.implies_verification,
+        })
+        .collect()
+}
+
+/// Print the passes a wipe will run, as a table or as one JSON object
+pub fn show_pass_plan(algorithm: &str, plan: &[PassSpec], json: bool) -> Result<()> {
+    let hex = |pass: &PassSpec| {
+        pass.pattern.unit(pass.pass).map(|unit| {
+            unit.iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    };
+
+    if json {
+        let passes: Vec<_> = plan
+            .iter()
+            .map(|pass| {
+                serde_json::json!({
+                    "pass": pass.pass,
+                    "pattern": pass.name,
+                    "bytes": hex(pass),
+                    "random": pass.pattern.is_random(),
+                    "verify": pass.verify,
+                })
+            })
+            .collect();
+        let json_output = serde_json::json!({
+            "type": "pass_plan",
+            "algorithm": algorithm,
+            "total_passes": plan.len(),
+            "passes": passes
+        });
+        println!("{}", to_json(&json_output)?);
+        return Ok(());
+    }
+
+    println!("{} algorithm, {} passes:", algorithm, plan.len());
+    println!(
+        "{:>4}  {:<12} {:<12} {:<7} VERIFY",
+        "PASS", "PATTERN", "BYTES", "RANDOM"
+    );
+    for pass in plan {
+        println!(
+            "{:>4}  {:<12} {:<12} {:<7} {}",
+            pass.pass,
+            pass.name,
+            hex(pass).unwrap_or_else(|| "-".to_string()),
+            if pass.pattern.is_random() {
+                "yes"
+            } else {
+                "no"
+            },
+            if pass.verify { "yes" } else { "no" }
+        );
+    }
+
+    Ok(())
+}
+
 pub fn get_pattern_name(algorithm: &WipeAlgorithm, pass: usize) -> &'static str {
+    // Custom passes are all alike, so describing `pass` of them covers this pass
+    describe_algorithm(algorithm, pass)
+        .patterns
+        .get(pass.wrapping_sub(1))
+        .copied()
+        .unwrap_or("????")
+}
+
+/// Result of checking the pattern tables of one algorithm with `--self-test`
+#[derive(Debug)]
+pub struct AlgorithmCheck {
+    pub algorithm: WipeAlgorithm,
+    pub passes: usize,
+    /// Every inconsistency found, empty when the algorithm passed
+    pub failures: Vec<String>,
+}
+
+impl AlgorithmCheck {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Pass count each algorithm is documented to have
+fn documented_pass_count(algorithm: &WipeAlgorithm, custom_passes: usize) -> usize {
     match algorithm {
-        WipeAlgorithm::Zero => "0x00",
-        WipeAlgorithm::Random => "RAND",
-        WipeAlgorithm::Dod5220 => match pass {
-            1 => "0x00",
-            2 => "0xFF",
-            3 => "RAND",
-            _ => "????",
-        },
-        WipeAlgorithm::Gutmann => "GUTM",
-        WipeAlgorithm::Custom => "RAND",
+        WipeAlgorithm::Zero | WipeAlgorithm::Random => 1,
+        WipeAlgorithm::Dod5220 => 3,
+        WipeAlgorithm::Gutmann => 35,
+        WipeAlgorithm::Custom => custom_passes,
+    }
+}
+
+/// Check that the pass count, pattern and pattern name of every pass of every
+/// algorithm agree with each other, so a table edit that breaks one of them
+/// is caught before it reaches a wipe
+pub fn self_test(custom_passes: usize) -> Vec<AlgorithmCheck> {
+    WipeAlgorithm::value_variants()
+        .iter()
+        .map(|algorithm| check_algorithm(algorithm, custom_passes))
+        .collect()
+}
+
+fn check_algorithm(algorithm: &WipeAlgorithm, custom_passes: usize) -> AlgorithmCheck {
+    let passes = get_algorithm_pass_count(algorithm, custom_passes);
+    let mut failures = Vec::new();
+
+    let expected = documented_pass_count(algorithm, custom_passes);
+    if passes != expected {
+        failures.push(format!("{} passes, expected {}", passes, expected));
+    }
+    if passes == 0 {
+        failures.push("no passes".to_string());
+    }
+
+    for pass in 1..=passes {
+        let checked = panic::catch_unwind(AssertUnwindSafe(|| {
+            let pattern = get_pass_pattern(algorithm, pass);
+            let name = get_pattern_name(algorithm, pass);
+            check_pass(&pattern, name, pass)
+        }));
+        match checked {
+            Ok(Ok(())) => {}
+            Ok(Err(failure)) => failures.push(format!("pass {}: {}", pass, failure)),
+            Err(_) => failures.push(format!("pass {}: panicked", pass)),
+        }
+    }
+
+    AlgorithmCheck {
+        algorithm: algorithm.clone(),
+        passes,
+        failures,
+    }
+}
+
+/// Check that a pattern is named after its kind and fills buffers the way it says
+fn check_pass(pattern: &WipePattern, name: &str, pass: usize) -> Result<(), String> {
+    let mut buffer = [0u8; 64];
+
+    match pattern {
+        WipePattern::Fixed(byte) => {
+            let expected = format!("0x{:02X}", byte);
+            if name != expected {
+                return Err(format!("fixed 0x{:02X} pattern named {:?}", byte, name));
+            }
+            pattern.fill(&mut buffer, pass);
+            if buffer.iter().any(|b| b != byte) {
+                return Err(format!("fixed 0x{:02X} pattern wrote other bytes", byte));
+            }
+        }
+        WipePattern::Random => {
+            if name != "RAND" {
+                return Err(format!("random pattern named {:?}", name));
+            }
+            pattern.fill(&mut buffer, pass);
+            if buffer.iter().all(|&b| b == buffer[0]) {
+                return Err("random pattern wrote a constant buffer".to_string());
+            }
+        }
+        WipePattern::Gutmann(patterns) => {
+            if name != "GUTM" {
+                return Err(format!("Gutmann pattern named {:?}", name));
+            }
+            if patterns.is_empty() || patterns.iter().any(Vec::is_empty) {
+                return Err("Gutmann table has an empty pattern".to_string());
+            }
+            pattern.fill(&mut buffer, pass);
+            let expected = &patterns[(pass - 1) % patterns.len()];
+            if (0..buffer.len()).any(|i| buffer[i] != expected[i % expected.len()]) {
+                return Err(format!("Gutmann pattern did not write {:02X?}", expected));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_fill_matches_naive_fill() {
+        let naive = |len: usize, pattern: &[u8]| -> Vec<u8> {
+            (0..len).map(|i| pattern[i % pattern.len()]).collect()
+        };
+
+        for pattern_len in 1..=8 {
+            let pattern: Vec<u8> = (1..=pattern_len as u8).map(|i| i * 0x11).collect();
+            let wipe_pattern = WipePattern::Gutmann(vec![pattern.clone()]);
+            for len in [0, 1, 7, 63, 64, 65, 192, 1000, 4096, 65_537] {
+                let mut buffer = vec![0u8; len];
+                wipe_pattern.fill(&mut buffer, 1);
+                assert_eq!(
+                    buffer,
+                    naive(len, &pattern),
+                    "pattern {:?}, length {}",
+                    pattern,
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_self_test_passes() {
+        for custom_passes in [1, 3, 7] {
+            let checks = self_test(custom_passes);
+            assert_eq!(checks.len(), WipeAlgorithm::value_variants().len());
+            for check in checks {
+                assert!(
+                    check.passed(),
+                    "{:?}: {:?}",
+                    check.algorithm,
+                    check.failures
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_pass_rejects_mismatched_names() {
+        assert!(check_pass(&WipePattern::Fixed(0xFF), "0xFF", 1).is_ok());
+        assert!(check_pass(&WipePattern::Fixed(0x00), "0xFF", 1).is_err());
+        assert!(check_pass(&WipePattern::Random, "0x00", 1).is_err());
+        assert!(check_pass(&WipePattern::Gutmann(vec![vec![]]), "GUTM", 1).is_err());
+    }
+
+    #[test]
+    fn test_algorithm_descriptors() {
+        let dod = describe_algorithm(&WipeAlgorithm::Dod5220, 3);
+        assert_eq!(dod.name, "dod5220");
+        assert_eq!(dod.aliases, vec!["dod"]);
+        assert_eq!(dod.pattern_summary(), "0x00, 0xFF, RAND");
+        assert_eq!(dod.relative_runtime, 3.0);
+
+        let gutmann = describe_algorithm(&WipeAlgorithm::Gutmann, 3);
+        assert_eq!(gutmann.pattern_summary(), "GUTM x35");
+        assert_eq!(describe_algorithm(&WipeAlgorithm::Custom, 7).passes, 7);
+
+        // Aliases named in the table are the ones clap accepts
+        for algorithm in WipeAlgorithm::value_variants() {
+            for alias in describe_algorithm(algorithm, 3).aliases {
+                let parsed = WipeAlgorithm::from_str(alias, true).unwrap();
+                assert_eq!(
+                    format!("{:?}", parsed),
+                    format!("{:?}", algorithm),
+                    "{}",
+                    alias
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_pass_plan() {
+        let plan = get_pass_plan(&WipeAlgorithm::Dod5220, 3);
+        assert_eq!(
+            plan.iter().map(|pass| pass.pass).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert_eq!(plan[1].pattern.unit(2), Some(&[0xFF][..]));
+        assert!(plan[2].pattern.is_random());
+
+        let gutmann = get_pass_plan(&WipeAlgorithm::Gutmann, 3);
+        assert_eq!(gutmann.len(), 35);
+        assert_eq!(gutmann[4].pattern.unit(5), Some(&[0x92, 0x49, 0x24][..]));
+        assert_eq!(get_pass_plan(&WipeAlgorithm::Custom, 5).len(), 5);
+    }
+
+    #[test]
+    fn test_parse_passes_spec() {
+        assert_eq!(
+            parse_passes_spec("00,FF,random,AA").unwrap(),
+            vec![
+                WipePattern::Fixed(0x00),
+                WipePattern::Fixed(0xFF),
+                WipePattern::Random,
+                WipePattern::Fixed(0xAA),
+            ]
+        );
+        assert_eq!(
+            parse_passes_spec("0x55, RANDOM ,0Xaa").unwrap(),
+            vec![
+                WipePattern::Fixed(0x55),
+                WipePattern::Random,
+                WipePattern::Fixed(0xAA),
+            ]
+        );
+
+        for invalid in ["", "00,,FF", "0", "FFF", "GG", "+F", "zero", "00,FF,"] {
+            assert!(parse_passes_spec(invalid).is_err(), "{:?}", invalid);
+        }
     }
 }
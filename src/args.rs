@@ -1,13 +1,35 @@
 use clap::{Parser, ValueEnum};
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    algorithms::{parse_passes_spec, WipePattern},
+    demo::parse_demo_size,
+    generator::RngKind,
+    targets::parse_duration,
+    throttle::parse_rate,
+    verify::parse_hex_pattern,
+    wipe::parse_buffer_size,
+};
+
+/// Passes of the custom algorithm when `--passes` is not given
+pub const DEFAULT_PASSES: u16 = 3;
+
+/// Size of the demo file when `--demo-size` is not given
+pub const DEFAULT_DEMO_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Most passes `--passes` accepts without `--yes-really`
+pub const MAX_PASSES: u16 = 100;
 
 #[derive(Debug, Clone, ValueEnum)]
 pub enum WipeAlgorithm {
     /// Simple zero overwrite (1 pass)
     Zero,
     /// Random data overwrite (1 pass)
+    #[value(alias = "prng")]
     Random,
     /// DoD 5220.22-M standard (3 passes: 0x00, 0xFF, random)
+    #[value(alias = "dod")]
     Dod5220,
     /// Gutmann method (35 passes)
     Gutmann,
@@ -15,55 +37,553 @@ pub enum WipeAlgorithm {
     Custom,
 }
 
+#[derive(Debug, Clone, Default, PartialEq, ValueEnum)]
+pub enum DemoContent {
+    /// Random bytes
+    Random,
+    /// The first pass pattern of the selected algorithm
+    Pattern,
+    /// Repeated "DEMO DATA" text
+    #[default]
+    Text,
+}
+
+/// When written data is forced to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    /// Never during the passes; the OS writes data back whenever it likes
+    None,
+    /// Flush at the end of each pass
+    Pass,
+    /// Start writing back every --sync-interval MB, and flush at the end of each pass
+    Interval,
+    /// Synchronous writes (O_SYNC, or write-through on Windows), each on disk when it returns
+    Always,
+}
+
+impl SyncMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyncMode::None => "none",
+            SyncMode::Pass => "pass",
+            SyncMode::Interval => "interval",
+            SyncMode::Always => "always",
+        }
+    }
+
+    /// What the mode guarantees about the written data, for the completion summary
+    pub fn guarantee(&self) -> &'static str {
+        match self {
+            SyncMode::None => {
+                "no flush during the passes; data may still have been cached when each pass ended"
+            }
+            SyncMode::Pass | SyncMode::Interval => {
+                "each pass was flushed to disk before the next one started"
+            }
+            SyncMode::Always => "each write reached the disk before the next one was issued",
+        }
+    }
+}
+
+/// How wipe writes are issued to the target
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum IoBackend {
+    /// One blocking write per buffer
+    Sync,
+    /// Several writes in flight at once through io_uring
+    Uring,
+}
+
+/// I/O scheduling class selected with `--io-priority`
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum IoPriority {
+    /// Disk time only when no other process wants it
+    Idle,
+    /// The default class, at its middle priority level
+    BestEffort,
+    /// Disk time ahead of every other process (needs CAP_SYS_ADMIN)
+    Rt,
+}
+
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum DriveType {
+    /// Whole physical disks
+    Disk,
+    /// Partitions
+    Part,
+    /// Logical volumes (Windows drive letters, APFS volumes)
+    Volume,
+    /// APFS containers (macOS)
+    Container,
+}
+
+impl DriveType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DriveType::Disk => "disk",
+            DriveType::Part => "part",
+            DriveType::Volume => "volume",
+            DriveType::Container => "container",
+        }
+    }
+}
+
 #[derive(Debug, Parser)]
 #[command(name = "secure-wipe")]
 #[command(about = "Secure file/device wiping utility with real-time visualization")]
 pub struct Args {
-    /// Target file or block device/partition to wipe (Unix: /dev/sda1, Windows: \\.\PhysicalDrive0 or \\.\C:). Optional in demo mode.
-    #[arg(short, long)]
-    pub target: Option<PathBuf>,
+    /// Target file or block device/partition to wipe (Unix: /dev/sda1, Windows: \\.\PhysicalDrive0 or \\.\C:). Repeat to wipe several targets. Cannot be combined with --demo.
+    #[arg(short, long, num_args = 1.., conflicts_with = "demo")]
+    pub target: Vec<PathBuf>,
+
+    /// Wipe every file beneath directory targets
+    #[arg(short = 'R', long)]
+    pub recursive: bool,
+
+    /// Only wipe files last modified at least this long ago (e.g. 90d, 12h, 30m)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub older_than: Option<Duration>,
+
+    /// Only wipe files last modified less than this long ago (e.g. 7d, 12h)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub newer_than: Option<Duration>,
+
+    /// List the files that would be wiped without touching them
+    #[arg(long, conflicts_with = "demo")]
+    pub dry_run: bool,
 
     /// Wiping algorithm to use
     #[arg(short, long, value_enum, default_value_t = WipeAlgorithm::Random)]
     pub algorithm: WipeAlgorithm,
 
-    /// Number of passes (for custom algorithm)
-    #[arg(short, long, default_value_t = 3)]
-    pub passes: usize,
+    /// Number of passes for the custom algorithm, at most 100 without --yes-really [default: 3]
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub passes: Option<u16>,
+
+    /// Allow a --passes count above 100
+    #[arg(long, requires = "passes")]
+    pub yes_really: bool,
+
+    /// Run exactly these passes instead of an algorithm: comma-separated hex bytes or `random`, e.g. 00,FF,random,AA
+    #[arg(
+        long,
+        value_name = "SPEC",
+        alias = "pattern-per-pass",
+        value_parser = parse_passes_spec,
+        conflicts_with_all = ["algorithm", "passes"]
+    )]
+    // Spelled out so clap takes the whole spec as one value, not a list of patterns
+    pub passes_spec: Option<::std::vec::Vec<WipePattern>>,
+
+    /// Name the passes "LABEL n/N" in the progress output instead of by their pattern, e.g. for custom algorithms whose passes are all RAND [default label: PASS]
+    #[arg(
+        long,
+        value_name = "LABEL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "PASS",
+        value_parser = clap::builder::NonEmptyStringValueParser::new()
+    )]
+    pub pattern_name: Option<String>,
+
+    /// Finish with an extra 0x00 pass so the target is left zeroed, after any algorithm
+    #[arg(long)]
+    pub last_pass_zero: bool,
 
     /// Demo mode - creates and wipes test file safely
     #[arg(short, long)]
     pub demo: bool,
 
-    /// Size of demo file in MB
-    #[arg(long, default_value_t = 100)]
-    pub demo_size: u64,
+    /// Attach the demo file to a loop device and wipe that as a real block device (root required)
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub demo_loop: bool,
+
+    /// Size of the demo file, e.g. 500M, 10G or 1T; a plain number is in MB [default: 100M]
+    // Without a default value, so that validate() can tell whether it was given
+    #[arg(long, value_name = "SIZE", value_parser = parse_demo_size)]
+    pub demo_size: Option<u64>,
+
+    /// Content the demo file is filled with before it is wiped [default: text]
+    #[arg(long, value_enum)]
+    pub demo_content: Option<DemoContent>,
+
+    /// Create the demo file sparse (set_len) instead of writing its content, for large demo sizes
+    #[arg(long, conflicts_with = "demo_content")]
+    pub demo_sparse: bool,
+
+    /// Buffer size for wiping operations, e.g. 64K, 4M or 1.5G; a plain number is in KB [default: adaptive, the fastest of a few sizes tried at the start of each pass]
+    #[arg(long, value_name = "SIZE", value_parser = parse_buffer_size)]
+    pub buffer_size: Option<usize>,
 
-    /// Buffer size in KB for wiping operations
-    #[arg(long, default_value_t = 1024)]
-    pub buffer_size: usize,
+    /// Largest share of the available memory a --buffer-size may take up, split between parallel jobs
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = 25,
+        value_parser = clap::value_parser!(u8).range(1..=90)
+    )]
+    pub max_buffer_memory: u8,
 
-    /// Force wipe without confirmation (dangerous!)
+    /// Force wipe without confirmation and without the running-system check (dangerous!)
     #[arg(short, long)]
     pub force: bool,
 
+    /// Skip the confirmation prompt only; unlike --force, wiping the running system is still refused without --allow-system
+    #[arg(short = 'y', long)]
+    pub assume_yes: bool,
+
+    /// Confirm by typing back a random 6-digit code instead of "WIPE"
+    #[arg(long, conflicts_with_all = ["force", "assume_yes"])]
+    pub confirm_code: bool,
+
+    /// Allow wiping the drive that holds the running system (root/boot filesystem)
+    #[arg(long)]
+    pub allow_system: bool,
+
     /// Verify wipe by reading back data
     #[arg(short, long)]
     pub verify: bool,
 
+    /// Read each pass back and verify it before the next pass starts, aborting on a mismatch
+    #[arg(long)]
+    pub verify_each_pass: bool,
+
+    /// Only verify that the targets hold this pattern (hex, e.g. 00 or 0xdeadbeef), without wiping them
+    #[arg(
+        long,
+        value_name = "HEX",
+        value_parser = parse_hex_pattern,
+        conflicts_with_all = ["demo", "free_space", "remove", "verify_each_pass", "dry_run"]
+    )]
+    // Spelled out so clap takes the whole pattern as one value, not a list of bytes
+    pub verify_pattern: Option<::std::vec::Vec<u8>>,
+
     /// Output machine-readable JSON for subprocess integration
     #[arg(long)]
     pub json: bool,
 
-    /// Fast mode - disable O_SYNC for better performance (less safe)
-    #[arg(long)]
+    /// Indent JSON output for reading instead of printing one compact object per line
+    #[arg(long, requires = "json")]
+    pub json_pretty: bool,
+
+    /// Write a JSON summary of the run to this file when it ends, even if the wipe fails
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["list_drives", "system_info", "dry_run", "free_space"]
+    )]
+    pub output_summary: Option<PathBuf>,
+
+    /// Append the run summary as one JSON line to this shared ledger file, locked while it is written
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["list_drives", "system_info", "dry_run", "free_space"]
+    )]
+    pub append_certificate: Option<PathBuf>,
+
+    /// Cancel the wipe cleanly, as with Ctrl-C, once this file is created
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["list_drives", "system_info", "dry_run"]
+    )]
+    pub cancel_file: Option<PathBuf>,
+
+    /// Fail unless this binary emits this version of the JSON event protocol, so a frontend can reject a mismatched binary
+    #[arg(long, value_name = "VERSION")]
+    pub protocol_version: Option<u32>,
+
+    /// When written data is forced to disk [default: always for block devices, interval for files]
+    #[arg(long, value_enum)]
+    pub sync_mode: Option<SyncMode>,
+
+    /// Fast mode - alias for --sync-mode none, for better performance (less safe)
+    #[arg(long, conflicts_with = "sync_mode")]
     pub fast: bool,
 
+    /// Skip only the flush at the end of each pass, keeping the sync mode and progress reporting; a crash or power loss can lose the data of the last passes
+    #[arg(long)]
+    pub no_sync: bool,
+
+    /// With --sync-mode interval, start writing cached data back to disk every this many MB, so dirty pages do not pile up until the end of each pass (0 disables)
+    #[arg(long, value_name = "MB", default_value_t = 256)]
+    pub sync_interval: u64,
+
+    /// Milliseconds between two progress updates, at least 50 [default: 200, 500 with --json, 2000 with --fast]
+    #[arg(long, value_name = "MS")]
+    pub progress_interval: Option<u64>,
+
+    /// After wiping a file, rename it to random names, truncate and delete it (files only)
+    #[arg(short, long)]
+    pub remove: bool,
+
+    /// Only overwrite the allocated extents of sparse files, skipping holes (files only)
+    #[arg(long)]
+    pub sparse_aware: bool,
+
+    /// Extend each file to this many bytes before wiping, to scrub blocks left past EOF by a truncate (files only)
+    #[arg(long, value_name = "BYTES", conflicts_with = "sparse_aware")]
+    pub fill_to_size: Option<u64>,
+
+    /// Leave this many bytes at the start of each target untouched, e.g. a bootloader or header
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = 0,
+        conflicts_with_all = ["remove", "free_space", "verify_pattern"]
+    )]
+    pub preserve_head: u64,
+
+    /// Leave this many bytes at the end of each target untouched, e.g. a backup partition table
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = 0,
+        conflicts_with_all = ["remove", "free_space", "verify_pattern"]
+    )]
+    pub preserve_tail: u64,
+
+    /// After wiping, sample the target and report its estimated entropy (bits per byte)
+    #[arg(long)]
+    pub entropy_check: bool,
+
+    /// Compute a SHA-256 digest of the data written by the final pass and report it
+    #[arg(long)]
+    pub hash_final_pass: bool,
+
+    /// Do not wipe NTFS alternate data streams of file targets
+    #[cfg(windows)]
+    #[arg(long)]
+    pub skip_ads: bool,
+
+    /// Dismount a Windows volume even if it cannot be locked because programs are using it
+    #[cfg(windows)]
+    #[arg(long)]
+    pub force_dismount: bool,
+
+    /// Temporarily lift the Host Protected Area of ATA disks so the hidden sectors get wiped too, and report HPA/DCO sizes
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub reset_hpa: bool,
+
+    /// Open block devices with O_DIRECT, bypassing the page cache with block-aligned writes
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub direct_io: bool,
+
+    /// Drop written data from the page cache as the wipe goes, so it does not evict the data of other programs [default: true for block devices, false for files]
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    pub no_cache: Option<bool>,
+
+    /// Issue writes one at a time (sync) or keep several in flight through io_uring (uring)
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_enum, default_value_t = IoBackend::Sync)]
+    pub io_backend: IoBackend,
+
+    /// Number of writes kept in flight by --io-backend uring
+    #[cfg(target_os = "linux")]
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..=256))]
+    pub queue_depth: u16,
+
+    /// Read SMART health and sector counts of ATA disks before and after wiping, warning if the drive is failing
+    #[cfg(target_os = "linux")]
+    #[arg(long)]
+    pub smart: bool,
+
+    /// Wipe the whole disk with this serial number, resolved through sysfs to its current device node. Repeat for several disks.
+    #[cfg(target_os = "linux")]
+    #[arg(long, value_name = "SERIAL", num_args = 1.., conflicts_with_all = ["demo", "free_space"])]
+    pub by_id: Vec<String>,
+
+    /// Wipe macOS /dev/diskN targets through their raw /dev/rdiskN node instead
+    #[cfg(target_os = "macos")]
+    #[arg(long)]
+    pub auto_raw: bool,
+
+    /// Unmount all volumes of macOS disk targets (diskutil unmountDisk) before wiping
+    #[cfg(target_os = "macos")]
+    #[arg(long)]
+    pub unmount: bool,
+
+    /// Let the machine sleep or shut down during the wipe instead of keeping it awake until it finishes
+    #[cfg(any(windows, target_os = "linux"))]
+    #[arg(long)]
+    pub allow_sleep: bool,
+
+    /// Crypto-erase a BitLocker volume by overwriting only its header and FVE metadata (keys)
+    #[cfg(windows)]
+    #[arg(long, conflicts_with_all = ["remove", "sparse_aware", "entropy_check"])]
+    pub bitlocker_metadata_only: bool,
+
+    /// Number of threads generating random data for random passes, alongside the writing thread
+    #[arg(long, default_value_t = 1)]
+    pub threads: usize,
+
+    /// Number of threads filling each buffer of random data in parallel, each from its own part of the pass's keystream
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..=64))]
+    pub rng_threads: u16,
+
+    /// Generate the next buffer of random data on a worker thread while the current one is written [default: when there is more than one CPU or --threads is above 1]
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    pub pipeline: Option<bool>,
+
+    /// Generator of the data written by random passes
+    #[arg(long, value_enum, default_value_t = RngKind::Chacha)]
+    pub rng: RngKind,
+
+    /// Number of targets to wipe concurrently when several are given
+    #[arg(short, long, visible_alias = "parallel", default_value_t = 1)]
+    pub jobs: usize,
+
+    /// Skip blocks that fail to write and continue, reporting them at the end
+    #[arg(long)]
+    pub skip_errors: bool,
+
+    /// Number of times to retry a block after a transient write error (0-16)
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(0..=16))]
+    pub retries: u32,
+
+    /// Cap the write rate of each target, e.g. 200M or 1G (bytes per second, binary units)
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    pub max_rate: Option<u64>,
+
+    /// Run at idle I/O and lowest CPU priority so other work on the machine stays responsive
+    #[arg(long, conflicts_with_all = ["list_drives", "system_info"])]
+    pub nice: bool,
+
+    /// I/O scheduling class of the wipe (Linux ioprio_set); ignored with a warning on other platforms
+    #[arg(
+        long,
+        value_name = "CLASS",
+        conflicts_with_all = ["nice", "list_drives", "system_info"]
+    )]
+    pub io_priority: Option<IoPriority>,
+
+    /// Wipe the free space of the filesystem mounted at this path instead of a target
+    #[arg(long, value_name = "MOUNTPOINT", conflicts_with_all = ["target", "demo", "remove"])]
+    pub free_space: Option<PathBuf>,
+
+    /// Space in MB to leave free on the filesystem during --free-space
+    #[arg(long, default_value_t = 64, requires = "free_space")]
+    pub reserve_mb: u64,
+
     /// List available drives and partitions instead of wiping
     #[arg(short, long)]
     pub list_drives: bool,
 
+    /// Print the drive list as CSV (path,type,size_bytes,size_gb,description)
+    #[arg(long, requires = "list_drives", conflicts_with = "json")]
+    pub csv: bool,
+
+    /// Only list drives of this type (repeatable)
+    #[arg(long = "type", value_enum, requires = "list_drives")]
+    pub drive_type: Vec<DriveType>,
+
+    /// Only list drives of at least this size in GB
+    #[arg(long, requires = "list_drives")]
+    pub min_size: Option<f64>,
+
+    /// Omit the drive holding the running system from the drive list
+    #[arg(long, requires = "list_drives")]
+    pub exclude_system: bool,
+
+    /// Only list removable media (USB sticks, card readers)
+    #[arg(long, requires = "list_drives")]
+    pub removable_only: bool,
+
     /// Show system information (OS, architecture, memory, etc.)
     #[arg(short = 's', long)]
     pub system_info: bool,
+
+    /// List every algorithm with its passes, patterns and relative runtime instead of wiping
+    #[arg(
+        long,
+        conflicts_with_all = ["list_drives", "system_info", "demo", "free_space"]
+    )]
+    pub list_algorithms: bool,
+
+    /// Print the passes the wipe would run (pattern bytes, random or not, verification) instead of wiping
+    #[arg(
+        long,
+        conflicts_with_all = ["list_drives", "system_info", "list_algorithms", "demo", "free_space", "verify_pattern"]
+    )]
+    pub show_passes: bool,
+
+    /// Check the pass tables of every algorithm for consistency and print PASS/FAIL for each
+    #[arg(
+        long,
+        hide = true,
+        alias = "pattern-verify-only",
+        conflicts_with_all = ["list_drives", "system_info", "demo", "free_space"]
+    )]
+    pub self_test: bool,
+}
+
+impl Args {
+    /// Reject combinations of flags that clap's attributes cannot express: demo
+    /// flags without `--demo` (clap counts the unset `--demo` flag as present for
+    /// `requires`), a `--passes` count the algorithm would ignore or that looks
+    /// like a typo, and backends that cannot honour another flag
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !self.demo {
+            #[cfg(target_os = "linux")]
+            let demo_loop = self.demo_loop.then_some("--demo-loop");
+            #[cfg(not(target_os = "linux"))]
+            let demo_loop = None;
+            if let Some(flag) = [
+                self.demo_size.map(|_| "--demo-size"),
+                self.demo_content.as_ref().map(|_| "--demo-content"),
+                self.demo_sparse.then_some("--demo-sparse"),
+                demo_loop,
+            ]
+            .into_iter()
+            .flatten()
+            .next()
+            {
+                anyhow::bail!("{} only applies with --demo", flag);
+            }
+        }
+
+        if let Some(passes) = self.passes {
+            if !matches!(self.algorithm, WipeAlgorithm::Custom) {
+                anyhow::bail!(
+                    "--passes only applies to --algorithm custom; the {:?} algorithm has a fixed number of passes",
+                    self.algorithm
+                );
+            }
+            if passes > MAX_PASSES && !self.yes_really {
+                anyhow::bail!(
+                    "--passes {} is more than {} passes; pass --yes-really if that is intended",
+                    passes,
+                    MAX_PASSES
+                );
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if self.io_backend == IoBackend::Uring && self.skip_errors {
+            anyhow::bail!(
+                "--skip-errors cannot be combined with --io-backend uring, which aborts the pass on the first failed write"
+            );
+        }
+
+        Ok(())
+    }
 }
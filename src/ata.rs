@@ -0,0 +1,184 @@
+//! ATA commands sent to disks through SCSI ATA PASS-THROUGH (SG_IO on Linux)
+
+use anyhow::Result;
+use std::{fs::File, os::unix::io::AsRawFd};
+
+const SG_IO: libc::c_ulong = 0x2285;
+const SG_DXFER_NONE: libc::c_int = -1;
+const SG_DXFER_FROM_DEV: libc::c_int = -3;
+const ATA_PASS_THROUGH_16: u8 = 0x85;
+/// Device register value selecting LBA addressing
+const ATA_DEVICE_LBA: u8 = 0x40;
+
+/// Little-endian 16-bit word `index` of a 512-byte ATA data structure
+pub fn word(data: &[u8; 512], index: usize) -> u64 {
+    u64::from(u16::from_le_bytes([data[index * 2], data[index * 2 + 1]]))
+}
+
+/// LBA returned in the ATA Status Return descriptor (SAT, descriptor code 0x09)
+/// of descriptor-format sense data
+pub fn return_lba(sense: &[u8]) -> Option<u64> {
+    let descriptor = sense.get(8..22)?;
+    if sense[0] & 0x7f != 0x72 || descriptor[0] != 0x09 {
+        return None;
+    }
+    Some(
+        u64::from(descriptor[7])
+            | u64::from(descriptor[9]) << 8
+            | u64::from(descriptor[11]) << 16
+            | u64::from(descriptor[6]) << 24
+            | u64::from(descriptor[8]) << 32
+            | u64::from(descriptor[10]) << 40,
+    )
+}
+
+/// `struct sg_io_hdr` from `<scsi/sg.h>`
+#[repr(C)]
+struct SgIoHdr {
+    interface_id: libc::c_int,
+    dxfer_direction: libc::c_int,
+    cmd_len: libc::c_uchar,
+    mx_sb_len: libc::c_uchar,
+    iovec_count: libc::c_ushort,
+    dxfer_len: libc::c_uint,
+    dxferp: *mut libc::c_void,
+    cmdp: *mut libc::c_uchar,
+    sbp: *mut libc::c_uchar,
+    timeout: libc::c_uint,
+    flags: libc::c_uint,
+    pack_id: libc::c_int,
+    usr_ptr: *mut libc::c_void,
+    status: libc::c_uchar,
+    masked_status: libc::c_uchar,
+    msg_status: libc::c_uchar,
+    sb_len_wr: libc::c_uchar,
+    host_status: libc::c_ushort,
+    driver_status: libc::c_ushort,
+    resid: libc::c_int,
+    duration: libc::c_uint,
+    info: libc::c_uint,
+}
+
+/// An ATA command sent through a SCSI ATA PASS-THROUGH (16) command
+pub struct AtaCommand {
+    pub command: u8,
+    pub features: u16,
+    pub lba: u64,
+    /// 48-bit (EXT) command
+    pub extend: bool,
+    /// Read one 512-byte sector of data, instead of a non-data command
+    /// whose result registers are returned in the sense data
+    pub data_in: bool,
+}
+
+impl AtaCommand {
+    fn cdb(&self) -> [u8; 16] {
+        let lba = self.lba.to_le_bytes();
+        let (protocol, flags, count) = if self.data_in {
+            // PIO data-in; transfer length in sectors taken from the count field
+            (4, 0x0e, 1)
+        } else {
+            // Non-data; CK_COND returns the result registers
+            (3, 0x20, 0)
+        };
+        [
+            ATA_PASS_THROUGH_16,
+            protocol << 1 | u8::from(self.extend),
+            flags,
+            (self.features >> 8) as u8,
+            self.features as u8,
+            0,
+            count,
+            lba[3],
+            lba[0],
+            lba[4],
+            lba[1],
+            lba[5],
+            lba[2],
+            ATA_DEVICE_LBA,
+            self.command,
+            0,
+        ]
+    }
+}
+
+/// Send `command` to the drive, returning the sense data
+pub fn send(file: &File, command: &AtaCommand, data: &mut [u8; 512]) -> Result<[u8; 32]> {
+    let mut cdb = command.cdb();
+    let mut sense = [0u8; 32];
+    let mut header = SgIoHdr {
+        interface_id: b'S' as libc::c_int,
+        dxfer_direction: if command.data_in {
+            SG_DXFER_FROM_DEV
+        } else {
+            SG_DXFER_NONE
+        },
+        cmd_len: cdb.len() as u8,
+        mx_sb_len: sense.len() as u8,
+        iovec_count: 0,
+        dxfer_len: if command.data_in { 512 } else { 0 },
+        dxferp: data.as_mut_ptr().cast(),
+        cmdp: cdb.as_mut_ptr(),
+        sbp: sense.as_mut_ptr(),
+        timeout: 10_000,
+        flags: 0,
+        pack_id: 0,
+        usr_ptr: std::ptr::null_mut(),
+        status: 0,
+        masked_status: 0,
+        msg_status: 0,
+        sb_len_wr: 0,
+        host_status: 0,
+        driver_status: 0,
+        resid: 0,
+        duration: 0,
+        info: 0,
+    };
+
+    if unsafe { libc::ioctl(file.as_raw_fd(), SG_IO, &mut header) } != 0 {
+        anyhow::bail!("SG_IO failed: {}", std::io::Error::last_os_error());
+    }
+    // CHECK CONDITION is expected with CK_COND; the ATA status decides success
+    let ata_status = if sense[0] & 0x7f == 0x72 && sense[8] == 0x09 {
+        sense[21]
+    } else if header.status == 0 && header.host_status == 0 {
+        0
+    } else {
+        anyhow::bail!(
+            "ATA command {:#04x} was rejected (SCSI status {:#04x}, host status {:#06x})",
+            command.command,
+            header.status,
+            header.host_status
+        );
+    };
+    if ata_status & 0x01 != 0 {
+        anyhow::bail!(
+            "ATA command {:#04x} was aborted by the drive",
+            command.command
+        );
+    }
+
+    Ok(sense)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_return_lba_from_descriptor_sense() {
+        // Descriptor sense data returning LBA 0x01_0203_0405
+        let mut sense = [0u8; 32];
+        sense[0] = 0x72;
+        sense[8] = 0x09;
+        sense[8 + 7] = 0x05;
+        sense[8 + 9] = 0x04;
+        sense[8 + 11] = 0x03;
+        sense[8 + 6] = 0x02;
+        sense[8 + 8] = 0x01;
+        assert_eq!(return_lba(&sense), Some(0x01_0203_0405));
+
+        sense[0] = 0x70;
+        assert_eq!(return_lba(&sense), None);
+    }
+}
@@ -0,0 +1,611 @@
+use anyhow::{Context, Result};
+use indicatif::MultiProgress;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(windows)]
+use crate::bitlocker::wipe_metadata;
+#[cfg(windows)]
+use crate::streams::{wipe_alternate_streams, AlternateStream};
+use crate::{
+    args::SyncMode,
+    entropy::{sample_entropy, EntropyEstimate},
+    platform,
+    progress::{emit_event, emit_target_event, to_json, ProgressEvent},
+    remove::remove_file_securely,
+    reports::SmartReport,
+    system::{get_device_identity, get_hostname, DeviceIdentity},
+    verify::{pattern_hex, verify_pattern},
+    wipe::{format_throughput, WipeContext, WipeOptions},
+};
+
+/// Exit code of a run stopped by an error, as for any error returned from `main`
+pub const EXIT_ERROR: i32 = 1;
+/// Exit code when some, but not all, targets failed; distinct from clap's usage
+/// error (2) and `EXIT_ERROR`, so wrappers can tell a failed target from a typo
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+/// Exit code when every target of a multi-target run failed
+pub const EXIT_ALL_FAILED: i32 = 4;
+
+/// Outcome of wiping a single target. Field names are part of the summary schema.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetResult {
+    pub target: String,
+    pub identity: Option<DeviceIdentity>,
+    pub algorithm: String,
+    /// Generator of the random data, when any pass wrote random data
+    pub rng: Option<String>,
+    /// When written data was forced to disk, `None` for a BitLocker metadata wipe
+    pub sync_mode: Option<SyncMode>,
+    pub success: bool,
+    pub passes_completed: usize,
+    pub bytes_written: u64,
+    /// Logical size of the target
+    pub size_bytes: u64,
+    /// Allocated size, reported when only allocated extents were overwritten
+    pub allocated_bytes: Option<u64>,
+    pub duration_seconds: f64,
+    pub average_throughput_mb_s: f64,
+    /// Read-back outcome of `--verify-each-pass`, or of the check with `--verify-pattern`
+    pub verified: Option<bool>,
+    pub error: Option<String>,
+    /// Randomness of the wiped contents, when --entropy-check was given
+    pub entropy: Option<EntropyEstimate>,
+    /// SHA-256 of the data written by the final pass, when --hash-final-pass was given
+    pub final_pass_sha256: Option<String>,
+    /// SMART status before and after the wipe, when --smart was given
+    pub smart: Option<SmartReport>,
+    /// NTFS alternate data streams that were wiped along with the file
+    #[cfg(windows)]
+    pub alternate_streams: Vec<AlternateStream>,
+}
+
+/// Wipe a single target, returning its outcome even when the wipe fails
+pub fn wipe_target(
+    path: &Path,
+    options: &WipeOptions,
+    multi_progress: Option<&MultiProgress>,
+) -> (TargetResult, Result<()>) {
+    let start_time = Instant::now();
+    let is_block_device = platform::is_block_device(path);
+
+    let mut passes_completed = 0;
+    let mut bytes_written = 0;
+    let mut size_bytes = 0;
+    let mut allocated_bytes = None;
+    let mut final_pass_sha256 = None;
+    let mut verified = None;
+
+    #[cfg(windows)]
+    let metadata_only = options.bitlocker_metadata_only;
+    #[cfg(not(windows))]
+    let metadata_only = false;
+    // A metadata wipe writes no pass data and a pattern check writes nothing
+    let writes_passes = !metadata_only && options.verify_pattern.is_none();
+
+    // SMART is read before anything is written so that changes during the wipe show up
+    #[cfg(target_os = "linux")]
+    let mut smart = None;
+    #[cfg(target_os = "linux")]
+    let outcome = if options.smart && is_block_device {
+        check_smart(path, &mut smart, options.json_mode)
+    } else {
+        Ok(())
+    };
+    #[cfg(not(target_os = "linux"))]
+    let (smart, outcome): (Option<SmartReport>, Result<()>) = (None, Ok(()));
+
+    let outcome = outcome.and_then(|_| {
+        if let Some(pattern) = &options.verify_pattern {
+            let check = verify_pattern(path, pattern, options.json_mode, multi_progress)?;
+            size_bytes = check.size_bytes;
+            verified = Some(check.mismatch_offset.is_none());
+            return match check.mismatch_offset {
+                Some(offset) => Err(anyhow::anyhow!(
+                    "{} does not hold the pattern {}: first mismatch at offset {}",
+                    path.display(),
+                    pattern_hex(pattern),
+                    offset
+                )),
+                None => {
+                    if !options.json_mode {
+                        println!(
+                            "{} holds the pattern {} in all {} bytes",
+                            path.display(),
+                            pattern_hex(pattern),
+                            check.bytes_verified
+                        );
+                    }
+                    Ok(())
+                }
+            };
+        }
+        #[cfg(windows)]
+        if metadata_only {
+            return wipe_metadata(path, options).map(|written| {
+                passes_completed = 1;
+                bytes_written = written;
+            });
+        }
+        WipeContext::new(path, is_block_device, options.clone()).and_then(|context| {
+            let mut context = match multi_progress {
+                Some(multi_progress) => context.with_multi_progress(multi_progress.clone()),
+                None => context,
+            };
+            size_bytes = context.size();
+            allocated_bytes = context.allocated_bytes();
+            let result = context.wipe();
+            passes_completed = context.passes_completed();
+            bytes_written = context.bytes_written();
+            final_pass_sha256 = context.final_pass_sha256().map(str::to_string);
+            verified = context.verified();
+            result
+        })
+    });
+
+    // A failed wipe is read again too, since bad sectors are a common cause
+    #[cfg(target_os = "linux")]
+    let outcome = if smart.is_some() {
+        outcome.and(check_smart(path, &mut smart, options.json_mode))
+    } else {
+        outcome
+    };
+
+    // Alternate data streams hold file content too; wipe them before any removal
+    #[cfg(windows)]
+    let mut alternate_streams = Vec::new();
+    #[cfg(windows)]
+    let outcome = outcome.and_then(|_| {
+        if options.skip_ads || is_block_device {
+            return Ok(());
+        }
+        alternate_streams = wipe_alternate_streams(path, options, multi_progress)?;
+        Ok(())
+    });
+
+    // Sample the wiped contents before a removed file disappears
+    let mut entropy = None;
+    let outcome = outcome.and_then(|_| {
+        if options.entropy_check {
+            entropy = Some(check_entropy(path, size_bytes, options.json_mode)?);
+        }
+        Ok(())
+    });
+
+    // Block devices never go through file removal
+    let outcome = outcome.and_then(|_| {
+        if options.remove && !is_block_device {
+            remove_file_securely(path, options.json_mode)
+        } else {
+            Ok(())
+        }
+    });
+
+    let duration_seconds = start_time.elapsed().as_secs_f64();
+    let average_throughput_mb_s = if duration_seconds > 0.0 {
+        bytes_written as f64 / duration_seconds / 1_048_576.0
+    } else {
+        0.0
+    };
+
+    let result = TargetResult {
+        target: path.display().to_string(),
+        identity: get_device_identity(path),
+        algorithm: options.algorithm_name(),
+        rng: options
+            .random_generator()
+            .filter(|_| writes_passes)
+            .map(str::to_string),
+        sync_mode: writes_passes.then(|| options.sync_mode_for(is_block_device)),
+        success: outcome.is_ok(),
+        passes_completed,
+        bytes_written,
+        size_bytes,
+        allocated_bytes,
+        duration_seconds,
+        average_throughput_mb_s,
+        verified,
+        entropy,
+        final_pass_sha256,
+        smart,
+        error: outcome.as_ref().err().map(|e| format!("{:#}", e)),
+        #[cfg(windows)]
+        alternate_streams,
+    };
+
+    (result, outcome)
+}
+
+/// Estimate the entropy of a wiped target and report it as an event or a line of output
+fn check_entropy(path: &Path, size_bytes: u64, json_mode: bool) -> Result<EntropyEstimate> {
+    let estimate = sample_entropy(path, size_bytes)?;
+
+    if json_mode {
+        let _ = emit_target_event(
+            &path.display().to_string(),
+            &ProgressEvent::EntropyCheck {
+                samples: estimate.samples,
+                sampled_bytes: estimate.sampled_bytes,
+                bits_per_byte: estimate.bits_per_byte,
+                chi_square: estimate.chi_square,
+            },
+        );
+    } else {
+        println!(
+            "Entropy check for {}: {:.4} bits/byte over {} samples ({:.2} MB), chi-square {:.1}",
+            path.display(),
+            estimate.bits_per_byte,
+            estimate.samples,
+            estimate.sampled_bytes as f64 / 1_048_576.0,
+            estimate.chi_square
+        );
+    }
+
+    Ok(estimate)
+}
+
+/// Read the SMART status of a disk into `report`, the first call recording it
+/// as the state before the wipe and the second as the state after it. Reported
+/// as an event or a line of output, with warnings when the drive is failing.
+#[cfg(target_os = "linux")]
+fn check_smart(path: &Path, report: &mut Option<SmartReport>, json_mode: bool) -> Result<()> {
+    let status = crate::smart::read_smart(path)?;
+    let phase = if report.is_some() { "after" } else { "before" };
+
+    let mut warnings = Vec::new();
+    if !status.passed {
+        warnings.push(format!(
+            "SMART overall health of {} is FAILED; the drive may not be reliably wiped",
+            path.display()
+        ));
+    }
+    match report {
+        Some(report) => {
+            report.record_after(status.clone());
+            if report.counts_changed {
+                warnings.push(format!(
+                    "Reallocated or pending sector count of {} increased during the wipe",
+                    path.display()
+                ));
+            }
+        }
+        None => *report = Some(SmartReport::new(status.clone())),
+    }
+
+    let count = |value: Option<u64>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+    if json_mode {
+        let target = path.display().to_string();
+        let _ = emit_target_event(
+            &target,
+            &ProgressEvent::SmartHealth {
+                phase: phase.to_string(),
+                passed: status.passed,
+                reallocated_sectors: status.reallocated_sectors,
+                pending_sectors: status.pending_sectors,
+                power_on_hours: status.power_on_hours,
+            },
+        );
+        for message in warnings {
+            let _ = emit_target_event(&target, &ProgressEvent::Info { message });
+        }
+    } else {
+        println!(
+            "SMART {} wipe of {}: {}, reallocated sectors {}, pending sectors {}, power-on hours {}",
+            phase,
+            path.display(),
+            if status.passed { "PASSED" } else { "FAILED" },
+            count(status.reallocated_sectors),
+            count(status.pending_sectors),
+            count(status.power_on_hours)
+        );
+        for message in warnings {
+            println!("Warning: {}", message);
+        }
+    }
+
+    Ok(())
+}
+
+/// Wipe several targets, running up to `jobs` of them concurrently
+pub fn wipe_targets(targets: &[PathBuf], options: &WipeOptions, jobs: usize) -> Vec<TargetResult> {
+    let jobs = jobs.clamp(1, targets.len().max(1));
+    let options = WipeOptions {
+        jobs,
+        ..options.clone()
+    };
+
+    let multi_progress = if options.json_mode {
+        None
+    } else {
+        Some(MultiProgress::new())
+    };
+
+    // Workers pull the next target index from a shared counter
+    let next_target = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<TargetResult>>> = Mutex::new(vec![None; targets.len()]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let index = next_target.fetch_add(1, Ordering::SeqCst);
+                let Some(path) = targets.get(index) else {
+                    break;
+                };
+
+                let (result, _) = wipe_target(path, &options, multi_progress.as_ref());
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Report the per-target outcomes as a summary event or an aligned table
+pub fn report_results(results: &[TargetResult], json_mode: bool) {
+    let succeeded = results.iter().filter(|result| result.success).count();
+    let total_bytes_written: u64 = results.iter().map(|result| result.bytes_written).sum();
+
+    if json_mode {
+        let _ = emit_event(&ProgressEvent::Summary {
+            succeeded,
+            failed: results.len() - succeeded,
+            total_bytes_written,
+            total_size_bytes: results.iter().map(|result| result.size_bytes).sum(),
+            targets: results.to_vec(),
+        });
+        return;
+    }
+
+    let rows: Vec<[String; 7]> = results
+        .iter()
+        .map(|result| {
+            let identity = result
+                .identity
+                .as_ref()
+                .and_then(|identity| identity.serial.clone().or(identity.model.clone()))
+                .unwrap_or_else(|| "-".to_string());
+            let verified = match result.verified {
+                Some(true) => "yes",
+                Some(false) => "FAILED",
+                None => "-",
+            };
+            [
+                result.target.clone(),
+                identity,
+                if result.success { "OK" } else { "FAILED" }.to_string(),
+                result.passes_completed.to_string(),
+                format!("{:.2} MB", result.bytes_written as f64 / 1_048_576.0),
+                format_throughput(result.average_throughput_mb_s * 1_048_576.0),
+                verified.to_string(),
+            ]
+        })
+        .collect();
+
+    let headers = [
+        "TARGET", "IDENTITY", "STATUS", "PASSES", "WRITTEN", "SPEED", "VERIFIED",
+    ];
+    let mut widths = headers.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_row = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!();
+    println!(
+        "Wipe summary ({} of {} succeeded):",
+        succeeded,
+        results.len()
+    );
+    println!("  {}", format_row(&headers.map(String::from)));
+    for row in &rows {
+        println!("  {}", format_row(row));
+    }
+    println!(
+        "  Total written: {:.2} MB",
+        total_bytes_written as f64 / 1_048_576.0
+    );
+
+    for result in results {
+        if let Some(ref error) = result.error {
+            println!("  {}: {}", result.target, error);
+        }
+    }
+}
+
+/// Process exit code encoding whether all, some, or none of the targets succeeded
+pub fn exit_code(results: &[TargetResult]) -> i32 {
+    let failed = results.iter().filter(|result| !result.success).count();
+    if failed == 0 {
+        0
+    } else if failed == results.len() {
+        EXIT_ALL_FAILED
+    } else {
+        EXIT_PARTIAL_FAILURE
+    }
+}
+
+/// Machine-readable outcome of a whole run, written by `--output-summary`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// "success", "partial" when only some targets failed, or "error"
+    pub status: String,
+    pub exit_code: i32,
+    pub algorithm: String,
+    pub passes: usize,
+    pub bytes_written_total: u64,
+    pub elapsed_seconds: f64,
+    pub average_throughput_mb_s: f64,
+    /// Whether every target read back correctly with `--verify-each-pass` or `--verify-pattern`
+    pub verified: Option<bool>,
+    /// Error that ended the run early
+    pub error: Option<String>,
+    pub targets: Vec<TargetResult>,
+}
+
+impl RunSummary {
+    /// Summarize a run from its target results and its outcome: the exit code,
+    /// or the error that stopped it
+    pub fn new(
+        options: &WipeOptions,
+        targets: Vec<TargetResult>,
+        outcome: Result<i32, String>,
+        elapsed_seconds: f64,
+    ) -> Self {
+        let (exit_code, error) = match outcome {
+            Ok(code) => (code, None),
+            Err(error) => (EXIT_ERROR, Some(error)),
+        };
+        let status = match (exit_code, &error) {
+            (0, None) => "success",
+            (EXIT_PARTIAL_FAILURE, None) => "partial",
+            _ => "error",
+        };
+        let bytes_written_total = targets.iter().map(|result| result.bytes_written).sum();
+        let verified = if targets.iter().any(|result| result.verified == Some(false)) {
+            Some(false)
+        } else if !targets.is_empty() && targets.iter().all(|result| result.verified == Some(true))
+        {
+            Some(true)
+        } else {
+            None
+        };
+
+        RunSummary {
+            status: status.to_string(),
+            exit_code,
+            algorithm: options.algorithm_name(),
+            passes: if options.verify_pattern.is_some() {
+                0
+            } else {
+                options.total_passes()
+            },
+            bytes_written_total,
+            elapsed_seconds,
+            average_throughput_mb_s: if elapsed_seconds > 0.0 {
+                bytes_written_total as f64 / elapsed_seconds / 1_048_576.0
+            } else {
+                0.0
+            },
+            verified,
+            error,
+            targets,
+        }
+    }
+}
+
+/// Write a run summary to `path` as a single JSON document
+pub fn write_run_summary(path: &Path, summary: &RunSummary) -> Result<()> {
+    let json = to_json(summary).context("Failed to serialize the run summary")?;
+    std::fs::write(path, json + "\n")
+        .with_context(|| format!("Failed to write the run summary to {}", path.display()))
+}
+
+/// One line of the `--append-certificate` ledger: the run summary, stamped with
+/// the machine and time it was recorded, so that ledgers collected from a fleet
+/// can be merged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerRecord {
+    /// Seconds since the Unix epoch when the record was appended
+    pub recorded_at: u64,
+    pub hostname: String,
+    #[serde(flatten)]
+    pub summary: RunSummary,
+}
+
+/// Append the run summary to a JSON-lines ledger as one compact line, holding an
+/// exclusive lock on the ledger so that concurrent runs never interleave lines
+pub fn append_ledger_record(path: &Path, summary: &RunSummary) -> Result<()> {
+    let record = LedgerRecord {
+        recorded_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+        hostname: get_hostname(),
+        summary: summary.clone(),
+    };
+    // Always compact, whatever --json-pretty says: each line is one record
+    let line = serde_json::to_string(&record).context("Failed to serialize the ledger record")?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open the ledger {}", path.display()))?;
+    platform::lock_file_exclusive(&file)
+        .with_context(|| format!("Failed to lock the ledger {}", path.display()))?;
+    file.write_all(format!("{}\n", line).as_bytes())
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("Failed to append to the ledger {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_summary_status() {
+        let options = WipeOptions::default();
+        let status = |outcome| RunSummary::new(&options, Vec::new(), outcome, 1.0);
+
+        assert_eq!(status(Ok(0)).status, "success");
+        assert_eq!(status(Ok(EXIT_PARTIAL_FAILURE)).status, "partial");
+        assert_eq!(status(Ok(EXIT_ALL_FAILED)).status, "error");
+
+        let failed = status(Err("Permission denied".to_string()));
+        assert_eq!(failed.status, "error");
+        assert_eq!(failed.exit_code, EXIT_ERROR);
+        assert_eq!(failed.error.as_deref(), Some("Permission denied"));
+        assert_eq!(failed.verified, None);
+    }
+
+    #[test]
+    fn test_ledger_lines_from_concurrent_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = dir.path().join("ledger.jsonl");
+        let summary = RunSummary::new(&WipeOptions::default(), Vec::new(), Ok(0), 1.0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..10 {
+                        append_ledger_record(&ledger, &summary).unwrap();
+                    }
+                });
+            }
+        });
+
+        let contents = std::fs::read_to_string(&ledger).unwrap();
+        assert_eq!(contents.lines().count(), 80);
+        for line in contents.lines() {
+            let record: LedgerRecord = serde_json::from_str(line).unwrap();
+            assert_eq!(record.summary.status, "success");
+            assert!(record.recorded_at > 0);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Detection and crypto-erase of BitLocker volumes on Windows
+
+use anyhow::{Context, Result};
+use rand::RngCore;
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    os::windows::io::AsRawHandle,
+    path::Path,
+};
+use winapi::um::winnt::HANDLE;
+
+use crate::{
+    platform::{
+        device_open_path,
+        windows::{
+            get_device_type, lock_and_dismount_volume, unlock_volume, volume_disk_extents,
+            DeviceType,
+        },
+    },
+    progress::{emit_target_event, ProgressEvent},
+    reports::BitLockerInfo,
+    wipe::WipeOptions,
+};
+
+/// Signature at offset 3 of a BitLocker volume header, in place of the OEM ID
+const FVE_SIGNATURE: &[u8; 8] = b"-FVE-FS-";
+/// Positions of the three FVE metadata block offsets in the volume header (Windows 7+)
+const METADATA_OFFSET_FIELDS: [usize; 3] = [176, 184, 192];
+/// Size of an FVE metadata block. The blocks hold the wrapped volume keys, so
+/// destroying all three copies makes the encrypted data unrecoverable.
+pub const METADATA_BLOCK_BYTES: u64 = 64 * 1024;
+/// Bytes of the volume header read for detection and overwritten by a metadata wipe
+pub const HEADER_BYTES: usize = 4096;
+
+fn has_fve_signature(header: &[u8]) -> bool {
+    header.get(3..11) == Some(FVE_SIGNATURE.as_slice())
+}
+
+/// Offsets of the FVE metadata blocks, relative to the start of the volume,
+/// read from a BitLocker volume header. `None` if the header is not BitLocker's.
+pub fn metadata_offsets(header: &[u8]) -> Option<Vec<u64>> {
+    if !has_fve_signature(header) {
+        return None;
+    }
+
+    let mut offsets = Vec::new();
+    for field in METADATA_OFFSET_FIELDS {
+        let bytes = header.get(field..field + 8)?;
+        let offset = u64::from_le_bytes(bytes.try_into().ok()?);
+        if offset != 0 && !offsets.contains(&offset) {
+            offsets.push(offset);
+        }
+    }
+    (!offsets.is_empty()).then_some(offsets)
+}
+
+fn read_header(file: &mut File, offset: u64) -> std::io::Result<Vec<u8>> {
+    let mut header = vec![0u8; HEADER_BYTES];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut header)?;
+    Ok(header)
+}
+
+/// Detect whether a volume target is BitLocker-encrypted. Always `None`
+/// for files and physical drives.
+pub fn detect(path: &Path) -> Option<BitLockerInfo> {
+    if !matches!(
+        get_device_type(path),
+        DeviceType::LogicalDrive | DeviceType::Volume
+    ) {
+        return None;
+    }
+
+    // A locked volume exposes its raw, encrypted sectors including the FVE header
+    let mut volume = File::open(device_open_path(path)).ok()?;
+    if has_fve_signature(&read_header(&mut volume, 0).ok()?) {
+        return Some(BitLockerInfo { locked: true });
+    }
+
+    // An unlocked volume presents the decrypted filesystem; the FVE header is
+    // only visible on the disk underneath
+    let extent = volume_disk_extents(volume.as_raw_handle() as HANDLE)
+        .into_iter()
+        .next()?;
+    let mut disk = File::open(format!(r"\\.\PhysicalDrive{}", extent.disk_number)).ok()?;
+    let header = read_header(&mut disk, extent.starting_offset).ok()?;
+    has_fve_signature(&header).then_some(BitLockerInfo { locked: false })
+}
+
+/// Crypto-erase a BitLocker volume by overwriting its volume header and all FVE
+/// metadata blocks with random data, leaving the rest of the volume untouched.
+/// Returns the number of bytes written.
+pub fn wipe_metadata(path: &Path, options: &WipeOptions) -> Result<u64> {
+    let volume = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(device_open_path(path))
+        .with_context(|| format!("Failed to open volume: {}", path.display()))?;
+    let handle = volume.as_raw_handle() as HANDLE;
+
+    let extent = volume_disk_extents(handle)
+        .into_iter()
+        .next()
+        .with_context(|| format!("Failed to locate {} on its disk", path.display()))?;
+    let disk_path = format!(r"\\.\PhysicalDrive{}", extent.disk_number);
+    let mut disk = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&disk_path)
+        .with_context(|| format!("Failed to open {}", disk_path))?;
+
+    let header = read_header(&mut disk, extent.starting_offset)
+        .with_context(|| format!("Failed to read the volume header of {}", path.display()))?;
+    let offsets = metadata_offsets(&header)
+        .with_context(|| format!("{} is not a BitLocker volume", path.display()))?;
+
+    // Windows refuses writes to disk sectors that belong to a mounted volume
+    let locked = lock_and_dismount_volume(handle, &path.to_string_lossy(), options.force_dismount)?;
+
+    let regions: Vec<(u64, u64)> = std::iter::once((0, HEADER_BYTES as u64))
+        .chain(offsets.iter().map(|&offset| (offset, METADATA_BLOCK_BYTES)))
+        .collect();
+
+    let mut write_regions = || -> Result<u64> {
+        let mut buffer = vec![0u8; METADATA_BLOCK_BYTES as usize];
+        let mut bytes_written = 0;
+        for &(offset, length) in &regions {
+            let block = &mut buffer[..length as usize];
+            rand::thread_rng().fill_bytes(block);
+            disk.seek(SeekFrom::Start(extent.starting_offset + offset))?;
+            disk.write_all(block).with_context(|| {
+                format!(
+                    "Failed to overwrite BitLocker metadata at offset {}",
+                    offset
+                )
+            })?;
+            bytes_written += length;
+        }
+        disk.sync_all()
+            .with_context(|| format!("Failed to sync {}", disk_path))?;
+        Ok(bytes_written)
+    };
+    let result = write_regions();
+
+    if locked {
+        unlock_volume(handle);
+    }
+    let bytes_written = result?;
+
+    if options.json_mode {
+        let _ = emit_target_event(
+            &path.display().to_string(),
+            &ProgressEvent::BitLockerMetadataWiped {
+                bytes_written,
+                metadata_offsets: offsets,
+            },
+        );
+    } else {
+        println!(
+            "Overwrote the BitLocker header and {} metadata block(s) of {} ({} bytes); the volume's keys are gone",
+            offsets.len(),
+            path.display(),
+            bytes_written
+        );
+    }
+
+    Ok(bytes_written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_offsets_from_volume_header() {
+        let mut header = vec![0u8; HEADER_BYTES];
+        assert!(metadata_offsets(&header).is_none());
+
+        header[3..11].copy_from_slice(FVE_SIGNATURE);
+        header[176..184].copy_from_slice(&0x0210_0000u64.to_le_bytes());
+        header[184..192].copy_from_slice(&0x1a10_0000u64.to_le_bytes());
+        header[192..200].copy_from_slice(&0x3310_0000u64.to_le_bytes());
+        assert_eq!(
+            metadata_offsets(&header),
+            Some(vec![0x0210_0000, 0x1a10_0000, 0x3310_0000])
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Set once the user asked to stop; long-running loops poll it and clean up
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Temporary files removed even when a second Ctrl-C skips the normal cleanup
+static TEMPORARY_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Exit code used when a second Ctrl-C forces an immediate exit
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// How often the `--cancel-file` is looked for
+const CANCEL_FILE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Route Ctrl-C into the cancellation flag so wipes can stop cleanly.
+/// A second Ctrl-C removes the registered temporary files and exits
+/// immediately. With a `cancel_file`, creating that file cancels the same
+/// way, for supervisors that cannot send signals.
+pub fn install_handler(cancel_file: Option<&Path>) -> Result<()> {
+    ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            remove_temporary_files();
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+    })
+    .context("Failed to install Ctrl-C handler")?;
+
+    if let Some(path) = cancel_file {
+        // A file left over from an earlier run would cancel before the first write
+        if path.exists() {
+            anyhow::bail!(
+                "Cancel file {} already exists; remove it before starting the wipe",
+                path.display()
+            );
+        }
+        watch_cancel_file(path.to_path_buf());
+    }
+    Ok(())
+}
+
+/// Set the cancellation flag once `path` appears
+fn watch_cancel_file(path: PathBuf) {
+    thread::spawn(move || {
+        while !path.exists() {
+            thread::sleep(CANCEL_FILE_POLL_INTERVAL);
+        }
+        CANCELLED.store(true, Ordering::SeqCst);
+    });
+}
+
+/// Have a second Ctrl-C remove `path` before exiting
+pub fn register_temporary_file(path: &Path) {
+    if let Ok(mut files) = TEMPORARY_FILES.lock() {
+        files.push(path.to_path_buf());
+    }
+}
+
+/// Forget a temporary file once it has been removed the normal way
+pub fn unregister_temporary_file(path: &Path) {
+    if let Ok(mut files) = TEMPORARY_FILES.lock() {
+        files.retain(|file| file != path);
+    }
+}
+
+fn remove_temporary_files() {
+    if let Ok(files) = TEMPORARY_FILES.lock() {
+        for path in files.iter() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
@@ -1,30 +1,182 @@
 use anyhow::{Context, Result};
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
 use std::{
     fs::OpenOptions,
     io::{BufWriter, Write},
     path::Path,
     time::{Duration, Instant},
 };
+#[cfg(target_os = "linux")]
+use std::{path::PathBuf, process::Command};
 
-use crate::progress::{emit_event, ProgressEvent};
+use crate::{
+    algorithms::WipePattern,
+    args::DemoContent,
+    platform::free_space_bytes,
+    progress::{emit_event, ProgressEvent},
+    ui::confirm_demo_size,
+    wipe::{parse_size, WipeTarget},
+};
+
+/// A demo file attached to a loop device, so that the demo exercises the block
+/// device code paths. The loop device is detached when dropped.
+#[cfg(target_os = "linux")]
+pub struct LoopDevice {
+    path: PathBuf,
+}
+
+#[cfg(target_os = "linux")]
+impl LoopDevice {
+    /// Attach `backing_file` to the first free loop device with `losetup`, with
+    /// the given logical sector size or the default of 512 bytes
+    pub fn attach(backing_file: &Path, sector_size: Option<u32>, json_mode: bool) -> Result<Self> {
+        let mut losetup = Command::new("losetup");
+        losetup.arg("--find").arg("--show");
+        if let Some(sector_size) = sector_size {
+            losetup.arg("--sector-size").arg(sector_size.to_string());
+        }
+        let output = losetup
+            .arg(backing_file)
+            .output()
+            .with_context(|| "Failed to run losetup")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to attach {} to a loop device (root is required): {}",
+                backing_file.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        let message = format!(
+            "Attached demo file {} to loop device {}",
+            backing_file.display(),
+            path.display()
+        );
+        if json_mode {
+            let _ = emit_event(&ProgressEvent::Info { message });
+        } else {
+            println!("{}", message);
+        }
+
+        Ok(LoopDevice { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for LoopDevice {
+    fn drop(&mut self) {
+        let _ = Command::new("losetup").arg("-d").arg(&self.path).status();
+    }
+}
+
+/// Parse a `--demo-size` value into bytes, e.g. `500M`, `10G` or `1T`; a plain
+/// number is a number of MB, as the flag took before suffixes were accepted.
+pub fn parse_demo_size(value: &str) -> Result<u64, String> {
+    let bytes = parse_size(value, 1024 * 1024)?;
+    if bytes == 0 {
+        return Err("the demo file must not be empty".to_string());
+    }
+    Ok(bytes)
+}
+
+/// Refuse a demo file larger than the free space of the filesystem it would be
+/// created on, which would otherwise fill the disk partway through creating or
+/// wiping it. With `force` the shortfall is only reported. A demo file taking
+/// more than half of the free space must be confirmed unless `force` or
+/// `assume_yes` is given; returns `false` when the user declines.
+pub fn check_demo_space(
+    path: &Path,
+    size_bytes: u64,
+    force: bool,
+    assume_yes: bool,
+    json_mode: bool,
+) -> Result<bool> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    // Without a free space figure there is nothing to check against
+    let Ok(free_bytes) = free_space_bytes(dir) else {
+        return Ok(true);
+    };
+    check_demo_size_against(dir, size_bytes, free_bytes, force, assume_yes, json_mode)
+}
+
+/// The [`check_demo_space`] decision for a filesystem with `free_bytes` free at `dir`
+pub fn check_demo_size_against(
+    dir: &Path,
+    size_bytes: u64,
+    free_bytes: u64,
+    force: bool,
+    assume_yes: bool,
+    json_mode: bool,
+) -> Result<bool> {
+    if size_bytes <= free_bytes / 2 {
+        return Ok(true);
+    }
 
-pub fn create_demo_file(path: &Path, size_mb: u64, json_mode: bool) -> Result<()> {
+    if size_bytes <= free_bytes {
+        if force || assume_yes {
+            return Ok(true);
+        }
+        let message = format!(
+            "A {} demo file takes more than half of the {} free on {}",
+            HumanBytes(size_bytes),
+            HumanBytes(free_bytes),
+            dir.display()
+        );
+        if json_mode {
+            anyhow::bail!("{}; pass --yes or --force to create it", message);
+        }
+        return confirm_demo_size(&message);
+    }
+
+    let message = format!(
+        "A {} demo file does not fit in the {} free on {}",
+        HumanBytes(size_bytes),
+        HumanBytes(free_bytes),
+        dir.display()
+    );
+    if !force {
+        anyhow::bail!(
+            "{}; choose a smaller --demo-size, or pass --force to try anyway",
+            message
+        );
+    }
+    if json_mode {
+        let _ = emit_event(&ProgressEvent::Info { message });
+    } else {
+        println!("Warning: {}", message);
+    }
+    Ok(true)
+}
+
+/// Create a demo file of `size_bytes` bytes filled with `content`. The `pattern`
+/// content uses `first_pass`, the pattern of the first wipe pass. A `sparse` demo file is
+/// only extended to its size, without writing any content.
+pub fn create_demo_file(
+    path: &Path,
+    size_bytes: u64,
+    content: &DemoContent,
+    first_pass: WipePattern,
+    sparse: bool,
+    json_mode: bool,
+) -> Result<()> {
     if json_mode {
         let _ = emit_event(&ProgressEvent::Info {
             message: format!(
-                "Creating demo file: {} (Size: {} MB)",
+                "Creating demo file: {} (Size: {})",
                 path.display(),
-                size_mb
+                HumanBytes(size_bytes)
             ),
         });
     } else {
         println!("Creating demo file: {}", path.display());
-        println!("Size: {} MB", size_mb);
+        println!("Size: {}", HumanBytes(size_bytes));
     }
 
-    let size_bytes = size_mb * 1024 * 1024;
-
     // Create file with proper options for Windows
     let file = OpenOptions::new()
         .write(true)
@@ -33,10 +185,32 @@ pub fn create_demo_file(path: &Path, size_mb: u64, json_mode: bool) -> Result<()
         .open(path)
         .with_context(|| format!("Failed to create demo file: {}", path.display()))?;
 
+    // A sparse file allocates nothing until the wipe writes it, so there is no
+    // create phase to wait for
+    if sparse {
+        file.set_len(size_bytes)
+            .with_context(|| format!("Failed to size sparse demo file: {}", path.display()))?;
+
+        if json_mode {
+            let _ = emit_event(&ProgressEvent::DemoFileCreated {
+                path: path.display().to_string(),
+                size_bytes,
+            });
+        } else {
+            println!("Sparse demo file ready for secure wiping");
+        }
+        return Ok(());
+    }
+
     // On Windows, pre-allocating large files can be problematic
     // Instead, we'll write in chunks and let the filesystem handle allocation
     let mut writer = BufWriter::new(file);
-    let pattern = b"DEMO DATA - This will be securely wiped! ";
+    let text = b"DEMO DATA - This will be securely wiped! ";
+    let pattern = match content {
+        DemoContent::Random => Some(WipePattern::Random),
+        DemoContent::Pattern => Some(first_pass),
+        DemoContent::Text => None,
+    };
     let mut written = 0u64;
 
     let pb = if !json_mode {
@@ -67,9 +241,14 @@ pub fn create_demo_file(path: &Path, size_mb: u64, json_mode: bool) -> Result<()
         let remaining = size_bytes - written;
         let chunk_size = std::cmp::min(buffer.len() as u64, remaining) as usize;
 
-        // Fill buffer with pattern
-        for i in 0..chunk_size {
-            buffer[i] = pattern[i % pattern.len()];
+        // Fill buffer with the requested content
+        match &pattern {
+            Some(pattern) => pattern.fill(&mut buffer[..chunk_size], 1),
+            None => {
+                for i in 0..chunk_size {
+                    buffer[i] = text[i % text.len()];
+                }
+            }
         }
 
         writer
@@ -106,25 +285,18 @@ pub fn create_demo_file(path: &Path, size_mb: u64, json_mode: bool) -> Result<()
         .flush()
         .with_context(|| "Failed to flush demo file")?;
 
-    // On Unix systems, also sync to ensure data is on disk
-    #[cfg(unix)]
-    {
-        use std::os::unix::io::AsRawFd;
-        unsafe {
-            libc::fsync(writer.get_ref().as_raw_fd());
-        }
-    }
-
-    // On Windows, use FlushFileBuffers
-    #[cfg(windows)]
-    {
-        use std::os::windows::io::AsRawHandle;
-        use winapi::um::fileapi::FlushFileBuffers;
-
-        unsafe {
-            use winapi::ctypes::c_void;
-            FlushFileBuffers(writer.get_ref().as_raw_handle() as *mut c_void);
+    // Sync to ensure data is on disk (fsync, or FlushFileBuffers on Windows)
+    if let Err(e) = writer.get_ref().flush_to_disk() {
+        if json_mode {
+            let _ = emit_event(&ProgressEvent::Error {
+                message: format!("Failed to sync demo file to disk: {}", e),
+                code: Some("sync_failed".to_string()),
+                pass: None,
+                errno: e.raw_os_error(),
+            });
         }
+        return Err(e)
+            .with_context(|| format!("Failed to sync demo file to disk: {}", path.display()));
     }
 
     if let Some(pb) = pb {
@@ -134,7 +306,7 @@ pub fn create_demo_file(path: &Path, size_mb: u64, json_mode: bool) -> Result<()
     if json_mode {
         let _ = emit_event(&ProgressEvent::DemoFileCreated {
             path: path.display().to_string(),
-            size_mb,
+            size_bytes,
         });
     } else {
         println!("Demo file ready for secure wiping");
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+use crate::{progress::to_json, system::describe_device};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DriveInfo {
     pub path: String,
@@ -8,9 +10,64 @@ pub struct DriveInfo {
     pub size_bytes: Option<u64>,
     pub size_gb: Option<f64>,
     pub description: String,
+    pub is_system: bool,
+    pub removable: Option<bool>,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    /// Volume label (Windows logical drives)
+    pub label: Option<String>,
+    pub file_system: Option<String>,
+    /// Where the drive's filesystem is mounted, if anywhere
+    pub mount_point: Option<String>,
+    /// The drive exists but has no readable medium (e.g. an empty card reader)
+    pub no_media: bool,
+}
+
+/// Output format for the drive listing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DriveOutputFormat {
+    Human,
+    Json,
+    Csv,
+}
+
+/// Criteria applied to the enumerated drives before output
+#[derive(Debug, Clone, Default)]
+pub struct DriveFilter {
+    /// Only keep drives of these types (empty keeps all)
+    pub types: Vec<String>,
+    /// Only keep drives of at least this size; drives of unknown size are dropped
+    pub min_size_gb: Option<f64>,
+    /// Drop the drive(s) holding the running system
+    pub exclude_system: bool,
+    /// Only keep drives known to be removable media
+    pub removable_only: bool,
+}
+
+impl DriveFilter {
+    fn matches(&self, drive: &DriveInfo) -> bool {
+        if !self.types.is_empty() && !self.types.contains(&drive.drive_type) {
+            return false;
+        }
+
+        if self.exclude_system && drive.is_system {
+            return false;
+        }
+
+        if self.removable_only && drive.removable != Some(true) {
+            return false;
+        }
+
+        if let Some(min_size_gb) = self.min_size_gb {
+            return drive.size_gb.is_some_and(|size_gb| size_gb >= min_size_gb);
+        }
+
+        true
+    }
 }
 
-pub fn list_drives(json_mode: bool) -> Result<()> {
+pub fn list_drives(format: DriveOutputFormat, filter: &DriveFilter) -> Result<()> {
     let mut drives = Vec::new();
 
     // Get platform-specific drives
@@ -24,16 +81,29 @@ pub fn list_drives(json_mode: bool) -> Result<()> {
         drives.extend(list_windows_drives()?);
     }
 
-    if json_mode {
-        // Output JSON format
-        let json_output = serde_json::json!({
-            "type": "drive_list",
-            "drives": drives
-        });
-        println!("{}", serde_json::to_string_pretty(&json_output)?);
-    } else {
-        // Output human-readable format
-        print_drives_human_readable(&drives);
+    for drive in &mut drives {
+        let path = std::path::Path::new(&drive.path);
+        drive.is_system = crate::platform::is_system_device(path);
+        drive.removable = crate::platform::is_removable_device(path);
+        if let Some(identity) = crate::system::get_device_identity(path) {
+            drive.vendor = identity.vendor;
+            drive.model = identity.model;
+            drive.serial = identity.serial;
+        }
+    }
+
+    drives.retain(|drive| filter.matches(drive));
+
+    match format {
+        DriveOutputFormat::Json => {
+            let json_output = serde_json::json!({
+                "type": "drive_list",
+                "drives": drives
+            });
+            println!("{}", to_json(&json_output)?);
+        }
+        DriveOutputFormat::Csv => print_drives_csv(&drives),
+        DriveOutputFormat::Human => print_drives_human_readable(&drives),
     }
 
     Ok(())
@@ -41,6 +111,25 @@ pub fn list_drives(json_mode: bool) -> Result<()> {
 
 #[cfg(unix)]
 fn list_unix_drives() -> Result<Vec<DriveInfo>> {
+    // macOS and FreeBSD have no lsblk, and example Linux paths would only mislead
+    #[cfg(target_os = "macos")]
+    {
+        get_diskutil_drives()
+    }
+
+    #[cfg(target_os = "freebsd")]
+    {
+        get_geom_drives()
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "freebsd")))]
+    {
+        list_lsblk_drives()
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "freebsd"))))]
+fn list_lsblk_drives() -> Result<Vec<DriveInfo>> {
     let mut drives = Vec::new();
 
     // Try to use lsblk for detailed information
@@ -55,12 +144,12 @@ fn list_unix_drives() -> Result<Vec<DriveInfo>> {
     Ok(drives)
 }
 
-#[cfg(unix)]
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "freebsd"))))]
 fn get_lsblk_drives() -> Result<Vec<DriveInfo>> {
     use std::process::Command;
 
     let output = Command::new("lsblk")
-        .args(["-J", "-o", "NAME,TYPE,SIZE,MOUNTPOINT"])
+        .args(["-J", "-b", "-o", "NAME,PATH,TYPE,SIZE,MOUNTPOINT"])
         .output()?;
 
     if !output.status.success() {
@@ -68,52 +157,140 @@ fn get_lsblk_drives() -> Result<Vec<DriveInfo>> {
     }
 
     let json_str = String::from_utf8(output.stdout)?;
-    let lsblk_output: serde_json::Value = serde_json::from_str(&json_str)?;
+    parse_lsblk_json(&json_str)
+}
+
+/// Flatten `lsblk -J` output into drive entries, descending through arbitrarily
+/// deep `children` (partition -> crypt -> lvm). Nested entries name their parent.
+#[cfg(unix)]
+#[cfg_attr(any(target_os = "macos", target_os = "freebsd"), allow(dead_code))]
+fn parse_lsblk_json(json_str: &str) -> Result<Vec<DriveInfo>> {
+    let lsblk_output: serde_json::Value = serde_json::from_str(json_str)?;
 
     let mut drives = Vec::new();
 
     if let Some(blockdevices) = lsblk_output["blockdevices"].as_array() {
-        for device in blockdevices {
-            if let (Some(name), Some(device_type), size) = (
-                device["name"].as_str(),
-                device["type"].as_str(),
-                device["size"].as_str(),
-            ) {
-                let path = format!("/dev/{}", name);
-                let size_info = size.unwrap_or("Unknown").to_string();
-
-                drives.push(DriveInfo {
-                    path: path.clone(),
-                    drive_type: device_type.to_string(),
-                    size_bytes: None, // lsblk doesn't give exact bytes easily
-                    size_gb: parse_size_to_gb(size.unwrap_or("")),
-                    description: format!("{} - {} {}", path, device_type, size_info),
-                });
-
-                // Add partitions
-                if let Some(children) = device["children"].as_array() {
-                    for child in children {
-                        if let (Some(child_name), Some(child_type), child_size) = (
-                            child["name"].as_str(),
-                            child["type"].as_str(),
-                            child["size"].as_str(),
-                        ) {
-                            let child_path = format!("/dev/{}", child_name);
-                            let child_size_info = child_size.unwrap_or("Unknown").to_string();
-
-                            drives.push(DriveInfo {
-                                path: child_path.clone(),
-                                drive_type: child_type.to_string(),
-                                size_bytes: None,
-                                size_gb: parse_size_to_gb(child_size.unwrap_or("")),
-                                description: format!(
-                                    "{} - {} {}",
-                                    child_path, child_type, child_size_info
-                                ),
-                            });
-                        }
-                    }
-                }
+        collect_lsblk_devices(blockdevices, None, &mut drives);
+    }
+
+    Ok(drives)
+}
+
+#[cfg(unix)]
+#[cfg_attr(any(target_os = "macos", target_os = "freebsd"), allow(dead_code))]
+fn collect_lsblk_devices(
+    devices: &[serde_json::Value],
+    parent: Option<&str>,
+    drives: &mut Vec<DriveInfo>,
+) {
+    for device in devices {
+        let (Some(name), Some(device_type)) = (device["name"].as_str(), device["type"].as_str())
+        else {
+            continue;
+        };
+
+        // Older lsblk versions have no PATH column
+        let path = match device["path"].as_str() {
+            Some(path) => path.to_string(),
+            None => format!("/dev/{}", name),
+        };
+
+        // A device with several parents (RAID members, multipath) is listed once
+        if drives.iter().any(|drive| drive.path == path) {
+            continue;
+        }
+
+        // With -b sizes are byte counts; some lsblk versions quote them as strings
+        let size_bytes = device["size"]
+            .as_u64()
+            .or_else(|| device["size"].as_str().and_then(|size| size.parse().ok()));
+        let size_info = size_bytes.map_or_else(|| "Unknown".to_string(), format_size);
+        let description = match parent {
+            Some(parent) => format!("{} - {} {} (on {})", path, device_type, size_info, parent),
+            None => format!("{} - {} {}", path, device_type, size_info),
+        };
+
+        drives.push(DriveInfo {
+            path: path.clone(),
+            drive_type: device_type.to_string(),
+            size_bytes,
+            size_gb: size_bytes.map(|bytes| bytes as f64 / 1_073_741_824.0),
+            description,
+            is_system: false,
+            removable: None,
+            vendor: None,
+            model: None,
+            serial: None,
+            label: None,
+            file_system: None,
+            mount_point: device["mountpoint"].as_str().map(str::to_string),
+            no_media: false,
+        });
+
+        if let Some(children) = device["children"].as_array() {
+            collect_lsblk_devices(children, Some(&path), drives);
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn get_diskutil_drives() -> Result<Vec<DriveInfo>> {
+    use std::process::Command;
+
+    let output = Command::new("diskutil").args(["list", "-plist"]).output()?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("diskutil list command failed"));
+    }
+
+    parse_diskutil_plist(&output.stdout)
+}
+
+/// Flatten `diskutil list -plist` output into drive entries in the same shape as
+/// the lsblk ones: whole disks (`disk`), their partitions (`part`), APFS
+/// containers (`container`, on their physical store) and APFS volumes (`volume`)
+#[cfg(unix)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_diskutil_plist(plist_data: &[u8]) -> Result<Vec<DriveInfo>> {
+    let root: plist::Value = plist::from_bytes(plist_data)?;
+
+    let mut drives = Vec::new();
+    let disks = root
+        .as_dictionary()
+        .and_then(|root| root.get("AllDisksAndPartitions"))
+        .and_then(|disks| disks.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    for disk in disks.iter().filter_map(plist::Value::as_dictionary) {
+        // An APFS container is a synthesized disk living on a partition
+        let physical_store = disk
+            .get("APFSPhysicalStores")
+            .and_then(|stores| stores.as_array())
+            .and_then(|stores| stores.first())
+            .and_then(|store| store.as_dictionary())
+            .and_then(|store| diskutil_string(store, "DeviceIdentifier"))
+            .map(|identifier| format!("/dev/{}", identifier));
+        let disk_type = if physical_store.is_some() {
+            "container"
+        } else {
+            "disk"
+        };
+
+        let Some(disk_path) =
+            push_diskutil_entry(&mut drives, disk, disk_type, physical_store.as_deref())
+        else {
+            continue;
+        };
+
+        for (key, entry_type) in [("Partitions", "part"), ("APFSVolumes", "volume")] {
+            let entries = disk.get(key).and_then(|entries| entries.as_array());
+            for entry in entries
+                .into_iter()
+                .flatten()
+                .filter_map(plist::Value::as_dictionary)
+            {
+                push_diskutil_entry(&mut drives, entry, entry_type, Some(&disk_path));
             }
         }
     }
@@ -122,6 +299,147 @@ fn get_lsblk_drives() -> Result<Vec<DriveInfo>> {
 }
 
 #[cfg(unix)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn diskutil_string(entry: &plist::Dictionary, key: &str) -> Option<String> {
+    entry.get(key)?.as_string().map(str::to_string)
+}
+
+/// Add one disk, partition, or volume from the diskutil plist, returning its path
+#[cfg(unix)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn push_diskutil_entry(
+    drives: &mut Vec<DriveInfo>,
+    entry: &plist::Dictionary,
+    drive_type: &str,
+    parent: Option<&str>,
+) -> Option<String> {
+    let path = format!("/dev/{}", diskutil_string(entry, "DeviceIdentifier")?);
+    let size_bytes = entry
+        .get("Size")
+        .and_then(|size| size.as_unsigned_integer());
+    let mount_point = diskutil_string(entry, "MountPoint");
+
+    let size_info = size_bytes.map_or_else(|| "Unknown".to_string(), format_size);
+    let mut description = format!("{} - {} {}", path, drive_type, size_info);
+    if let Some(parent) = parent {
+        description.push_str(&format!(" (on {})", parent));
+    }
+    if let Some(mount_point) = &mount_point {
+        description.push_str(&format!(" mounted at {}", mount_point));
+    }
+
+    drives.push(DriveInfo {
+        path: path.clone(),
+        drive_type: drive_type.to_string(),
+        size_bytes,
+        size_gb: size_bytes.map(|bytes| bytes as f64 / 1_073_741_824.0),
+        description,
+        is_system: false,
+        removable: None,
+        vendor: None,
+        model: None,
+        serial: None,
+        label: diskutil_string(entry, "VolumeName"),
+        file_system: (drive_type == "volume").then(|| "apfs".to_string()),
+        mount_point,
+        no_media: false,
+    });
+
+    Some(path)
+}
+
+#[cfg(target_os = "freebsd")]
+fn get_geom_drives() -> Result<Vec<DriveInfo>> {
+    use std::process::Command;
+
+    let output = Command::new("sysctl")
+        .args(["-n", "kern.geom.conftxt"])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("sysctl kern.geom.conftxt failed"));
+    }
+
+    // Mount points are best effort; the drive list is still useful without them
+    let mounts = Command::new("mount")
+        .arg("-p")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .unwrap_or_default();
+
+    Ok(parse_geom_conftxt(
+        &String::from_utf8_lossy(&output.stdout),
+        &mounts,
+    ))
+}
+
+/// Turn the GEOM configuration (`kern.geom.conftxt`) into drive entries in the
+/// same shape as the lsblk ones: `DISK` providers become `disk` and `PART`
+/// providers `part` entries naming their disk. Other classes (labels, encryption
+/// layers) are skipped. `mounts` is `mount -p` output, in fstab format.
+#[cfg(unix)]
+#[cfg_attr(not(target_os = "freebsd"), allow(dead_code))]
+fn parse_geom_conftxt(conftxt: &str, mounts: &str) -> Vec<DriveInfo> {
+    let mut drives = Vec::new();
+    let mut disk: Option<String> = None;
+
+    for line in conftxt.lines() {
+        // <depth> <class> <name> <mediasize> <sectorsize> [key value]...
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [_, class, name, media_size, ..] = fields.as_slice() else {
+            continue;
+        };
+
+        let path = format!("/dev/{}", name);
+        let (drive_type, parent) = match *class {
+            "DISK" => {
+                disk = Some(path.clone());
+                ("disk", None)
+            }
+            "PART" => ("part", disk.clone()),
+            _ => continue,
+        };
+
+        // Empty drives (e.g. an optical drive without a disc) report a size of 0
+        let size_bytes = media_size.parse::<u64>().ok().filter(|&size| size > 0);
+        let mount = mounts.lines().find_map(|mount| {
+            let mut mount_fields = mount.split_whitespace();
+            (mount_fields.next() == Some(path.as_str()))
+                .then(|| (mount_fields.next(), mount_fields.next()))
+        });
+        let mount_point = mount.and_then(|(mount_point, _)| mount_point.map(str::to_string));
+
+        let size_info = size_bytes.map_or_else(|| "Unknown".to_string(), format_size);
+        let mut description = format!("{} - {} {}", path, drive_type, size_info);
+        if let Some(parent) = &parent {
+            description.push_str(&format!(" (on {})", parent));
+        }
+        if let Some(mount_point) = &mount_point {
+            description.push_str(&format!(" mounted at {}", mount_point));
+        }
+
+        drives.push(DriveInfo {
+            path,
+            drive_type: drive_type.to_string(),
+            size_bytes,
+            size_gb: size_bytes.map(|bytes| bytes as f64 / 1_073_741_824.0),
+            description,
+            is_system: false,
+            removable: None,
+            vendor: None,
+            model: None,
+            serial: None,
+            label: None,
+            file_system: mount.and_then(|(_, file_system)| file_system.map(str::to_string)),
+            mount_point,
+            no_media: size_bytes.is_none(),
+        });
+    }
+
+    drives
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "freebsd"))))]
 fn get_common_unix_devices() -> Vec<DriveInfo> {
     vec![
         DriveInfo {
@@ -130,6 +448,15 @@ fn get_common_unix_devices() -> Vec<DriveInfo> {
             size_bytes: None,
             size_gb: None,
             description: "/dev/sda - SATA disk (example)".to_string(),
+            is_system: false,
+            removable: None,
+            vendor: None,
+            model: None,
+            serial: None,
+            label: None,
+            file_system: None,
+            mount_point: None,
+            no_media: false,
         },
         DriveInfo {
             path: "/dev/sda1".to_string(),
@@ -137,6 +464,15 @@ fn get_common_unix_devices() -> Vec<DriveInfo> {
             size_bytes: None,
             size_gb: None,
             description: "/dev/sda1 - SATA partition (example)".to_string(),
+            is_system: false,
+            removable: None,
+            vendor: None,
+            model: None,
+            serial: None,
+            label: None,
+            file_system: None,
+            mount_point: None,
+            no_media: false,
         },
         DriveInfo {
             path: "/dev/nvme0n1".to_string(),
@@ -144,6 +480,15 @@ fn get_common_unix_devices() -> Vec<DriveInfo> {
             size_bytes: None,
             size_gb: None,
             description: "/dev/nvme0n1 - NVMe disk (example)".to_string(),
+            is_system: false,
+            removable: None,
+            vendor: None,
+            model: None,
+            serial: None,
+            label: None,
+            file_system: None,
+            mount_point: None,
+            no_media: false,
         },
         DriveInfo {
             path: "/dev/nvme0n1p1".to_string(),
@@ -151,6 +496,15 @@ fn get_common_unix_devices() -> Vec<DriveInfo> {
             size_bytes: None,
             size_gb: None,
             description: "/dev/nvme0n1p1 - NVMe partition (example)".to_string(),
+            is_system: false,
+            removable: None,
+            vendor: None,
+            model: None,
+            serial: None,
+            label: None,
+            file_system: None,
+            mount_point: None,
+            no_media: false,
         },
     ]
 }
@@ -165,20 +519,19 @@ fn list_windows_drives() -> Result<Vec<DriveInfo>> {
     // Add logical drives
     drives.extend(get_windows_logical_drives()?);
 
+    // Add volumes only reachable through their GUID path
+    drives.extend(get_windows_unlettered_volumes());
+
     Ok(drives)
 }
 
 #[cfg(windows)]
 fn get_windows_physical_drives() -> Result<Vec<DriveInfo>> {
-    use winapi::{
-        shared::minwindef::{DWORD, LPVOID},
-        um::{
-            fileapi::{CreateFileW, OPEN_EXISTING},
-            handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
-            ioapiset::DeviceIoControl,
-            winioctl::{DISK_GEOMETRY_EX, IOCTL_DISK_GET_DRIVE_GEOMETRY_EX},
-            winnt::{FILE_ATTRIBUTE_NORMAL, GENERIC_READ},
-        },
+    use crate::platform::windows::device_size;
+    use winapi::um::{
+        fileapi::{CreateFileW, OPEN_EXISTING},
+        handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+        winnt::{FILE_ATTRIBUTE_NORMAL, GENERIC_READ},
     };
 
     let mut drives = Vec::new();
@@ -200,24 +553,12 @@ fn get_windows_physical_drives() -> Result<Vec<DriveInfo>> {
             );
 
             if handle != INVALID_HANDLE_VALUE {
-                let mut geometry: DISK_GEOMETRY_EX = std::mem::zeroed();
-                let mut bytes_returned: DWORD = 0;
-
-                let success = DeviceIoControl(
-                    handle,
-                    IOCTL_DISK_GET_DRIVE_GEOMETRY_EX,
-                    std::ptr::null_mut(),
-                    0,
-                    &mut geometry as *mut _ as LPVOID,
-                    std::mem::size_of::<DISK_GEOMETRY_EX>() as DWORD,
-                    &mut bytes_returned,
-                    std::ptr::null_mut(),
-                );
+                // Same size query as the wipe itself, so the listing matches what gets wiped
+                let size = device_size(handle);
 
                 CloseHandle(handle);
 
-                if success != 0 {
-                    let size_bytes = *geometry.DiskSize.QuadPart() as u64;
+                if let Ok(size_bytes) = size {
                     let size_gb = size_bytes as f64 / 1_073_741_824.0;
 
                     drives.push(DriveInfo {
@@ -226,6 +567,15 @@ fn get_windows_physical_drives() -> Result<Vec<DriveInfo>> {
                         size_bytes: Some(size_bytes),
                         size_gb: Some(size_gb),
                         description: format!("{} - Physical Drive ({:.2} GB)", drive_path, size_gb),
+                        is_system: false,
+                        removable: None,
+                        vendor: None,
+                        model: None,
+                        serial: None,
+                        label: None,
+                        file_system: None,
+                        mount_point: None,
+                        no_media: false,
                     });
                 } else {
                     drives.push(DriveInfo {
@@ -234,6 +584,15 @@ fn get_windows_physical_drives() -> Result<Vec<DriveInfo>> {
                         size_bytes: None,
                         size_gb: None,
                         description: format!("{} - Physical Drive (size unknown)", drive_path),
+                        is_system: false,
+                        removable: None,
+                        vendor: None,
+                        model: None,
+                        serial: None,
+                        label: None,
+                        file_system: None,
+                        mount_point: None,
+                        no_media: false,
                     });
                 }
             }
@@ -245,6 +604,7 @@ fn get_windows_physical_drives() -> Result<Vec<DriveInfo>> {
 
 #[cfg(windows)]
 fn get_windows_logical_drives() -> Result<Vec<DriveInfo>> {
+    use crate::platform::windows::volume_info;
     use winapi::um::fileapi::GetLogicalDrives;
 
     let mut drives = Vec::new();
@@ -256,13 +616,43 @@ fn get_windows_logical_drives() -> Result<Vec<DriveInfo>> {
                 if (drive_mask >> i) & 1 == 1 {
                     let drive_letter = (b'A' + i) as char;
                     let drive_path = format!(r"\\.\{}:", drive_letter);
+                    let volume = volume_info(drive_letter);
+
+                    let description = match &volume {
+                        Some(volume) => {
+                            let mut description = format!("{} -", drive_path);
+                            if let Some(label) = &volume.label {
+                                description.push_str(&format!(" \"{}\"", label));
+                            }
+                            if let Some(file_system) = &volume.file_system {
+                                description.push_str(&format!(" {}", file_system));
+                            }
+                            description.push_str(&format!(
+                                " {:.1} GB",
+                                volume.total_bytes as f64 / 1_073_741_824.0
+                            ));
+                            description
+                        }
+                        None => format!("{} - no media", drive_path),
+                    };
 
                     drives.push(DriveInfo {
                         path: drive_path.clone(),
                         drive_type: "volume".to_string(),
-                        size_bytes: None,
-                        size_gb: None,
-                        description: format!("{} - Logical Volume", drive_path),
+                        size_bytes: volume.as_ref().map(|v| v.total_bytes),
+                        size_gb: volume
+                            .as_ref()
+                            .map(|v| v.total_bytes as f64 / 1_073_741_824.0),
+                        description,
+                        is_system: false,
+                        removable: None,
+                        vendor: None,
+                        model: None,
+                        serial: None,
+                        label: volume.as_ref().and_then(|v| v.label.clone()),
+                        file_system: volume.as_ref().and_then(|v| v.file_system.clone()),
+                        mount_point: volume.as_ref().map(|_| format!("{}:\\", drive_letter)),
+                        no_media: volume.is_none(),
                     });
                 }
             }
@@ -272,6 +662,95 @@ fn get_windows_logical_drives() -> Result<Vec<DriveInfo>> {
     Ok(drives)
 }
 
+/// Volumes without a drive letter (recovery partitions, volumes mounted on a
+/// folder), listed by their `\\?\Volume{GUID}\` path
+#[cfg(windows)]
+fn get_windows_unlettered_volumes() -> Vec<DriveInfo> {
+    use crate::platform::windows::{
+        device_size, list_volume_guid_paths, trim_volume_path, volume_info_at, volume_mount_points,
+    };
+    use winapi::um::{
+        fileapi::{CreateFileW, OPEN_EXISTING},
+        handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+        winnt::{FILE_ATTRIBUTE_NORMAL, FILE_SHARE_READ, FILE_SHARE_WRITE},
+    };
+
+    let mut drives = Vec::new();
+
+    for volume_path in list_volume_guid_paths() {
+        let mount_points = volume_mount_points(&volume_path);
+        // Lettered volumes are already listed as \\.\X:
+        if mount_points
+            .iter()
+            .any(|mount_point| mount_point.len() == 3)
+        {
+            continue;
+        }
+
+        let wide_path: Vec<u16> = trim_volume_path(&volume_path)
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+        let size_bytes = unsafe {
+            let handle = CreateFileW(
+                wide_path.as_ptr(),
+                0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                std::ptr::null_mut(),
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                std::ptr::null_mut(),
+            );
+            if handle == INVALID_HANDLE_VALUE {
+                None
+            } else {
+                let size = device_size(handle).ok();
+                CloseHandle(handle);
+                size
+            }
+        };
+        let volume = volume_info_at(&volume_path);
+
+        let mut description = format!("{} -", volume_path);
+        if let Some(label) = volume.as_ref().and_then(|v| v.label.as_ref()) {
+            description.push_str(&format!(" \"{}\"", label));
+        }
+        if let Some(file_system) = volume.as_ref().and_then(|v| v.file_system.as_ref()) {
+            description.push_str(&format!(" {}", file_system));
+        }
+        match size_bytes {
+            Some(size_bytes) => {
+                description.push_str(&format!(" {:.1} GB", size_bytes as f64 / 1_073_741_824.0))
+            }
+            None => description.push_str(" size unknown"),
+        }
+        if mount_points.is_empty() {
+            description.push_str(" (no mount point)");
+        } else {
+            description.push_str(&format!(" (mounted at {})", mount_points.join(", ")));
+        }
+
+        drives.push(DriveInfo {
+            path: volume_path,
+            drive_type: "volume".to_string(),
+            size_bytes,
+            size_gb: size_bytes.map(|size| size as f64 / 1_073_741_824.0),
+            description,
+            is_system: false,
+            removable: None,
+            vendor: None,
+            model: None,
+            serial: None,
+            label: volume.as_ref().and_then(|v| v.label.clone()),
+            file_system: volume.as_ref().and_then(|v| v.file_system.clone()),
+            mount_point: mount_points.first().cloned(),
+            no_media: false,
+        });
+    }
+
+    drives
+}
+
 #[cfg(not(any(unix, windows)))]
 fn list_unix_drives() -> Result<Vec<DriveInfo>> {
     Ok(vec![])
@@ -282,32 +761,69 @@ fn list_windows_drives() -> Result<Vec<DriveInfo>> {
     Ok(vec![])
 }
 
-fn parse_size_to_gb(size_str: &str) -> Option<f64> {
-    if size_str.is_empty() {
-        return None;
-    }
-
-    let size_str = size_str.to_uppercase();
-    let (number_part, unit) = if size_str.ends_with('G') || size_str.ends_with("GB") {
-        (size_str.trim_end_matches("GB").trim_end_matches('G'), 1.0)
-    } else if size_str.ends_with('M') || size_str.ends_with("MB") {
-        (size_str.trim_end_matches("MB").trim_end_matches('M'), 0.001)
-    } else if size_str.ends_with('K') || size_str.ends_with("KB") {
-        (
-            size_str.trim_end_matches("KB").trim_end_matches('K'),
-            0.000001,
-        )
+/// Format a byte count the way lsblk does, e.g. `476.9G`
+#[cfg(unix)]
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "K", "M", "G", "T", "P"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 || size.fract() == 0.0 {
+        format!("{}{}", size as u64, UNITS[unit])
     } else {
-        (size_str.as_str(), 0.000000001) // Assume bytes
-    };
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
 
-    if let Ok(number) = number_part.parse::<f64>() {
-        Some(number * unit)
+/// Quote a CSV field when it contains separators, quotes, or line breaks
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
     } else {
-        None
+        value.to_string()
     }
 }
 
+fn print_drives_csv(drives: &[DriveInfo]) {
+    println!("path,type,size_bytes,size_gb,description");
+    for drive in drives {
+        println!(
+            "{},{},{},{},{}",
+            csv_field(&drive.path),
+            csv_field(&drive.drive_type),
+            drive.size_bytes.map(|b| b.to_string()).unwrap_or_default(),
+            drive
+                .size_gb
+                .map(|gb| format!("{:.2}", gb))
+                .unwrap_or_default(),
+            csv_field(&drive.description)
+        );
+    }
+}
+
+fn print_drive_line(drive: &DriveInfo) {
+    let mut line = format!("  {}", drive.description);
+    if let Some(label) = describe_device(
+        drive.vendor.as_deref(),
+        drive.model.as_deref(),
+        drive.serial.as_deref(),
+    ) {
+        line.push_str(&format!(" ({})", label));
+    }
+    if drive.removable == Some(true) {
+        line.push_str(" [REMOVABLE]");
+    }
+    if drive.is_system {
+        line.push_str(" [SYSTEM]");
+    }
+    println!("{}", line);
+}
+
 fn print_drives_human_readable(drives: &[DriveInfo]) {
     if drives.is_empty() {
         println!("No drives found or platform not supported for drive enumeration.");
@@ -320,6 +836,7 @@ fn print_drives_human_readable(drives: &[DriveInfo]) {
     // Group by drive type
     let mut physical_drives = Vec::new();
     let mut partitions = Vec::new();
+    let mut containers = Vec::new();
     let mut volumes = Vec::new();
     let mut other = Vec::new();
 
@@ -327,6 +844,7 @@ fn print_drives_human_readable(drives: &[DriveInfo]) {
         match drive.drive_type.as_str() {
             "disk" => physical_drives.push(drive),
             "part" => partitions.push(drive),
+            "container" => containers.push(drive),
             "volume" => volumes.push(drive),
             _ => other.push(drive),
         }
@@ -335,7 +853,7 @@ fn print_drives_human_readable(drives: &[DriveInfo]) {
     if !physical_drives.is_empty() {
         println!("Physical Drives:");
         for drive in physical_drives {
-            println!("  {}", drive.description);
+            print_drive_line(drive);
         }
         println!();
     }
@@ -343,7 +861,15 @@ fn print_drives_human_readable(drives: &[DriveInfo]) {
     if !partitions.is_empty() {
         println!("Partitions:");
         for drive in partitions {
-            println!("  {}", drive.description);
+            print_drive_line(drive);
+        }
+        println!();
+    }
+
+    if !containers.is_empty() {
+        println!("APFS Containers:");
+        for drive in containers {
+            print_drive_line(drive);
         }
         println!();
     }
@@ -351,7 +877,7 @@ fn print_drives_human_readable(drives: &[DriveInfo]) {
     if !volumes.is_empty() {
         println!("Volumes:");
         for drive in volumes {
-            println!("  {}", drive.description);
+            print_drive_line(drive);
         }
         println!();
     }
@@ -359,7 +885,7 @@ fn print_drives_human_readable(drives: &[DriveInfo]) {
     if !other.is_empty() {
         println!("Other Devices:");
         for drive in other {
-            println!("  {}", drive.description);
+            print_drive_line(drive);
         }
         println!();
     }
@@ -389,3 +915,120 @@ fn print_drives_human_readable(drives: &[DriveInfo]) {
     println!("   Always verify the target device before proceeding.");
     println!("   Use demo mode for safe testing: --demo --demo-size 10");
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lsblk_nested_children() {
+        let drives = parse_lsblk_json(include_str!("../tests/fixtures/lsblk_nested.json"))
+            .expect("fixture should parse");
+
+        let paths: Vec<&str> = drives.iter().map(|drive| drive.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/dev/nvme0n1",
+                "/dev/nvme0n1p1",
+                "/dev/nvme0n1p2",
+                "/dev/mapper/luks-3f2a",
+                "/dev/mapper/vg0-root",
+                "/dev/mapper/vg0-swap",
+                "/dev/sdb",
+            ]
+        );
+
+        let root = &drives[4];
+        assert_eq!(root.drive_type, "lvm");
+        assert_eq!(root.size_bytes, Some(214748364800));
+        assert_eq!(root.size_gb, Some(200.0));
+        assert_eq!(
+            root.description,
+            "/dev/mapper/vg0-root - lvm 200G (on /dev/mapper/luks-3f2a)"
+        );
+        assert_eq!(drives[0].description, "/dev/nvme0n1 - disk 476.9G");
+        // Older lsblk versions quote the byte count
+        assert_eq!(drives[6].size_bytes, Some(16013942784));
+        assert!(!drives[0].description.contains("(on "));
+        assert_eq!(drives[1].mount_point.as_deref(), Some("/boot/efi"));
+    }
+
+    #[test]
+    fn test_parse_geom_conftxt() {
+        let mounts = "/dev/ada0p2\t\t/\t\tufs\trw\t1 1\n";
+        let drives = parse_geom_conftxt(include_str!("../tests/fixtures/geom_conftxt.txt"), mounts);
+
+        let entries: Vec<(&str, &str)> = drives
+            .iter()
+            .map(|drive| (drive.path.as_str(), drive.drive_type.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("/dev/ada0", "disk"),
+                ("/dev/ada0p3", "part"),
+                ("/dev/ada0p2", "part"),
+                ("/dev/ada0p1", "part"),
+                ("/dev/da0", "disk"),
+                ("/dev/da0p1", "part"),
+                ("/dev/cd0", "disk"),
+            ]
+        );
+
+        assert_eq!(drives[0].size_bytes, Some(256060514304));
+        assert_eq!(drives[0].description, "/dev/ada0 - disk 238.5G");
+        assert_eq!(
+            drives[2].description,
+            "/dev/ada0p2 - part 230.5G (on /dev/ada0) mounted at /"
+        );
+        assert_eq!(drives[2].file_system.as_deref(), Some("ufs"));
+        assert_eq!(
+            drives[5].description,
+            "/dev/da0p1 - part 3.6T (on /dev/da0)"
+        );
+        assert!(drives[6].no_media);
+        assert_eq!(drives[6].size_bytes, None);
+    }
+
+    #[test]
+    fn test_parse_diskutil_plist() {
+        let drives = parse_diskutil_plist(include_bytes!("../tests/fixtures/diskutil_list.plist"))
+            .expect("fixture should parse");
+
+        let entries: Vec<(&str, &str)> = drives
+            .iter()
+            .map(|drive| (drive.path.as_str(), drive.drive_type.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("/dev/disk0", "disk"),
+                ("/dev/disk0s1", "part"),
+                ("/dev/disk0s2", "part"),
+                ("/dev/disk3", "container"),
+                ("/dev/disk3s1", "volume"),
+                ("/dev/disk3s5", "volume"),
+                ("/dev/disk4", "disk"),
+                ("/dev/disk4s1", "part"),
+            ]
+        );
+
+        assert_eq!(drives[0].size_bytes, Some(500277790720));
+        assert_eq!(drives[0].description, "/dev/disk0 - disk 465.9G");
+        assert_eq!(
+            drives[3].description,
+            "/dev/disk3 - container 465.6G (on /dev/disk0s2)"
+        );
+
+        let data = &drives[5];
+        assert_eq!(data.label.as_deref(), Some("Data"));
+        assert_eq!(data.file_system.as_deref(), Some("apfs"));
+        assert_eq!(data.mount_point.as_deref(), Some("/System/Volumes/Data"));
+        assert_eq!(
+            data.description,
+            "/dev/disk3s5 - volume 465.6G (on /dev/disk3) mounted at /System/Volumes/Data"
+        );
+        assert_eq!(drives[7].mount_point.as_deref(), Some("/Volumes/USB"));
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::platform::device_open_path;
+
+/// Number of blocks read from the target
+const SAMPLE_COUNT: u64 = 64;
+/// Size of each sampled block
+const SAMPLE_BYTES: u64 = 64 * 1024;
+/// Sample offsets are aligned so that raw devices accept the reads
+const SAMPLE_ALIGNMENT: u64 = 4096;
+
+/// Randomness estimate for a sample of a wiped target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntropyEstimate {
+    pub samples: u64,
+    pub sampled_bytes: u64,
+    /// Shannon entropy of the byte histogram: ~0 for a fixed pattern, ~8 for random data
+    pub bits_per_byte: f64,
+    /// Chi-square statistic against a uniform byte distribution (255 degrees of freedom)
+    pub chi_square: f64,
+}
+
+/// Offsets of the blocks to read. Targets too small to sample are read whole;
+/// otherwise one block is picked at random from each of `SAMPLE_COUNT` equal
+/// stripes so that samples never overlap.
+fn sample_offsets(size: u64) -> Vec<u64> {
+    if size <= SAMPLE_COUNT * SAMPLE_BYTES {
+        return (0..size).step_by(SAMPLE_BYTES as usize).collect();
+    }
+
+    let stripe = size / SAMPLE_COUNT / SAMPLE_ALIGNMENT * SAMPLE_ALIGNMENT;
+    let slots = (stripe - SAMPLE_BYTES) / SAMPLE_ALIGNMENT;
+    let mut rng = rand::thread_rng();
+    (0..SAMPLE_COUNT)
+        .map(|stripe_index| stripe_index * stripe + rng.gen_range(0..=slots) * SAMPLE_ALIGNMENT)
+        .collect()
+}
+
+/// Read sample blocks from the first `size` bytes of `path` and estimate how
+/// random their contents look
+pub fn sample_entropy(path: &Path, size: u64) -> Result<EntropyEstimate> {
+    let mut file = File::open(device_open_path(path))
+        .with_context(|| format!("Failed to open {} for entropy check", path.display()))?;
+
+    let mut histogram = [0u64; 256];
+    let mut buffer = vec![0u8; SAMPLE_BYTES as usize];
+    let mut samples = 0;
+
+    for offset in sample_offsets(size) {
+        let length = std::cmp::min(SAMPLE_BYTES, size - offset) as usize;
+
+        file.seek(SeekFrom::Start(offset))
+            .with_context(|| format!("Failed to seek to offset {}", offset))?;
+        file.read_exact(&mut buffer[..length])
+            .with_context(|| format!("Failed to read sample at offset {}", offset))?;
+
+        for &byte in &buffer[..length] {
+            histogram[byte as usize] += 1;
+        }
+        samples += 1;
+    }
+
+    Ok(estimate_from_histogram(&histogram, samples))
+}
+
+fn estimate_from_histogram(histogram: &[u64; 256], samples: u64) -> EntropyEstimate {
+    let total: u64 = histogram.iter().sum();
+    if total == 0 {
+        return EntropyEstimate {
+            samples,
+            sampled_bytes: 0,
+            bits_per_byte: 0.0,
+            chi_square: 0.0,
+        };
+    }
+
+    let expected = total as f64 / 256.0;
+    let mut bits_per_byte = 0.0;
+    let mut chi_square = 0.0;
+    for &count in histogram {
+        if count > 0 {
+            let probability = count as f64 / total as f64;
+            bits_per_byte -= probability * probability.log2();
+        }
+        chi_square += (count as f64 - expected).powi(2) / expected;
+    }
+
+    EntropyEstimate {
+        samples,
+        sampled_bytes: total,
+        bits_per_byte,
+        chi_square,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_entropy_of_zero_and_random_data() {
+        let size = 16 * 1024 * 1024;
+
+        let mut zeros = NamedTempFile::new().unwrap();
+        zeros.write_all(&vec![0u8; size]).unwrap();
+        let estimate = sample_entropy(zeros.path(), size as u64).unwrap();
+        assert_eq!(estimate.samples, SAMPLE_COUNT);
+        assert!(estimate.bits_per_byte < 0.01);
+
+        let mut data = vec![0u8; size];
+        rand::thread_rng().fill_bytes(&mut data);
+        let mut random = NamedTempFile::new().unwrap();
+        random.write_all(&data).unwrap();
+        let estimate = sample_entropy(random.path(), size as u64).unwrap();
+        assert!(estimate.bits_per_byte > 7.99);
+        assert!(estimate.chi_square < 400.0);
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use indicatif::MultiProgress;
+use std::{
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    cancel,
+    platform::{free_space_bytes, preallocate},
+    priority::PriorityMode,
+    wipe::{format_duration, format_throughput, WipeContext, WipeOptions},
+};
+
+/// Largest single fill file, so filesystems with file size limits (FAT32) still fill up
+const FILL_FILE_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Smallest fill file worth creating; free space beyond the reserve that is
+/// smaller than this is left as it is
+const FILL_FILE_MIN_BYTES: u64 = 1024 * 1024;
+
+/// Temporary fill files, deleted when dropped so that errors, panics and
+/// cancellation never leave the filesystem full. They are also registered
+/// with the Ctrl-C handler, for a second Ctrl-C that exits without unwinding.
+struct FillFiles {
+    paths: Vec<PathBuf>,
+}
+
+impl FillFiles {
+    fn push(&mut self, path: PathBuf) {
+        cancel::register_temporary_file(&path);
+        self.paths.push(path);
+    }
+
+    fn remove_last(&mut self) {
+        if let Some(path) = self.paths.pop() {
+            let _ = fs::remove_file(&path);
+            cancel::unregister_temporary_file(&path);
+        }
+    }
+}
+
+impl Drop for FillFiles {
+    fn drop(&mut self) {
+        while !self.paths.is_empty() {
+            self.remove_last();
+        }
+    }
+}
+
+/// Whether a write error means the filesystem (or our quota on it) is full
+fn is_out_of_space(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+    )
+}
+
+/// Overwrite the free space of a mounted filesystem: fill it with temporary files,
+/// each reserved up front and then wiped like any other target, then delete them.
+pub fn wipe_free_space(mount_point: &Path, options: &WipeOptions, reserve_mb: u64) -> Result<()> {
+    let free_bytes = free_space_bytes(mount_point)?;
+    let reserve_bytes = reserve_mb * 1024 * 1024;
+    if free_bytes.saturating_sub(reserve_bytes) < FILL_FILE_MIN_BYTES {
+        anyhow::bail!(
+            "{} has only {} bytes free, which is within the {} MB reserve",
+            mount_point.display(),
+            free_bytes,
+            reserve_mb
+        );
+    }
+
+    if !options.json_mode {
+        println!(
+            "Wiping free space on {} using {} algorithm ({} passes)",
+            mount_point.display(),
+            options.algorithm_name(),
+            options.total_passes()
+        );
+        println!(
+            "Free space: {:.2} MB, reserving {} MB",
+            free_bytes as f64 / 1_048_576.0,
+            reserve_mb
+        );
+        if options.priority != PriorityMode::Normal {
+            println!("Priority: {}", options.priority.description());
+        }
+        println!();
+    }
+
+    let start_time = Instant::now();
+    let mut fill_files = FillFiles { paths: Vec::new() };
+    // One progress bar per fill file, named after it
+    let multi_progress = (!options.json_mode).then(MultiProgress::new);
+    let mut filled_bytes = 0u64;
+
+    // Fill files are reserved one at a time from what is still free, until the
+    // reserve is all that is left. Running out of space while reserving (file
+    // system metadata, other writers) halves the file, and ends the fill once
+    // even the smallest one does not fit.
+    let mut len = std::cmp::min(FILL_FILE_MAX_BYTES, free_bytes - reserve_bytes);
+    while len >= FILL_FILE_MIN_BYTES {
+        let path = mount_point.join(format!(
+            ".secure_wipe_fill_{}_{}",
+            std::process::id(),
+            fill_files.paths.len()
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create fill file {}", path.display()))?;
+        fill_files.push(path.clone());
+
+        match preallocate(&file, len) {
+            Ok(()) => {}
+            Err(e) if is_out_of_space(&e) => {
+                drop(file);
+                fill_files.remove_last();
+                len /= 2;
+                continue;
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to reserve fill file {}", path.display()))
+            }
+        }
+
+        let mut context =
+            WipeContext::from_target(file, &path.display().to_string(), options.clone())?;
+        if let Some(multi_progress) = &multi_progress {
+            context = context.with_multi_progress(multi_progress.clone());
+        }
+        context.wipe()?;
+        filled_bytes += len;
+
+        len = std::cmp::min(
+            FILL_FILE_MAX_BYTES,
+            free_space_bytes(mount_point)?.saturating_sub(reserve_bytes),
+        );
+    }
+
+    // Deleting the fill files returns the space to the filesystem
+    drop(fill_files);
+
+    if !options.json_mode {
+        let elapsed = start_time.elapsed();
+        let throughput = (filled_bytes as f64 * options.total_passes() as f64)
+            / elapsed.as_secs_f64()
+            / 1_048_576.0;
+        println!();
+        println!(
+            "Free space wipe completed: {:.2} MB overwritten and released",
+            filled_bytes as f64 / 1_048_576.0
+        );
+        println!("Total time: {}", format_duration(elapsed.as_secs_f64()));
+        println!(
+            "Average throughput: {}",
+            format_throughput(throughput * 1_048_576.0)
+        );
+        if options.sleep_inhibited {
+            println!("System sleep was inhibited during the wipe");
+        }
+    }
+
+    Ok(())
+}
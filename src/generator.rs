@@ -0,0 +1,434 @@
+use aes::{
+    cipher::{KeyIvInit, StreamCipher, StreamCipherSeek},
+    Aes128,
+};
+use aligned_vec::{avec_rt, AVec, RuntimeAlign};
+use clap::ValueEnum;
+use rand::{rngs::OsRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+use std::{
+    sync::{
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Generator of the data written by random passes
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum RngKind {
+    /// The OS random source (getrandom) for every buffer; slow but maximal
+    Os,
+    /// ChaCha20 CSPRNG keyed from the OS once per pass
+    Chacha,
+    /// AES-128-CTR keystream keyed from the OS once per pass; fastest secure option with AES-NI
+    AesCtr,
+    /// Xoshiro256++, not cryptographically secure; for scrubbing where raw speed matters
+    Fast,
+}
+
+impl RngKind {
+    /// Name of the generator, as reported in the start event and summary
+    pub fn name(&self) -> &'static str {
+        match self {
+            RngKind::Os => "os",
+            RngKind::Chacha => "chacha20",
+            RngKind::AesCtr => "aes-128-ctr",
+            RngKind::Fast => "xoshiro256++",
+        }
+    }
+}
+
+/// Filled buffers each worker keeps ready ahead of the writer
+const BUFFERS_PER_WORKER: usize = 2;
+/// Smallest part of a buffer worth filling on a thread of its own
+const MIN_THREAD_CHUNK: usize = 64 * 1024;
+
+/// AES-128 in counter mode with a 64-bit nonce and a 64-bit block counter
+type Aes128Ctr = ctr::Ctr64BE<Aes128>;
+
+/// Fills buffers with the random data of a pass at a given offset of the target
+pub trait RandomSource: Send + Sync {
+    fn fill_at(&self, offset: u64, buffer: &mut [u8]);
+
+    /// Whether the data at an offset does not depend on how the pass was split
+    /// into buffers, so that it can be generated again to verify the pass
+    fn reproducible(&self) -> bool {
+        false
+    }
+}
+
+/// A ChaCha20 keystream under a per-pass key from the OS: the byte at offset X
+/// of the target is byte X of the keystream, whichever part of the pass it is
+/// generated with
+struct ChaChaSource {
+    key: [u8; 32],
+}
+
+impl RandomSource for ChaChaSource {
+    fn fill_at(&self, offset: u64, buffer: &mut [u8]) {
+        let mut rng = ChaCha20Rng::from_seed(self.key);
+        // The keystream is addressed in 32-bit words; an unaligned offset
+        // starts inside the first word
+        rng.set_word_pos(u128::from(offset / 4));
+        let skip = (offset % 4) as usize;
+        if skip == 0 {
+            rng.fill_bytes(buffer);
+            return;
+        }
+
+        let mut word = [0u8; 4];
+        rng.fill_bytes(&mut word);
+        let head = std::cmp::min(4 - skip, buffer.len());
+        buffer[..head].copy_from_slice(&word[skip..skip + head]);
+        rng.fill_bytes(&mut buffer[head..]);
+    }
+
+    fn reproducible(&self) -> bool {
+        true
+    }
+}
+
+/// An AES-128-CTR keystream under a per-pass key and nonce from the OS: the
+/// byte at offset X of the target is byte X of the keystream. AES-NI makes it
+/// faster than ChaCha20 on most x86_64 CPUs.
+struct AesCtrSource {
+    key: [u8; 16],
+    /// Nonce in the upper half of the counter block, the block counter starts at 0
+    iv: [u8; 16],
+}
+
+impl RandomSource for AesCtrSource {
+    fn fill_at(&self, offset: u64, buffer: &mut [u8]) {
+        let mut cipher = Aes128Ctr::new(&self.key.into(), &self.iv.into());
+        cipher.seek(offset);
+        // The keystream is what encrypting zeros yields
+        buffer.fill(0);
+        cipher.apply_keystream(buffer);
+    }
+
+    fn reproducible(&self) -> bool {
+        true
+    }
+}
+
+/// The OS random source (getrandom) for every buffer
+struct OsSource;
+
+impl RandomSource for OsSource {
+    fn fill_at(&self, _offset: u64, buffer: &mut [u8]) {
+        OsRng.fill_bytes(buffer);
+    }
+}
+
+/// Xoshiro256++ seeded from a per-pass seed and the offset of each buffer.
+/// Fast, but its output can be predicted from a few outputs, so it only suits
+/// scrubbing where nobody tries to tell the data apart from noise.
+struct XoshiroSource {
+    seed: u64,
+}
+
+impl RandomSource for XoshiroSource {
+    fn fill_at(&self, offset: u64, buffer: &mut [u8]) {
+        Xoshiro256PlusPlus::seed_from_u64(self.seed ^ offset).fill_bytes(buffer);
+    }
+}
+
+/// The random data of one pass, from the generator picked with `--rng`. A
+/// buffer is split into parts that are filled on separate threads, each part
+/// generated for its own offset.
+///
+/// With the ChaCha20 and AES-CTR generators the data depends only on the pass
+/// key and the offset, not on the buffer size, the number of threads, or the
+/// order buffers are filled in. The key comes from the OS and lives as long as
+/// the stream, so the pass can be verified against the data generated again;
+/// it is never stored or reported.
+#[derive(Clone)]
+pub struct RandomStream {
+    source: Arc<dyn RandomSource>,
+    threads: usize,
+}
+
+impl RandomStream {
+    /// A stream of the `rng` generator under a fresh key, filling each buffer
+    /// on up to `threads` threads
+    pub fn new(rng: RngKind, threads: usize) -> Self {
+        let source: Arc<dyn RandomSource> = match rng {
+            RngKind::Os => Arc::new(OsSource),
+            RngKind::Chacha => {
+                let mut key = [0u8; 32];
+                OsRng.fill_bytes(&mut key);
+                Arc::new(ChaChaSource { key })
+            }
+            RngKind::AesCtr => {
+                let mut key = [0u8; 16];
+                let mut iv = [0u8; 16];
+                OsRng.fill_bytes(&mut key);
+                OsRng.fill_bytes(&mut iv[..8]);
+                Arc::new(AesCtrSource { key, iv })
+            }
+            RngKind::Fast => Arc::new(XoshiroSource {
+                seed: OsRng.next_u64(),
+            }),
+        };
+        Self::with_source(source, threads)
+    }
+
+    fn with_source(source: Arc<dyn RandomSource>, threads: usize) -> Self {
+        RandomStream {
+            source,
+            threads: threads.max(1),
+        }
+    }
+
+    /// Whether `fill` yields the same data for an offset every time, so that
+    /// the pass can be read back and compared with the data generated again
+    pub fn reproducible(&self) -> bool {
+        self.source.reproducible()
+    }
+
+    /// Fill `buffer` with the data of the pass at `offset`, splitting it into
+    /// one part per thread. Parts are whole ChaCha20 blocks except the last.
+    pub fn fill(&self, offset: u64, buffer: &mut [u8]) {
+        let part_len = buffer
+            .len()
+            .div_ceil(self.threads)
+            .max(MIN_THREAD_CHUNK)
+            .next_multiple_of(64);
+        if part_len >= buffer.len() {
+            self.source.fill_at(offset, buffer);
+            return;
+        }
+
+        thread::scope(|scope| {
+            let mut parts = buffer.chunks_mut(part_len).enumerate();
+            // The calling thread fills the first part itself
+            let first = parts.next();
+            for (index, part) in parts {
+                let source = &self.source;
+                scope.spawn(move || source.fill_at(offset + (index * part_len) as u64, part));
+            }
+            if let Some((_, part)) = first {
+                self.source.fill_at(offset, part);
+            }
+        });
+    }
+}
+
+/// Split `ranges` of (offset, length) into the chunks of at most `chunk_len`
+/// bytes they are written in, in write order
+pub fn range_chunks(
+    ranges: Vec<(u64, u64)>,
+    chunk_len: usize,
+) -> impl Iterator<Item = (u64, usize)> {
+    ranges.into_iter().flat_map(move |(offset, length)| {
+        let end = offset.saturating_add(length);
+        (offset..end)
+            .step_by(chunk_len)
+            .map(move |chunk| (chunk, std::cmp::min(chunk_len as u64, end - chunk) as usize))
+    })
+}
+
+/// Buffer exchanged with a `RandomPool`, aligned so that writes bypassing the
+/// OS cache can be made from it directly
+pub type PoolBuffer = AVec<u8, RuntimeAlign>;
+
+/// Generates random data on a pool of worker threads so that random passes are
+/// not limited by the speed of a single RNG. Even a single worker takes the
+/// generation off the writing thread when there is a spare CPU, so one buffer
+/// is written while the next is filled.
+///
+/// The workers fill buffers from a shared `RandomStream`, each with the chunks
+/// of the ranges the writer will write them to, and keep them in a bounded
+/// channel. Buffers are taken from the workers in strict round-robin order and
+/// handed back for refilling once written, so memory use stays fixed at
+/// `threads * BUFFERS_PER_WORKER` buffers and the writer stays sequential.
+pub struct RandomPool {
+    filled: Vec<Receiver<PoolBuffer>>,
+    empty: Vec<SyncSender<PoolBuffer>>,
+    workers: Vec<JoinHandle<()>>,
+    next: usize,
+}
+
+impl RandomPool {
+    /// Start `threads` workers filling buffers of `chunk_len` bytes for the
+    /// chunks of `ranges`, in the order `range_chunks` yields them. The buffers
+    /// start at a multiple of `alignment` bytes.
+    pub fn new(
+        threads: usize,
+        stream: &RandomStream,
+        ranges: Vec<(u64, u64)>,
+        chunk_len: usize,
+        alignment: usize,
+    ) -> Self {
+        let threads = threads.max(1);
+        let mut filled = Vec::with_capacity(threads);
+        let mut empty = Vec::with_capacity(threads);
+        let mut workers = Vec::with_capacity(threads);
+
+        for worker in 0..threads {
+            let (filled_tx, filled_rx) = sync_channel::<PoolBuffer>(BUFFERS_PER_WORKER);
+            let (empty_tx, empty_rx) = sync_channel::<PoolBuffer>(BUFFERS_PER_WORKER);
+            for _ in 0..BUFFERS_PER_WORKER {
+                empty_tx
+                    .send(avec_rt!([alignment]| 0u8; chunk_len))
+                    .expect("worker channel has room for its initial buffers");
+            }
+
+            let stream = stream.clone();
+            let chunks = range_chunks(ranges.clone(), chunk_len)
+                .skip(worker)
+                .step_by(threads);
+            workers.push(thread::spawn(move || {
+                // Stops after its last chunk, or once the pool is dropped and
+                // either channel disconnects
+                for (offset, len) in chunks {
+                    let Ok(mut buffer) = empty_rx.recv() else {
+                        break;
+                    };
+                    stream.fill(offset, &mut buffer[..len]);
+                    if filled_tx.send(buffer).is_err() {
+                        break;
+                    }
+                }
+            }));
+
+            filled.push(filled_rx);
+            empty.push(empty_tx);
+        }
+
+        RandomPool {
+            filled,
+            empty,
+            workers,
+            next: 0,
+        }
+    }
+
+    /// Replace `buffer` with the buffer filled for the next chunk, returning the
+    /// old one to its worker for refilling. Both buffers must have the pool's size
+    /// and alignment.
+    pub fn exchange(&mut self, buffer: &mut PoolBuffer) {
+        let worker = self.next;
+        let mut fresh = self.filled[worker]
+            .recv()
+            .expect("random generator thread exited unexpectedly");
+        std::mem::swap(buffer, &mut fresh);
+        // A worker that has finished its chunks no longer takes buffers back
+        let _ = self.empty[worker].send(fresh);
+        self.next = (worker + 1) % self.filled.len();
+    }
+}
+
+impl Drop for RandomPool {
+    fn drop(&mut self) {
+        // Disconnect both channels so blocked workers wake up and exit
+        self.filled.clear();
+        self.empty.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_hands_out_fresh_buffers() {
+        // Throughput is compared by `cargo bench --bench random_fill`
+        const BUFFER_SIZE: usize = 1024 * 1024;
+        const BUFFERS: usize = 16;
+
+        const ALIGNMENT: usize = 4096;
+
+        let mut buffer = avec_rt!([ALIGNMENT]| 0u8; BUFFER_SIZE);
+        let mut pool = RandomPool::new(
+            4,
+            &RandomStream::new(RngKind::Chacha, 1),
+            vec![(0, (BUFFERS * BUFFER_SIZE) as u64)],
+            BUFFER_SIZE,
+            ALIGNMENT,
+        );
+        let mut previous = [0u8; 64];
+        for _ in 0..BUFFERS {
+            pool.exchange(&mut buffer);
+            assert_eq!(buffer.len(), BUFFER_SIZE);
+            assert_eq!(buffer.as_ptr() as usize % ALIGNMENT, 0);
+            assert_ne!(buffer[..64], previous, "pool handed out a repeated buffer");
+            previous.copy_from_slice(&buffer[..64]);
+        }
+    }
+
+    /// Streams of the reproducible generators under fixed keys
+    fn keyed_streams(seed: u8, threads: usize) -> [RandomStream; 2] {
+        [
+            RandomStream::with_source(Arc::new(ChaChaSource { key: [seed; 32] }), threads),
+            RandomStream::with_source(
+                Arc::new(AesCtrSource {
+                    key: [seed; 16],
+                    iv: [seed, 1, 2, 3, 4, 5, 6, 7, 0, 0, 0, 0, 0, 0, 0, 0],
+                }),
+                threads,
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_stream_depends_only_on_offset() {
+        const LEN: usize = 1024 * 1024;
+        for (index, stream) in keyed_streams(7, 1).into_iter().enumerate() {
+            assert!(stream.reproducible());
+            let mut whole = vec![0u8; LEN];
+            stream.fill(0, &mut whole);
+
+            // Split across threads, including a last part shorter than the others
+            let mut split = vec![0u8; LEN - 100];
+            keyed_streams(7, 6)[index].fill(0, &mut split);
+            assert_eq!(split, whole[..LEN - 100]);
+
+            // Unaligned offsets start inside a keystream word or block
+            for offset in [1usize, 2, 3, 5, 17, 4099, 65_537] {
+                let mut part = vec![0u8; 70_001];
+                keyed_streams(7, 3)[index].fill(offset as u64, &mut part);
+                assert_eq!(
+                    part,
+                    whole[offset..offset + part.len()],
+                    "offset {}",
+                    offset
+                );
+            }
+
+            // Pool workers fill each chunk with the data at its offset
+            let ranges = vec![(4096, 300_000), (600_000, 400_000)];
+            let mut pool = RandomPool::new(3, &stream, ranges.clone(), 128 * 1024, 1);
+            let mut buffer = avec_rt!([1]| 0u8; 128 * 1024);
+            for (offset, len) in range_chunks(ranges, 128 * 1024) {
+                pool.exchange(&mut buffer);
+                let offset = offset as usize;
+                assert_eq!(buffer[..len], whole[offset..offset + len]);
+            }
+
+            let mut other = vec![0u8; 4096];
+            keyed_streams(8, 1)[index].fill(0, &mut other);
+            assert_ne!(other, whole[..4096]);
+        }
+    }
+
+    #[test]
+    fn test_rng_fill() {
+        // Rates are compared by `cargo bench --bench random_fill`
+        let mut buffer = vec![0u8; 64 * 1024];
+        for rng in [RngKind::Os, RngKind::Chacha, RngKind::AesCtr, RngKind::Fast] {
+            RandomStream::new(rng, 1).fill(0, &mut buffer);
+            assert!(
+                buffer.iter().filter(|&&byte| byte == 0).count() < buffer.len() / 128,
+                "{} left the buffer mostly zero",
+                rng.name()
+            );
+            buffer.fill(0);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Lifting the Host Protected Area of ATA disks for `--reset-hpa` on Linux
+
+use anyhow::{Context, Result};
+use std::{
+    fs::File,
+    os::unix::{fs::MetadataExt, io::AsRawFd},
+    path::Path,
+};
+
+use crate::{
+    ata::{return_lba, send, word, AtaCommand},
+    platform::linux::{logical_block_size, sysfs_block_name},
+    reports::HiddenArea,
+};
+
+const ATA_IDENTIFY_DEVICE: u8 = 0xec;
+const ATA_DEVICE_CONFIGURATION: u8 = 0xb1;
+const DCO_IDENTIFY: u16 = 0xc2;
+const ATA_READ_NATIVE_MAX_ADDRESS_EXT: u8 = 0x27;
+const ATA_SET_MAX_ADDRESS_EXT: u8 = 0x37;
+
+impl HiddenArea {
+    fn new(
+        sector_size: u32,
+        visible_sectors: u64,
+        native_sectors: u64,
+        dco_sectors: Option<u64>,
+    ) -> Self {
+        HiddenArea {
+            sector_size,
+            visible_sectors,
+            native_sectors,
+            hpa_bytes: native_sectors.saturating_sub(visible_sectors) * u64::from(sector_size),
+            dco_sectors,
+            dco_bytes: dco_sectors.map(|dco_sectors| {
+                dco_sectors.saturating_sub(native_sectors) * u64::from(sector_size)
+            }),
+        }
+    }
+}
+
+/// Number of user addressable sectors in an IDENTIFY DEVICE response: the
+/// 48-bit count in words 100-103 when supported, else the 28-bit one in words 60-61
+fn identify_sectors(identify: &[u8; 512]) -> u64 {
+    if word(identify, 83) & (1 << 10) != 0 {
+        (100..104).fold(0, |sectors, index| {
+            sectors | word(identify, index) << (16 * (index - 100))
+        })
+    } else {
+        word(identify, 60) | word(identify, 61) << 16
+    }
+}
+
+/// Whether IDENTIFY DEVICE reports support for the DCO feature set (word 83 bit 11)
+fn identify_supports_dco(identify: &[u8; 512]) -> bool {
+    word(identify, 83) & (1 << 11) != 0
+}
+
+/// Maximum LBA allowed by the DCO, from words 3-6 of a DEVICE CONFIGURATION IDENTIFY response
+fn dco_max_lba(dco: &[u8; 512]) -> u64 {
+    (3..7).fold(0, |lba, index| lba | word(dco, index) << (16 * (index - 3)))
+}
+
+/// Lift the Host Protected Area of the ATA disk `path` until the next power
+/// cycle, so the whole native capacity gets wiped, and report the HPA and DCO
+/// sizes. The kernel is asked to rescan the disk so that the size of the open
+/// `file` reflects the new capacity.
+pub fn reset_hidden_area(path: &Path, file: &File) -> Result<HiddenArea> {
+    let name = file
+        .metadata()
+        .ok()
+        .and_then(|metadata| sysfs_block_name(metadata.rdev()))
+        .with_context(|| format!("{} is not a block device", path.display()))?;
+    if Path::new(&format!("/sys/class/block/{}/partition", name)).exists() {
+        anyhow::bail!(
+            "A Host Protected Area belongs to a whole disk, but {} is a partition",
+            path.display()
+        );
+    }
+
+    let sector_size = logical_block_size(file.as_raw_fd())
+        .with_context(|| format!("Failed to get the sector size of {}", path.display()))?;
+
+    let mut identify = [0u8; 512];
+    send(
+        file,
+        &AtaCommand {
+            command: ATA_IDENTIFY_DEVICE,
+            features: 0,
+            lba: 0,
+            extend: false,
+            data_in: true,
+        },
+        &mut identify,
+    )
+    .with_context(|| {
+        format!(
+            "{} did not answer IDENTIFY DEVICE; is it an ATA drive?",
+            path.display()
+        )
+    })?;
+    let visible_sectors = identify_sectors(&identify);
+
+    // DCO IDENTIFY must come before READ NATIVE MAX, which must immediately precede SET MAX
+    let dco_sectors = if identify_supports_dco(&identify) {
+        let mut dco = [0u8; 512];
+        send(
+            file,
+            &AtaCommand {
+                command: ATA_DEVICE_CONFIGURATION,
+                features: DCO_IDENTIFY,
+                lba: 0,
+                extend: false,
+                data_in: true,
+            },
+            &mut dco,
+        )
+        .ok()
+        .map(|_| dco_max_lba(&dco) + 1)
+    } else {
+        None
+    };
+
+    let mut no_data = [0u8; 512];
+    let sense = send(
+        file,
+        &AtaCommand {
+            command: ATA_READ_NATIVE_MAX_ADDRESS_EXT,
+            features: 0,
+            lba: 0,
+            extend: true,
+            data_in: false,
+        },
+        &mut no_data,
+    )
+    .with_context(|| {
+        format!(
+            "Failed to read the native max address of {}",
+            path.display()
+        )
+    })?;
+    let native_max_lba = return_lba(&sense)
+        .with_context(|| format!("{} did not return the native max address", path.display()))?;
+    let native_sectors = native_max_lba + 1;
+
+    if native_sectors > visible_sectors {
+        // Feature bit 0 (VV) clear: the new max address lasts until the next power cycle
+        send(
+            file,
+            &AtaCommand {
+                command: ATA_SET_MAX_ADDRESS_EXT,
+                features: 0,
+                lba: native_max_lba,
+                extend: true,
+                data_in: false,
+            },
+            &mut no_data,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to lift the Host Protected Area of {}",
+                path.display()
+            )
+        })?;
+
+        // The kernel caches the capacity until the disk is rescanned
+        std::fs::write(format!("/sys/class/block/{}/device/rescan", name), "1").with_context(
+            || format!("Failed to rescan {} after lifting its HPA", path.display()),
+        )?;
+    }
+
+    Ok(HiddenArea::new(
+        sector_size,
+        visible_sectors,
+        native_sectors,
+        dco_sectors,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hidden_area_from_ata_responses() {
+        // 48-bit addressing with 1000 user addressable sectors
+        let mut identify = [0u8; 512];
+        identify[166..168].copy_from_slice(&((1u16 << 10) | (1 << 11)).to_le_bytes());
+        identify[200..202].copy_from_slice(&1000u16.to_le_bytes());
+        assert_eq!(identify_sectors(&identify), 1000);
+        assert!(identify_supports_dco(&identify));
+
+        let mut dco = [0u8; 512];
+        dco[6..8].copy_from_slice(&0x2000u16.to_le_bytes());
+        dco[8..10].copy_from_slice(&0x0001u16.to_le_bytes());
+        assert_eq!(dco_max_lba(&dco), 0x1_2000);
+
+        let area = HiddenArea::new(512, 1000, 1200, Some(1500));
+        assert_eq!(area.hpa_bytes, 200 * 512);
+        assert_eq!(area.dco_bytes, Some(300 * 512));
+    }
+}
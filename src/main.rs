@@ -1,96 +1,487 @@
 use anyhow::{Context, Result};
 use clap::Parser;
+use std::time::{Duration, Instant};
 
 mod algorithms;
 mod args;
+#[cfg(target_os = "linux")]
+mod ata;
+mod batch;
+#[cfg(windows)]
+mod bitlocker;
+mod cancel;
 mod demo;
 mod drives;
+mod entropy;
+mod freespace;
+mod generator;
+#[cfg(target_os = "linux")]
+mod hpa;
+mod pattern;
 mod platform;
+mod power;
+mod priority;
 mod progress;
+mod remove;
+mod reports;
+#[cfg(target_os = "linux")]
+mod smart;
+#[cfg(windows)]
+mod streams;
 mod system;
+mod targets;
+mod throttle;
 mod ui;
+#[cfg(target_os = "linux")]
+mod uring;
+mod verify;
 mod wipe;
 
-use args::Args;
-use demo::create_demo_file;
-use drives::list_drives;
+use args::{Args, SyncMode, DEFAULT_DEMO_SIZE, DEFAULT_PASSES};
+use batch::{
+    append_ledger_record, exit_code, report_results, wipe_target, wipe_targets, write_run_summary,
+    RunSummary, TargetResult,
+};
+use demo::{check_demo_space, create_demo_file};
+use drives::{list_drives, DriveFilter, DriveOutputFormat};
+use freespace::wipe_free_space;
+use power::SleepInhibitor;
+use priority::PriorityMode;
 use system::{display_system_info, get_system_info};
-use ui::confirm_wipe;
-use wipe::WipeContext;
+use targets::{expand_targets, matched_files, TargetFilter};
+use ui::{check_system_targets, confirm_wipe};
+use wipe::WipeOptions;
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    progress::set_pretty_json(args.json_pretty);
+
+    let start_time = Instant::now();
+    let mut results = Vec::new();
+    let outcome = run(&args, &mut results);
+
+    // Written whatever the outcome, so that callers always get a summary file
+    if args.output_summary.is_some() || args.append_certificate.is_some() {
+        let summary = RunSummary::new(
+            &wipe_options(&args),
+            results,
+            outcome.as_ref().copied().map_err(|e| format!("{:#}", e)),
+            start_time.elapsed().as_secs_f64(),
+        );
+        let mut written = Ok(());
+        if let Some(summary_path) = &args.output_summary {
+            written = write_run_summary(summary_path, &summary);
+        }
+        if let Some(ledger_path) = &args.append_certificate {
+            written = written.and(append_ledger_record(ledger_path, &summary));
+        }
+        if let Err(e) = written {
+            if outcome.is_err() {
+                eprintln!("{:#}", e);
+            } else {
+                return Err(e);
+            }
+        }
+    }
+
+    match outcome? {
+        0 => Ok(()),
+        code => std::process::exit(code),
+    }
+}
+
+/// Wipe settings selected on the command line
+fn wipe_options(args: &Args) -> WipeOptions {
+    WipeOptions {
+        algorithm: args.algorithm.clone(),
+        passes: usize::from(args.passes.unwrap_or(DEFAULT_PASSES)),
+        passes_spec: args.passes_spec.clone(),
+        pattern_name: args.pattern_name.clone(),
+        buffer_size_bytes: args.buffer_size,
+        max_buffer_memory_percent: args.max_buffer_memory,
+        json_mode: args.json,
+        sync_mode: if args.fast {
+            Some(SyncMode::None)
+        } else {
+            args.sync_mode
+        },
+        sync_interval_mb: args.sync_interval,
+        no_sync: args.no_sync,
+        skip_errors: args.skip_errors,
+        jobs: 1,
+        retries: args.retries,
+        max_rate: args.max_rate,
+        remove: args.remove,
+        sparse_aware: args.sparse_aware,
+        fill_to_size: args.fill_to_size,
+        preserve_head: args.preserve_head,
+        preserve_tail: args.preserve_tail,
+        entropy_check: args.entropy_check,
+        last_pass_zero: args.last_pass_zero,
+        threads: args.threads,
+        rng_threads: usize::from(args.rng_threads),
+        pipeline: args.pipeline,
+        rng: args.rng,
+        #[cfg(windows)]
+        skip_ads: args.skip_ads,
+        #[cfg(windows)]
+        force_dismount: args.force_dismount,
+        #[cfg(windows)]
+        bitlocker_metadata_only: args.bitlocker_metadata_only,
+        #[cfg(target_os = "linux")]
+        reset_hpa: args.reset_hpa,
+        #[cfg(target_os = "linux")]
+        direct_io: args.direct_io,
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        no_cache: args.no_cache,
+        #[cfg(target_os = "linux")]
+        io_backend: args.io_backend,
+        #[cfg(target_os = "linux")]
+        queue_depth: usize::from(args.queue_depth),
+        #[cfg(target_os = "linux")]
+        smart: args.smart,
+        sleep_inhibited: false,
+        priority: PriorityMode::Normal,
+        hash_final_pass: args.hash_final_pass,
+        verify_each_pass: args.verify_each_pass,
+        verify_pattern: args.verify_pattern.clone(),
+        progress_interval: args.progress_interval.map(Duration::from_millis),
+    }
+}
+
+/// Run the command, collecting the result of every wiped target, and return
+/// the exit code of a wipe in which some targets failed
+fn run(args: &Args, results: &mut Vec<TargetResult>) -> Result<i32> {
+    if let Some(version) = args.protocol_version {
+        ui::check_protocol_version(version, args.json)?;
+    }
 
     // Handle list drives command
     if args.list_drives {
-        return list_drives(args.json);
+        let format = if args.json {
+            DriveOutputFormat::Json
+        } else if args.csv {
+            DriveOutputFormat::Csv
+        } else {
+            DriveOutputFormat::Human
+        };
+        let filter = DriveFilter {
+            types: args
+                .drive_type
+                .iter()
+                .map(|drive_type| drive_type.as_str().to_string())
+                .collect(),
+            min_size_gb: args.min_size,
+            exclude_system: args.exclude_system,
+            removable_only: args.removable_only,
+        };
+        return list_drives(format, &filter).map(|_| 0);
     }
 
     // Handle system info command
     if args.system_info {
         let system_info = get_system_info().context("Failed to gather system information")?;
-        return display_system_info(&system_info, args.json);
+        return display_system_info(&system_info, args.json).map(|_| 0);
+    }
+
+    if args.list_algorithms {
+        let custom_passes = usize::from(args.passes.unwrap_or(DEFAULT_PASSES));
+        return algorithms::list_algorithms(custom_passes, args.json).map(|_| 0);
+    }
+
+    if args.show_passes {
+        args.validate()?;
+        let options = wipe_options(args);
+        return algorithms::show_pass_plan(
+            &options.algorithm_name(),
+            &options.pass_plan(),
+            args.json,
+        )
+        .map(|_| 0);
+    }
+
+    // Handle the algorithm table self-test
+    if args.self_test {
+        let checks = algorithms::self_test(usize::from(args.passes.unwrap_or(DEFAULT_PASSES)));
+        for check in &checks {
+            if check.passed() {
+                println!("PASS {:?} ({} passes)", check.algorithm, check.passes);
+            } else {
+                println!("FAIL {:?}: {}", check.algorithm, check.failures.join("; "));
+            }
+        }
+        return Ok(if checks.iter().all(|check| check.passed()) {
+            0
+        } else {
+            1
+        });
     }
 
+    args.validate()?;
+    let mut wipe_options = wipe_options(args);
+
+    // On Linux the priorities belong to a thread and are inherited by the threads
+    // it starts, so they are lowered before the cancel watcher or any writer starts
+    if args.nice {
+        wipe_options.priority = priority::lower_priority()?;
+    } else if let Some(class) = args.io_priority {
+        match priority::set_io_priority(class)? {
+            Some(mode) => wipe_options.priority = mode,
+            None => {
+                let message =
+                    "--io-priority is only supported on Linux; running at the normal priority"
+                        .to_string();
+                if args.json {
+                    let _ = progress::emit_event(&progress::ProgressEvent::Info { message });
+                } else {
+                    println!("Warning: {}", message);
+                }
+            }
+        }
+    }
+
+    // Keep the machine awake until the wipe ends; the guard releases the request on drop
+    let inhibit_sleep = |reason: String| {
+        #[cfg(any(windows, target_os = "linux"))]
+        if args.allow_sleep {
+            return None;
+        }
+        SleepInhibitor::acquire(&reason)
+    };
+
+    // Handle free space wipe of a mounted filesystem
+    if let Some(mount_point) = &args.free_space {
+        if !mount_point.is_dir() {
+            anyhow::bail!(
+                "Free space target must be a directory: {}",
+                mount_point.display()
+            );
+        }
+        cancel::install_handler(args.cancel_file.as_deref())?;
+        let sleep_inhibitor =
+            inhibit_sleep(format!("Wiping free space on {}", mount_point.display()));
+        wipe_options.sleep_inhibited = sleep_inhibitor.is_some();
+        return wipe_free_space(mount_point, &wipe_options, args.reserve_mb).map(|_| 0);
+    }
+
+    // Disks named by serial or /dev/disk/ link are wiped through their current node
+    #[cfg(target_os = "linux")]
+    let target_paths = targets::resolve_disk_targets(&args.target, &args.by_id)?;
+    // Drives typed as `C:` or `2` are wiped through their device paths; with
+    // --recursive a drive root names the files beneath it instead
+    #[cfg(windows)]
+    let target_paths: Vec<_> = if args.recursive {
+        args.target.clone()
+    } else {
+        args.target
+            .iter()
+            .map(|path| platform::windows::normalize_device_path(path))
+            .collect()
+    };
+    #[cfg(not(any(target_os = "linux", windows)))]
+    let target_paths = args.target.clone();
+
     // Validate arguments for wiping operations
-    if !args.demo && args.target.is_none() {
+    if !args.demo && target_paths.is_empty() {
         anyhow::bail!(
             "Target file must be specified when not in demo mode. Use --target <PATH> or --demo"
         );
     }
 
-    let target_path = if args.demo {
+    let targets = if args.demo {
+        let demo_size = args.demo_size.unwrap_or(DEFAULT_DEMO_SIZE);
         let demo_path =
             std::env::temp_dir().join(format!("secure_wipe_demo_{}.img", std::process::id()));
-        create_demo_file(&demo_path, args.demo_size, args.json)?;
-        demo_path
+        if !check_demo_space(
+            &demo_path,
+            demo_size,
+            args.force,
+            args.assume_yes,
+            args.json,
+        )? {
+            println!("Operation cancelled by user");
+            return Ok(0);
+        }
+        create_demo_file(
+            &demo_path,
+            demo_size,
+            &args.demo_content.clone().unwrap_or_default(),
+            wipe_options.pass_pattern(1).0,
+            args.demo_sparse,
+            args.json,
+        )?;
+        vec![demo_path]
     } else {
-        args.target.clone().unwrap() // Safe to unwrap because we validated above
+        let filter = TargetFilter {
+            older_than: args.older_than,
+            newer_than: args.newer_than,
+        };
+        let targets = expand_targets(&target_paths, args.recursive, &filter)?;
+        if targets.is_empty() {
+            anyhow::bail!("No files matched the given targets and filters");
+        }
+        targets
     };
 
-    // Check if target is a block device (platform-specific)
-    let is_block_device = {
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::FileTypeExt;
-            match std::fs::metadata(&target_path) {
-                Ok(meta) => meta.file_type().is_block_device(),
-                Err(_) => false,
-            }
+    if args.dry_run {
+        return ui::print_dry_run(&matched_files(&targets), args.json).map(|_| 0);
+    }
+
+    let demo_file = args.demo.then(|| targets[0].clone());
+
+    // Wipe the demo file through a loop device to exercise the block device paths
+    #[cfg(target_os = "linux")]
+    let loop_device = if args.demo_loop {
+        Some(demo::LoopDevice::attach(&targets[0], None, args.json)?)
+    } else {
+        None
+    };
+    #[cfg(target_os = "linux")]
+    let targets = match &loop_device {
+        Some(loop_device) => vec![loop_device.path().to_path_buf()],
+        None => targets,
+    };
+
+    // The buffered /dev/diskN node is far slower than the raw /dev/rdiskN one
+    #[cfg(target_os = "macos")]
+    let targets: Vec<_> = if args.auto_raw {
+        targets
+            .into_iter()
+            .map(|target| match platform::macos::raw_disk_path(&target) {
+                Some(raw_path) => std::path::PathBuf::from(raw_path),
+                None => target,
+            })
+            .collect()
+    } else {
+        targets
+    };
+
+    for target_path in &targets {
+        let is_block_device = platform::is_block_device(target_path);
+        if !target_path.exists() && !args.demo && !is_block_device {
+            anyhow::bail!(
+                "Target file or device does not exist: {}",
+                target_path.display()
+            );
+        }
+        if args.remove && is_block_device {
+            anyhow::bail!(
+                "--remove only applies to files, but {} is a block device",
+                target_path.display()
+            );
         }
         #[cfg(windows)]
+        if args.bitlocker_metadata_only
+            && !matches!(
+                platform::windows::get_device_type(target_path),
+                platform::windows::DeviceType::LogicalDrive | platform::windows::DeviceType::Volume
+            )
         {
-            platform::windows::is_windows_device_path(&target_path)
+            anyhow::bail!(
+                "--bitlocker-metadata-only only applies to volumes, but {} is not one",
+                target_path.display()
+            );
         }
-        #[cfg(not(any(unix, windows)))]
-        {
-            false
+        #[cfg(target_os = "linux")]
+        if args.reset_hpa && !is_block_device {
+            anyhow::bail!(
+                "--reset-hpa only applies to disks, but {} is a file",
+                target_path.display()
+            );
         }
-    };
+        #[cfg(target_os = "linux")]
+        if args.direct_io && !is_block_device {
+            anyhow::bail!(
+                "--direct-io only applies to block devices, but {} is a file",
+                target_path.display()
+            );
+        }
+        #[cfg(target_os = "linux")]
+        if args.smart && !is_block_device {
+            anyhow::bail!(
+                "--smart only applies to disks, but {} is a file",
+                target_path.display()
+            );
+        }
+        if args.sparse_aware && is_block_device {
+            anyhow::bail!(
+                "--sparse-aware only applies to files, but {} is a block device",
+                target_path.display()
+            );
+        }
+        if let Some(fill_to_size) = args.fill_to_size {
+            if is_block_device {
+                anyhow::bail!(
+                    "--fill-to-size only applies to files, but {} is a block device",
+                    target_path.display()
+                );
+            }
+            let current_size = std::fs::metadata(target_path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            if fill_to_size < current_size {
+                anyhow::bail!(
+                    "--fill-to-size {} is smaller than {} ({} bytes)",
+                    fill_to_size,
+                    target_path.display(),
+                    current_size
+                );
+            }
+        }
+    }
 
-    if !target_path.exists() && !args.demo && !is_block_device {
-        anyhow::bail!(
-            "Target file or device does not exist: {}",
-            target_path.display()
-        );
+    ui::check_privileges(&targets, args.json)?;
+
+    // Checking a pattern only reads the targets, so there is nothing to confirm
+    if !args.force && args.verify_pattern.is_none() {
+        check_system_targets(&targets, args.allow_system)?;
+
+        if !args.assume_yes && !confirm_wipe(&targets, args.demo, args.confirm_code)? {
+            println!("Operation cancelled by user");
+            return Ok(0);
+        }
     }
 
-    if !args.force && !confirm_wipe(&target_path, args.demo)? {
-        println!("Operation cancelled by user");
-        return Ok(());
+    // A mounted volume keeps the disk busy, so opening it for writing would fail
+    #[cfg(target_os = "macos")]
+    if args.unmount {
+        for target_path in targets
+            .iter()
+            .filter(|path| platform::is_block_device(path))
+        {
+            platform::macos::unmount_disk(target_path).with_context(|| {
+                format!("Failed to unmount the volumes of {}", target_path.display())
+            })?;
+            if !args.json {
+                println!("Unmounted the volumes of {}", target_path.display());
+            }
+        }
     }
 
-    let mut wipe_context = WipeContext::new(
-        &target_path,
-        args.algorithm,
-        args.passes,
-        args.buffer_size,
-        args.json,
-        is_block_device,
-        args.fast,
-    )?;
+    cancel::install_handler(args.cancel_file.as_deref())?;
+    let sleep_inhibitor = inhibit_sleep(format!(
+        "Wiping {}",
+        targets
+            .iter()
+            .map(|target| target.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    wipe_options.sleep_inhibited = sleep_inhibitor.is_some();
 
-    wipe_context.wipe()?;
+    if let [target_path] = targets.as_slice() {
+        let (result, outcome) = wipe_target(target_path, &wipe_options, None);
+        results.push(result);
+        outcome?;
+    } else {
+        *results = wipe_targets(&targets, &wipe_options, args.jobs);
+        report_results(results, args.json);
+        let code = exit_code(results);
+        if code != 0 {
+            return Ok(code);
+        }
+    }
+    drop(sleep_inhibitor);
 
     if args.verify {
         println!("\nVerifying wipe...");
@@ -98,43 +489,292 @@ fn main() -> Result<()> {
         println!("Verification not yet implemented");
     }
 
-    if args.demo {
-        std::fs::remove_file(&target_path).with_context(|| "Failed to clean up demo file")?;
+    #[cfg(target_os = "linux")]
+    drop(loop_device);
+
+    // With --remove the demo file has already been deleted
+    if let Some(demo_file) = demo_file.filter(|_| !args.remove) {
+        std::fs::remove_file(&demo_file).with_context(|| "Failed to clean up demo file")?;
         if !args.json {
             println!("Demo file cleaned up");
         }
     }
 
-    Ok(())
+    Ok(0)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::args::WipeAlgorithm;
+    use crate::algorithms::{get_pass_pattern, WipePattern};
+    use crate::args::{DemoContent, WipeAlgorithm};
+    use crate::wipe::WipeContext;
     use tempfile::NamedTempFile;
 
     #[test]
     fn test_demo_file_creation() {
         let temp_file = NamedTempFile::new().unwrap();
-        let result = create_demo_file(temp_file.path(), 1, false);
+        let result = create_demo_file(
+            temp_file.path(),
+            1024 * 1024,
+            &DemoContent::Text,
+            WipePattern::Random,
+            false,
+            false,
+        );
         assert!(result.is_ok());
     }
 
     #[test]
-    fn test_wipe_context_creation() {
+    fn test_demo_size_beyond_free_space() {
+        use crate::demo::check_demo_size_against;
+
+        const FREE: u64 = 100 * 1024 * 1024;
+        let dir = std::env::temp_dir();
+        let check = |size_bytes, force, assume_yes| {
+            check_demo_size_against(&dir, size_bytes, FREE, force, assume_yes, true)
+        };
+        assert!(check(FREE / 2, false, false).unwrap());
+
+        // More than half of the free space needs --yes or --force, and in JSON
+        // mode there is no prompt to ask with
+        let most = FREE / 4 * 3;
+        assert!(check(most, false, false).is_err());
+        assert!(check(most, false, true).unwrap());
+        assert!(check(most, true, false).unwrap());
+
+        // More than is free fits nowhere; --force turns the error into a warning
+        let error = check(FREE + 1, false, true).unwrap_err();
+        assert!(error.to_string().contains("--demo-size"), "{}", error);
+        assert!(check(FREE + 1, true, false).unwrap());
+
+        // The real filesystem query lets a small demo file through
+        let demo_path = dir.join("secure_wipe_demo_space_check.img");
+        assert!(check_demo_space(&demo_path, 1024, false, false, true).unwrap());
+        assert!(!demo_path.exists());
+    }
+
+    #[test]
+    fn test_args_validation() {
+        let parse = |flags: &[&str]| {
+            Args::try_parse_from(std::iter::once("secure-wipe-bin").chain(flags.iter().copied()))
+        };
+        let check = |flags: &[&str]| {
+            let args = parse(flags).map_err(anyhow::Error::from)?;
+            args.validate().map(|_| wipe_options(&args).total_passes())
+        };
+
+        // A target next to --demo would be silently ignored
+        assert!(parse(&["--demo", "--target", "/dev/sdb"]).is_err());
+        assert!(parse(&["--demo", "--retries", "16"]).is_ok());
+        assert!(parse(&["--demo", "--retries", "17"]).is_err());
+        assert!(parse(&["--demo"]).is_ok());
+        // The demo file would be created (and left behind) just to be listed
+        assert!(parse(&["--demo", "--dry-run"]).is_err());
+        // Demo flags only mean something with --demo
+        assert!(check(&["--target", "/tmp/file", "--demo-size", "10M"]).is_err());
+        assert!(check(&["--target", "/tmp/file", "--demo-content", "random"]).is_err());
+        assert!(check(&["--target", "/tmp/file", "--demo-sparse"]).is_err());
+        assert!(check(&["--demo", "--demo-size", "10M", "--demo-sparse"]).is_ok());
+        #[cfg(target_os = "linux")]
+        assert!(check(&["--target", "/tmp/file", "--demo-loop"]).is_err());
+
+        assert_eq!(check(&["--algorithm", "custom"]).unwrap(), 3);
+        assert_eq!(check(&["--algorithm", "custom", "-p", "7"]).unwrap(), 7);
+        assert!(check(&["--algorithm", "custom", "--passes", "0"]).is_err());
+        assert!(check(&["--algorithm", "custom", "--passes", "101"]).is_err());
+        assert_eq!(
+            check(&["--algorithm", "custom", "--passes", "101", "--yes-really"]).unwrap(),
+            101
+        );
+        assert!(check(&["--yes-really"]).is_err());
+
+        // An algorithm with its own pass count would ignore --passes
+        let error = check(&["--algorithm", "dod5220", "--passes", "7"]).unwrap_err();
+        assert!(
+            error.to_string().contains("--algorithm custom"),
+            "{}",
+            error
+        );
+        assert!(check(&["--passes", "3"]).is_err());
+
+        #[cfg(target_os = "linux")]
+        {
+            assert!(check(&["--io-backend", "uring", "--skip-errors"]).is_err());
+            assert!(check(&["--io-backend", "uring"]).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_demo_size_units() {
+        let demo_size = |flags: &[&str]| {
+            Args::try_parse_from(
+                ["secure-wipe-bin", "--demo"]
+                    .into_iter()
+                    .chain(flags.iter().copied()),
+            )
+            .map(|args| args.demo_size.unwrap_or(DEFAULT_DEMO_SIZE))
+        };
+        assert_eq!(demo_size(&[]).unwrap(), 100 * 1024 * 1024);
+        // A plain number is still a number of MB
+        assert_eq!(demo_size(&["--demo-size", "10"]).unwrap(), 10 * 1024 * 1024);
+        assert_eq!(
+            demo_size(&["--demo-size", "500M"]).unwrap(),
+            500 * 1024 * 1024
+        );
+        assert_eq!(demo_size(&["--demo-size", "10G"]).unwrap(), 10 << 30);
+        assert_eq!(demo_size(&["--demo-size", "1t"]).unwrap(), 1 << 40);
+        for invalid in ["0", "-5G", "10X"] {
+            assert!(demo_size(&["--demo-size", invalid]).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_demo_file_content() {
         let temp_file = NamedTempFile::new().unwrap();
-        std::fs::write(temp_file.path(), b"test data").unwrap();
 
-        let result = WipeContext::new(
+        create_demo_file(
             temp_file.path(),
-            WipeAlgorithm::Zero,
-            1,
-            1024,
+            1024 * 1024,
+            &DemoContent::Pattern,
+            get_pass_pattern(&WipeAlgorithm::Dod5220, 1),
             false,
             false,
+        )
+        .unwrap();
+        let data = std::fs::read(temp_file.path()).unwrap();
+        assert_eq!(data.len(), 1024 * 1024);
+        assert!(data.iter().all(|&byte| byte == 0x00));
+
+        create_demo_file(
+            temp_file.path(),
+            1024 * 1024,
+            &DemoContent::Random,
+            WipePattern::Fixed(0x00),
             false,
+            false,
+        )
+        .unwrap();
+        let data = std::fs::read(temp_file.path()).unwrap();
+        assert!(!data.starts_with(b"DEMO DATA"));
+        assert!(data.iter().any(|&byte| byte != data[0]));
+    }
+
+    #[test]
+    fn test_pattern_name_labels() {
+        use std::io::Cursor;
+
+        // A --pattern-name label replaces the pattern with the pass number
+        let mut data = vec![0x5A; 64 * 1024];
+        let args = Args::parse_from(["secure-wipe-bin", "--algorithm", "custom", "--pattern-name"]);
+        let mut context = WipeContext::from_target(
+            Cursor::new(&mut data),
+            "memory",
+            WipeOptions {
+                buffer_size_bytes: Some(64 * 1024),
+                json_mode: true,
+                ..wipe_options(&args)
+            },
+        )
+        .unwrap();
+        context.wipe().unwrap();
+        let patterns: Vec<&str> = context
+            .pass_records()
+            .iter()
+            .map(|r| r.pattern.as_str())
+            .collect();
+        assert_eq!(patterns, ["PASS 1/3", "PASS 2/3", "PASS 3/3"]);
+
+        let args = Args::parse_from(["secure-wipe-bin", "--pattern-name=Custom"]);
+        assert_eq!(wipe_options(&args).pass_pattern(1).1, "Custom 1/1");
+        assert!(Args::try_parse_from(["secure-wipe-bin", "--pattern-name="]).is_err());
+    }
+
+    #[test]
+    fn test_sync_mode_selection() {
+        use crate::wipe::IoMode;
+
+        let options = |flags: &[&str]| {
+            wipe_options(&Args::parse_from(
+                std::iter::once("secure-wipe-bin").chain(flags.iter().copied()),
+            ))
+        };
+        assert_eq!(options(&["--fast"]).sync_mode, Some(SyncMode::None));
+        // --no-sync leaves the sync mode, and so the progress interval, alone
+        let no_sync = options(&["--no-sync"]);
+        assert!(no_sync.no_sync);
+        assert_eq!(no_sync.sync_mode, None);
+        assert!(
+            Args::try_parse_from(["secure-wipe-bin", "--fast", "--sync-mode", "pass"]).is_err()
         );
-        assert!(result.is_ok());
+
+        let defaults = options(&[]);
+        assert_eq!(defaults.sync_mode_for(true), SyncMode::Always);
+        assert_eq!(defaults.sync_mode_for(false), SyncMode::Interval);
+
+        // An explicit mode applies to files too
+        let temp_file = NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), vec![0xAB; 4096]).unwrap();
+        let context =
+            WipeContext::new(temp_file.path(), false, options(&["--sync-mode", "always"])).unwrap();
+        assert_eq!(context.io_mode(), IoMode::Synchronous);
+    }
+
+    #[test]
+    fn test_progress_interval() {
+        let interval = |flags: &[&str]| {
+            wipe_options(&Args::parse_from(
+                std::iter::once("secure-wipe-bin").chain(flags.iter().copied()),
+            ))
+            .progress_interval_or(Duration::from_millis(500))
+        };
+        assert_eq!(interval(&[]), Duration::from_millis(500));
+        assert_eq!(
+            interval(&["--progress-interval", "100"]),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            interval(&["--progress-interval", "5000"]),
+            Duration::from_secs(5)
+        );
+        // Too short an interval is raised to the minimum instead of flooding the frontend
+        assert_eq!(
+            interval(&["--progress-interval", "0"]),
+            crate::wipe::MIN_PROGRESS_INTERVAL
+        );
+    }
+
+    #[test]
+    fn test_buffer_size_is_in_kb() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), vec![0xAB; 4096]).unwrap();
+
+        let buffer_len = |flags: &[&str]| {
+            let args =
+                Args::parse_from(std::iter::once("secure-wipe-bin").chain(flags.iter().copied()));
+            let context = WipeContext::new(temp_file.path(), false, wipe_options(&args)).unwrap();
+            context.buffer_len()
+        };
+        assert_eq!(buffer_len(&["--buffer-size", "4096"]), 4 * 1024 * 1024);
+        // 1024 KB used to mean "unset" and was replaced by the auto-tuned size
+        assert_eq!(buffer_len(&["--buffer-size", "1024"]), 1024 * 1024);
+        assert_eq!(buffer_len(&["--buffer-size", "4M"]), 4 * 1024 * 1024);
+
+        // Without the flag the size comes from the memory heuristic, 4-32 MB for files
+        assert_eq!(Args::parse_from(["secure-wipe-bin"]).buffer_size, None);
+        let auto = buffer_len(&[]);
+        assert!(
+            (4 * 1024 * 1024..=32 * 1024 * 1024).contains(&auto),
+            "{}",
+            auto
+        );
+    }
+
+    #[test]
+    fn test_buffer_size_limits() {
+        for invalid in ["0", "3", "-1", "lots"] {
+            assert!(Args::try_parse_from(["secure-wipe-bin", "--buffer-size", invalid]).is_err());
+        }
+        assert!(Args::try_parse_from(["secure-wipe-bin", "--max-buffer-memory", "0"]).is_err());
     }
 }
@@ -0,0 +1,34 @@
+//! Filling buffers with a repeating byte pattern, kept free of other crate
+//! modules so the benchmarks can include it directly
+
+/// Fill `buffer` with `pattern` repeated from its first byte. Only the first
+/// block, a common multiple of the pattern length and 64 bytes, is written
+/// byte by byte; the rest is copied from the filled prefix in doubling steps,
+/// which keeps every copy in phase with the pattern.
+pub fn fill_repeating(buffer: &mut [u8], pattern: &[u8]) {
+    if let [byte] = pattern {
+        buffer.fill(*byte);
+        return;
+    }
+
+    let block = lcm(pattern.len(), 64).min(buffer.len());
+    for (byte, value) in buffer[..block].iter_mut().zip(pattern.iter().cycle()) {
+        *byte = *value;
+    }
+
+    let mut filled = block;
+    while filled < buffer.len() {
+        let len = filled.min(buffer.len() - filled);
+        buffer.copy_within(..len, filled);
+        filled += len;
+    }
+}
+
+/// Least common multiple of two sizes
+pub(crate) fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
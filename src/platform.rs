@@ -1,33 +1,790 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::pattern::lcm;
+
+/// Check whether a target path refers to a block device (platform-specific)
+pub fn is_block_device(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        match std::fs::metadata(path) {
+            Ok(meta) => {
+                meta.file_type().is_block_device()
+                    || (cfg!(target_os = "macos")
+                        && meta.file_type().is_char_device()
+                        && path.to_string_lossy().starts_with("/dev/rdisk"))
+                    // FreeBSD has no block devices; disks (GEOM providers) are character devices
+                    || (cfg!(target_os = "freebsd") && meta.file_type().is_char_device())
+            }
+            Err(_) => false,
+        }
+    }
+    #[cfg(windows)]
+    {
+        windows::is_windows_device_path(path)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Path to open a target through. Windows volume GUID paths lose their trailing
+/// backslash so that the volume itself is opened rather than its root directory.
+pub fn device_open_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        if windows::is_windows_device_path(path) {
+            return PathBuf::from(windows::trim_volume_path(&path.to_string_lossy()));
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Free space in bytes available to the current user on the filesystem containing `path`
+pub fn free_space_bytes(path: &Path) -> Result<u64> {
+    #[cfg(unix)]
+    {
+        use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        unsafe {
+            let mut stat: libc::statvfs = std::mem::zeroed();
+            if libc::statvfs(c_path.as_ptr(), &mut stat) != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to query free space on {}", path.display()));
+            }
+            Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+        }
+    }
+    #[cfg(windows)]
+    {
+        use winapi::um::{fileapi::GetDiskFreeSpaceExW, winnt::ULARGE_INTEGER};
+
+        let wide_path: Vec<u16> = path
+            .as_os_str()
+            .to_string_lossy()
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+        unsafe {
+            let mut free_to_caller: ULARGE_INTEGER = std::mem::zeroed();
+            if GetDiskFreeSpaceExW(
+                wide_path.as_ptr(),
+                &mut free_to_caller,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            ) == 0
+            {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to query free space on {}", path.display()));
+            }
+            Ok(*free_to_caller.QuadPart())
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        anyhow::bail!(
+            "Free space query is not supported on this platform: {}",
+            path.display()
+        )
+    }
+}
+
+/// Reserve `len` bytes of disk space for `file` and extend it to that length, so
+/// that writing it later cannot run out of space. Filesystems that cannot
+/// reserve space up front (ZFS, for one) only get the file extended.
+pub fn preallocate(file: &File, len: u64) -> std::io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len as libc::off_t) } {
+            0 => return Ok(()),
+            libc::EINVAL | libc::EOPNOTSUPP => {}
+            errno => return Err(std::io::Error::from_raw_os_error(errno)),
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let mut store = libc::fstore_t {
+            fst_flags: libc::F_ALLOCATEALL,
+            fst_posmode: libc::F_PEOFPOSMODE,
+            fst_offset: 0,
+            fst_length: len as libc::off_t,
+            fst_bytesalloc: 0,
+        };
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_PREALLOCATE, &mut store) } == -1 {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() != Some(libc::ENOTSUP) {
+                return Err(error);
+            }
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::AsRawHandle;
+        use winapi::um::{
+            fileapi::{SetFileInformationByHandle, FILE_ALLOCATION_INFO},
+            minwinbase::FileAllocationInfo,
+        };
+
+        unsafe {
+            let mut info: FILE_ALLOCATION_INFO = std::mem::zeroed();
+            *info.AllocationSize.QuadPart_mut() = len as i64;
+            if SetFileInformationByHandle(
+                file.as_raw_handle() as _,
+                FileAllocationInfo,
+                &mut info as *mut FILE_ALLOCATION_INFO as _,
+                std::mem::size_of::<FILE_ALLOCATION_INFO>() as u32,
+            ) == 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+    }
+    file.set_len(len)
+}
+
+/// Take an exclusive lock on a whole file, waiting while another process holds
+/// it. The lock is released when the file is closed.
+pub fn lock_file_exclusive(file: &File) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::AsRawHandle;
+        use winapi::um::{fileapi::LockFileEx, minwinbase::LOCKFILE_EXCLUSIVE_LOCK};
+
+        unsafe {
+            let mut overlapped = std::mem::zeroed();
+            if LockFileEx(
+                file.as_raw_handle() as winapi::um::winnt::HANDLE,
+                LOCKFILE_EXCLUSIVE_LOCK,
+                0,
+                u32::MAX,
+                u32::MAX,
+                &mut overlapped,
+            ) == 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = file;
+        Ok(())
+    }
+}
+
+/// Byte ranges `(offset, length)` of a file that are backed by allocated storage.
+/// Holes in sparse files are left out; platforms that cannot tell report the whole file.
+pub fn allocated_ranges(file: &File, size: u64) -> Result<Vec<(u64, u64)>> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let fd = file.as_raw_fd();
+        let mut ranges = Vec::new();
+        let mut offset = 0u64;
+
+        while offset < size {
+            let data = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+            if data < 0 {
+                let error = std::io::Error::last_os_error();
+                // ENXIO: no data past this offset, the rest of the file is a hole
+                if error.raw_os_error() == Some(libc::ENXIO) {
+                    break;
+                }
+                return Err(error).context("Failed to find allocated data (SEEK_DATA)");
+            }
+
+            let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+            if hole < 0 {
+                return Err(std::io::Error::last_os_error())
+                    .context("Failed to find end of allocated data (SEEK_HOLE)");
+            }
+
+            let end = std::cmp::min(hole as u64, size);
+            if end > data as u64 {
+                ranges.push((data as u64, end - data as u64));
+            }
+            offset = hole as u64;
+        }
+
+        Ok(ranges)
+    }
+    #[cfg(windows)]
+    {
+        windows::query_allocated_ranges(file, size)
+    }
+    #[cfg(not(any(target_os = "linux", windows)))]
+    {
+        let _ = file;
+        Ok(vec![(0, size)])
+    }
+}
+
+/// Check whether a device path holds the running system (root or boot filesystem),
+/// either directly or as the disk containing it
+pub fn is_system_device(path: &Path) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return false;
+        };
+        if !metadata.file_type().is_block_device() {
+            return false;
+        }
+
+        match linux::sysfs_block_name(metadata.rdev()) {
+            Some(name) => linux::system_block_devices().contains(&name),
+            None => false,
+        }
+    }
+    #[cfg(windows)]
+    {
+        windows::is_system_device_path(path)
+    }
+    #[cfg(not(any(target_os = "linux", windows)))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Whether the device behind a path is removable media (USB sticks, card readers).
+/// `None` when the path is not a device or the platform cannot tell.
+pub fn is_removable_device(path: &Path) -> Option<bool> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        let metadata = std::fs::metadata(path).ok()?;
+        if !metadata.file_type().is_block_device() {
+            return None;
+        }
+        linux::is_removable(&linux::sysfs_block_name(metadata.rdev())?)
+    }
+    #[cfg(windows)]
+    {
+        windows::storage_properties(&path.to_string_lossy()).map(|props| props.removable)
+    }
+    #[cfg(not(any(target_os = "linux", windows)))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Whether the process may open raw devices for writing: root (or `CAP_SYS_ADMIN`
+/// on Linux) on Unix, membership of the Administrators group on Windows
+pub fn has_device_privileges() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let is_root = unsafe { libc::geteuid() == 0 };
+        is_root || linux::has_cap_sys_admin()
+    }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        unsafe { libc::geteuid() == 0 }
+    }
+    #[cfg(windows)]
+    {
+        windows::is_elevated()
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        true
+    }
+}
+
+/// Zone model of a block device (`queue/zoned` in Linux sysfs)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZonedModel {
+    /// Not zoned: any sector can be written at any time
+    None,
+    /// Zoned, but the device also accepts random writes (host-aware SMR)
+    HostAware,
+    /// Zones must be written sequentially and reset before being rewritten
+    /// (host-managed SMR, NVMe ZNS)
+    HostManaged,
+}
+
+/// Write limits the request queue of a block device advertises (Linux sysfs)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QueueLimits {
+    /// Largest request the kernel passes to the device in one piece (`max_sectors_kb`)
+    pub max_request_kb: u32,
+    /// Preferred size of large writes, e.g. a RAID stripe (`optimal_io_size`), 0 if not reported
+    pub optimal_io_bytes: u32,
+    /// Smallest unit the device is written in (`logical_block_size`)
+    pub logical_block_size: u32,
+    /// Whether the device is zoned (`zoned`)
+    pub zoned: ZonedModel,
+}
+
+/// Largest write unit taken from the queue limits; an optimal I/O size that
+/// would push the unit past it (some USB bridges report 0xFFFF sectors) is ignored
+const MAX_WRITE_UNIT: usize = 64 * 1024 * 1024;
+
+impl QueueLimits {
+    /// Size write buffers should be a whole multiple of: the least common
+    /// multiple of the largest request, the optimal I/O size and the logical
+    /// block size, so that a unit is both whole requests and whole stripes
+    pub fn write_unit(&self) -> usize {
+        let request = lcm(
+            self.max_request_kb as usize * 1024,
+            self.logical_block_size as usize,
+        );
+        match self.optimal_io_bytes as usize {
+            0 => request,
+            optimal => Some(lcm(request, optimal))
+                .filter(|&unit| unit <= MAX_WRITE_UNIT)
+                .unwrap_or(request),
+        }
+    }
+
+    /// Round a buffer size down to whole write units, never below one, so that
+    /// each write is split into full-size requests and none is left over
+    pub fn align_buffer(&self, buffer_bytes: usize) -> usize {
+        let unit = self.write_unit();
+        std::cmp::max(buffer_bytes / unit, 1) * unit
+    }
+}
+
+/// Serial number from a SCSI Unit Serial Number VPD page (0x80): the page
+/// length is in bytes 2-3 and the space-padded ASCII serial follows from byte 4
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn vpd_unit_serial(page: &[u8]) -> Option<String> {
+    if page.len() < 4 || page[1] != 0x80 {
+        return None;
+    }
+    let len = usize::from(u16::from_be_bytes([page[2], page[3]]));
+    let serial = String::from_utf8_lossy(page.get(4..4 + len)?)
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+        .to_string();
+    (!serial.is_empty()).then_some(serial)
+}
+
+/// Linux-specific helpers built on sysfs
+#[cfg(target_os = "linux")]
+pub mod linux {
+    use anyhow::Result;
+    use std::{
+        collections::HashSet,
+        os::unix::io::RawFd,
+        path::{Path, PathBuf},
+    };
+
+    use super::{vpd_unit_serial, QueueLimits, ZonedModel};
+
+    /// Mount points whose backing devices count as the system drive
+    const SYSTEM_MOUNT_POINTS: &[&str] = &["/", "/boot", "/boot/efi"];
+    /// `_IO(0x12, 104)`: logical block size of a block device
+    const BLKSSZGET: libc::c_ulong = 0x1268;
+
+    /// Logical block size of an open block device, the unit O_DIRECT and ATA
+    /// commands address it in
+    pub fn logical_block_size(fd: RawFd) -> Result<u32> {
+        let mut block_size: libc::c_int = 0;
+        if unsafe { libc::ioctl(fd, BLKSSZGET, &mut block_size) } != 0 || block_size <= 0 {
+            anyhow::bail!(
+                "Failed to get the logical block size: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(block_size as u32)
+    }
+
+    /// Name of the filesystem holding `path` when it keeps old copies of file
+    /// data outside the file itself: in a journal (ext3/ext4, XFS, JFS,
+    /// ReiserFS) or in blocks left behind by copy-on-write or log-structured
+    /// writes (Btrfs, F2FS)
+    pub fn journaling_filesystem(path: &Path) -> Option<&'static str> {
+        use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+        let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return None;
+        }
+
+        // f_type magic numbers from linux/magic.h
+        match stat.f_type as u64 {
+            0xef53 => Some("ext3/ext4"),
+            0x5846_5342 => Some("XFS"),
+            0x3153_464a => Some("JFS"),
+            0x5265_4973 => Some("ReiserFS"),
+            0x9123_683e => Some("Btrfs"),
+            0xf2f5_2010 => Some("F2FS"),
+            _ => None,
+        }
+    }
+
+    /// Kernel name (e.g. `sda1`, `dm-0`) of the block device with the given device number
+    pub fn sysfs_block_name(dev: u64) -> Option<String> {
+        let sys_dir = std::fs::canonicalize(format!(
+            "/sys/dev/block/{}:{}",
+            libc::major(dev),
+            libc::minor(dev)
+        ))
+        .ok()?;
+        Some(sys_dir.file_name()?.to_string_lossy().to_string())
+    }
+
+    /// Write limits of the request queue of the block device with the given device
+    /// number; partitions share the queue of their disk. `None` without sysfs.
+    pub fn queue_limits(dev: u64) -> Option<QueueLimits> {
+        let mut sys_dir = std::fs::canonicalize(format!(
+            "/sys/dev/block/{}:{}",
+            libc::major(dev),
+            libc::minor(dev)
+        ))
+        .ok()?;
+        if sys_dir.join("partition").exists() {
+            sys_dir = sys_dir.parent()?.to_path_buf();
+        }
+        read_queue_limits(&sys_dir.join("queue"))
+    }
+
+    pub(super) fn read_queue_limits(queue_dir: &Path) -> Option<QueueLimits> {
+        let read_attr = |attr: &str| -> Option<u32> {
+            std::fs::read_to_string(queue_dir.join(attr))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+        };
+
+        // Kernels without zoned block device support have no `zoned` attribute
+        let zoned = match std::fs::read_to_string(queue_dir.join("zoned")) {
+            Ok(model) if model.trim() == "host-managed" => ZonedModel::HostManaged,
+            Ok(model) if model.trim() == "host-aware" => ZonedModel::HostAware,
+            _ => ZonedModel::None,
+        };
+
+        let limits = QueueLimits {
+            max_request_kb: read_attr("max_sectors_kb")?,
+            optimal_io_bytes: read_attr("optimal_io_size").unwrap_or(0),
+            logical_block_size: read_attr("logical_block_size")?,
+            zoned,
+        };
+        (limits.max_request_kb > 0 && limits.logical_block_size > 0).then_some(limits)
+    }
+
+    /// Kernel names of every block device the running system lives on: the devices
+    /// backing the system mount points, their parent disks, and the devices
+    /// underneath any device-mapper layers (LVM, LUKS)
+    pub fn system_block_devices() -> HashSet<String> {
+        use std::os::unix::fs::MetadataExt;
+
+        let mut devices = HashSet::new();
+        for mount_point in SYSTEM_MOUNT_POINTS {
+            if let Some(name) = std::fs::metadata(mount_point)
+                .ok()
+                .and_then(|metadata| sysfs_block_name(metadata.dev()))
+            {
+                collect_underlying_devices(&name, &mut devices);
+            }
+        }
+        devices
+    }
+
+    /// Whether `CAP_SYS_ADMIN` is in the effective capability set of this process
+    pub fn has_cap_sys_admin() -> bool {
+        const CAP_SYS_ADMIN: u32 = 21;
+
+        let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+            return false;
+        };
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("CapEff:"))
+            .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+            .is_some_and(|caps| caps & (1 << CAP_SYS_ADMIN) != 0)
+    }
+
+    /// Read the `removable` attribute of a block device; partitions inherit it from their disk
+    pub fn is_removable(name: &str) -> Option<bool> {
+        let mut sys_dir = std::fs::canonicalize(Path::new("/sys/class/block").join(name)).ok()?;
+        if sys_dir.join("partition").exists() {
+            sys_dir = sys_dir.parent()?.to_path_buf();
+        }
+
+        let removable = std::fs::read_to_string(sys_dir.join("removable")).ok()?;
+        Some(removable.trim() == "1")
+    }
+
+    /// Serial number of the disk at `sys_dir` in sysfs: the `serial` attribute
+    /// of NVMe, virtio and USB disks, else the unit serial number VPD page
+    /// that SCSI and SATA disks report
+    pub fn disk_serial(sys_dir: &Path) -> Option<String> {
+        let read_attr = |attr: &str| {
+            std::fs::read_to_string(sys_dir.join(attr))
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+
+        read_attr("device/serial")
+            .or_else(|| read_attr("serial"))
+            .or_else(|| {
+                std::fs::read(sys_dir.join("device/vpd_pg80"))
+                    .ok()
+                    .and_then(|page| vpd_unit_serial(&page))
+            })
+    }
+
+    /// Device node of the one whole disk whose serial number is `serial`, so that
+    /// a disk can be targeted regardless of the name it got at boot
+    pub fn disk_by_serial(serial: &str) -> Result<PathBuf> {
+        let mut matches = Vec::new();
+        for entry in std::fs::read_dir("/sys/block")?.flatten() {
+            let Ok(sys_dir) = std::fs::canonicalize(entry.path()) else {
+                continue;
+            };
+            if disk_serial(&sys_dir).is_some_and(|disk_serial| disk_serial == serial.trim()) {
+                matches.push(Path::new("/dev").join(entry.file_name()));
+            }
+        }
+
+        match matches.as_slice() {
+            [] => anyhow::bail!(
+                "No disk with serial {} found; run --list-drives to see the attached disks",
+                serial
+            ),
+            [disk] => Ok(disk.clone()),
+            _ => anyhow::bail!(
+                "Serial {} matches several disks ({}); target the device node instead",
+                serial,
+                matches
+                    .iter()
+                    .map(|disk| disk.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    fn collect_underlying_devices(name: &str, devices: &mut HashSet<String>) {
+        if !devices.insert(name.to_string()) {
+            return;
+        }
+
+        let Ok(sys_dir) = std::fs::canonicalize(Path::new("/sys/class/block").join(name)) else {
+            return;
+        };
+
+        // A partition's parent directory in sysfs is its whole disk
+        if sys_dir.join("partition").exists() {
+            if let Some(parent) = sys_dir.parent().and_then(|p| p.file_name()) {
+                collect_underlying_devices(&parent.to_string_lossy(), devices);
+            }
+        }
+
+        if let Ok(slaves) = std::fs::read_dir(sys_dir.join("slaves")) {
+            for slave in slaves.flatten() {
+                collect_underlying_devices(&slave.file_name().to_string_lossy(), devices);
+            }
+        }
+    }
+}
+
+/// macOS-specific helpers for disk devices
+#[cfg(target_os = "macos")]
+pub mod macos {
+    use anyhow::Result;
+    use std::{os::unix::io::RawFd, path::Path};
+
+    /// `_IOR('d', 24, u32)`: logical block size of a disk device
+    const DKIOCGETBLOCKSIZE: libc::c_ulong = 0x4004_6418;
+    /// `_IOR('d', 25, u64)`: number of blocks on a disk device
+    const DKIOCGETBLOCKCOUNT: libc::c_ulong = 0x4008_6419;
+
+    /// Logical block size of an open disk device
+    pub fn block_size(fd: RawFd) -> Result<u32> {
+        let mut block_size: u32 = 0;
+        if unsafe { libc::ioctl(fd, DKIOCGETBLOCKSIZE, &mut block_size) } != 0 || block_size == 0 {
+            anyhow::bail!(
+                "Failed to get disk block size: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(block_size)
+    }
+
+    /// Size in bytes of an open disk device, from its block count and block size
+    pub fn device_size(fd: RawFd) -> Result<u64> {
+        let mut block_count: u64 = 0;
+        if unsafe { libc::ioctl(fd, DKIOCGETBLOCKCOUNT, &mut block_count) } != 0 {
+            anyhow::bail!(
+                "Failed to get disk block count: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(block_count * u64::from(block_size(fd)?))
+    }
+
+    /// The raw character device (`/dev/rdiskN`) for a buffered disk path
+    /// (`/dev/diskN`), which is much faster for large sequential writes
+    pub fn raw_disk_path(path: &Path) -> Option<String> {
+        path.to_str()?
+            .strip_prefix("/dev/disk")
+            .map(|disk| format!("/dev/rdisk{}", disk))
+    }
+
+    /// Unmount every volume on the disk holding `path` with `diskutil unmountDisk`,
+    /// so that the disk is no longer busy when opened for writing
+    pub fn unmount_disk(path: &Path) -> Result<()> {
+        // diskutil names disks by their buffered node
+        let disk = path
+            .to_string_lossy()
+            .replacen("/dev/rdisk", "/dev/disk", 1);
+        let output = std::process::Command::new("diskutil")
+            .args(["unmountDisk", &disk])
+            .output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "diskutil unmountDisk {} failed: {}",
+                disk,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// FreeBSD disk (GEOM provider) utilities
+#[cfg(target_os = "freebsd")]
+pub mod freebsd {
+    use anyhow::Result;
+    use std::os::unix::io::RawFd;
+
+    /// `_IOR('d', 128, u_int)`: sector size of a disk device
+    const DIOCGSECTORSIZE: libc::c_ulong = 0x4004_6480;
+    /// `_IOR('d', 129, off_t)`: size in bytes of a disk device
+    const DIOCGMEDIASIZE: libc::c_ulong = 0x4008_6481;
+
+    /// Sector size of an open disk device
+    pub fn sector_size(fd: RawFd) -> Result<u32> {
+        let mut sector_size: libc::c_uint = 0;
+        if unsafe { libc::ioctl(fd, DIOCGSECTORSIZE, &mut sector_size) } != 0 || sector_size == 0 {
+            anyhow::bail!(
+                "Failed to get disk sector size: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(sector_size)
+    }
+
+    /// Size in bytes of an open disk device
+    pub fn media_size(fd: RawFd) -> Result<u64> {
+        let mut media_size: libc::off_t = 0;
+        if unsafe { libc::ioctl(fd, DIOCGMEDIASIZE, &mut media_size) } != 0 {
+            anyhow::bail!(
+                "Failed to get disk media size: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(media_size as u64)
+    }
+}
+
 /// Windows-specific utilities for disk and partition handling
 #[cfg(windows)]
 pub mod windows {
     use anyhow::Result;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
     use winapi::{
         shared::minwindef::{DWORD, LPVOID},
         um::{
             fileapi::{CreateFileW, OPEN_EXISTING},
             handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
-            winnt::{FILE_ATTRIBUTE_NORMAL, GENERIC_READ},
+            winnt::{
+                FILE_ATTRIBUTE_NORMAL, FILE_SHARE_READ, FILE_SHARE_WRITE, GENERIC_READ, HANDLE,
+            },
         },
     };
 
-    /// Check if a path represents a Windows physical drive or logical drive
+    /// Whether the process token is a member of the built-in Administrators group,
+    /// i.e. the program runs from an elevated prompt
+    pub fn is_elevated() -> bool {
+        use winapi::um::{
+            securitybaseapi::{AllocateAndInitializeSid, CheckTokenMembership, FreeSid},
+            winnt::{
+                DOMAIN_ALIAS_RID_ADMINS, PSID, SECURITY_BUILTIN_DOMAIN_RID, SECURITY_NT_AUTHORITY,
+                SID_IDENTIFIER_AUTHORITY,
+            },
+        };
+
+        unsafe {
+            let mut authority = SID_IDENTIFIER_AUTHORITY {
+                Value: SECURITY_NT_AUTHORITY,
+            };
+            let mut admin_group: PSID = std::ptr::null_mut();
+            if AllocateAndInitializeSid(
+                &mut authority,
+                2,
+                SECURITY_BUILTIN_DOMAIN_RID,
+                DOMAIN_ALIAS_RID_ADMINS,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                &mut admin_group,
+            ) == 0
+            {
+                return false;
+            }
+
+            // A null token checks the impersonation token of the calling thread
+            let mut is_member = 0;
+            let ok = CheckTokenMembership(std::ptr::null_mut(), admin_group, &mut is_member);
+            FreeSid(admin_group);
+            ok != 0 && is_member != 0
+        }
+    }
+
+    /// Check if a path represents a Windows physical drive, logical drive or volume
     pub fn is_windows_device_path(path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
-        // Physical drives: \\.\PhysicalDrive0, \\.\PhysicalDrive1, etc.
-        // Logical drives: \\.\C:, \\.\D:, etc.
-        path_str.starts_with(r"\\.\PhysicalDrive")
-            || (path_str.len() == 6 && path_str.starts_with(r"\\.\") && path_str.ends_with(':'))
+        get_device_type(path) != DeviceType::File
     }
 
     /// Get the device type from a Windows device path
     pub fn get_device_type(path: &Path) -> DeviceType {
         let path_str = path.to_string_lossy();
+        // Physical drives: \\.\PhysicalDrive0, \\.\PhysicalDrive1, etc.
+        // Logical drives: \\.\C:, \\.\D:, etc.
+        // Volumes without a drive letter: \\?\Volume{3f5e...}\
         if path_str.starts_with(r"\\.\PhysicalDrive") {
             DeviceType::PhysicalDrive
         } else if path_str.len() == 6 && path_str.starts_with(r"\\.\") && path_str.ends_with(':') {
             DeviceType::LogicalDrive
+        } else if is_volume_guid_path(&path_str) {
+            DeviceType::Volume
         } else {
             DeviceType::File
         }
@@ -37,9 +794,658 @@ pub mod windows {
     pub enum DeviceType {
         PhysicalDrive,
         LogicalDrive,
+        Volume,
         File,
     }
 
+    fn is_volume_guid_path(path_str: &str) -> bool {
+        let volume = trim_volume_path(path_str);
+        volume
+            .get(..11)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(r"\\?\Volume{"))
+            && volume.ends_with('}')
+    }
+
+    /// Expand the short forms of device targets users naturally type into the
+    /// device paths opened by the wipe: `C:` and `C:\` become `\\.\C:`, and
+    /// `PhysicalDrive2` and `2` become `\\.\PhysicalDrive2`. A bare number
+    /// only names a drive when no file of that name exists; any other path
+    /// is returned unchanged.
+    pub fn normalize_device_path(path: &Path) -> PathBuf {
+        let path_str = path.to_string_lossy();
+        let drive_letter = path_str
+            .strip_suffix(['\\', '/'])
+            .unwrap_or(&path_str)
+            .strip_suffix(':')
+            .filter(|letter| letter.len() == 1 && letter.bytes().all(|b| b.is_ascii_alphabetic()));
+        if let Some(letter) = drive_letter {
+            return PathBuf::from(format!(r"\\.\{}:", letter.to_ascii_uppercase()));
+        }
+
+        let drive_number = path_str
+            .get(..13)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("PhysicalDrive"))
+            .map_or(&*path_str, |_| &path_str[13..]);
+        if !drive_number.is_empty()
+            && drive_number.bytes().all(|b| b.is_ascii_digit())
+            && (drive_number.len() != path_str.len() || !path.exists())
+        {
+            return PathBuf::from(format!(r"\\.\PhysicalDrive{}", drive_number));
+        }
+
+        path.to_path_buf()
+    }
+
+    /// Volume GUID paths name the volume's root directory when they end in a
+    /// backslash; without it CreateFileW opens the volume itself
+    pub fn trim_volume_path(path_str: &str) -> &str {
+        if path_str.starts_with(r"\\?\") {
+            path_str.strip_suffix('\\').unwrap_or(path_str)
+        } else {
+            path_str
+        }
+    }
+
+    /// Drive letter of the Windows installation, e.g. `C`
+    fn system_drive_letter() -> char {
+        std::env::var("SystemDrive")
+            .ok()
+            .and_then(|drive| drive.chars().next())
+            .unwrap_or('C')
+            .to_ascii_uppercase()
+    }
+
+    /// Numbers of the physical disks that hold the system volume
+    pub fn system_disk_numbers() -> Vec<u32> {
+        let volume_path = format!(r"\\.\{}:", system_drive_letter());
+        let wide_path: Vec<u16> = volume_path.encode_utf16().chain(Some(0)).collect();
+
+        unsafe {
+            let handle = CreateFileW(
+                wide_path.as_ptr(),
+                0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                std::ptr::null_mut(),
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                std::ptr::null_mut(),
+            );
+
+            if handle == INVALID_HANDLE_VALUE {
+                return Vec::new();
+            }
+
+            let extents = volume_disk_extents(handle);
+            CloseHandle(handle);
+
+            extents.iter().map(|extent| extent.disk_number).collect()
+        }
+    }
+
+    /// A contiguous region of a physical disk that belongs to a volume
+    #[derive(Debug, Clone, Copy)]
+    pub struct DiskExtent {
+        pub disk_number: u32,
+        pub starting_offset: u64,
+    }
+
+    /// Where a volume lives on its physical disks (IOCTL_VOLUME_GET_VOLUME_DISK_EXTENTS).
+    /// Empty when the handle is not a volume.
+    pub fn volume_disk_extents(handle: HANDLE) -> Vec<DiskExtent> {
+        use winapi::um::{
+            ioapiset::DeviceIoControl,
+            winioctl::{IOCTL_VOLUME_GET_VOLUME_DISK_EXTENTS, VOLUME_DISK_EXTENTS},
+        };
+
+        // Room for a volume spanning several disks, aligned for the extent structs
+        let mut buffer = [0u64; 128];
+        let mut bytes_returned: DWORD = 0;
+
+        unsafe {
+            let success = DeviceIoControl(
+                handle,
+                IOCTL_VOLUME_GET_VOLUME_DISK_EXTENTS,
+                std::ptr::null_mut(),
+                0,
+                buffer.as_mut_ptr() as LPVOID,
+                std::mem::size_of_val(&buffer) as DWORD,
+                &mut bytes_returned,
+                std::ptr::null_mut(),
+            );
+
+            if success == 0 {
+                return Vec::new();
+            }
+
+            let extents = &*(buffer.as_ptr() as *const VOLUME_DISK_EXTENTS);
+            let extent_ptr = extents.Extents.as_ptr();
+            (0..extents.NumberOfDiskExtents as usize)
+                .map(|i| {
+                    let extent = &*extent_ptr.add(i);
+                    DiskExtent {
+                        disk_number: extent.DiskNumber,
+                        starting_offset: *extent.StartingOffset.QuadPart() as u64,
+                    }
+                })
+                .collect()
+        }
+    }
+
+    /// Check whether a device path is the system volume or a disk holding it
+    pub fn is_system_device_path(path: &Path) -> bool {
+        let path_str = path.to_string_lossy();
+        match get_device_type(path) {
+            DeviceType::LogicalDrive => path_str
+                .chars()
+                .nth(4)
+                .is_some_and(|letter| letter.to_ascii_uppercase() == system_drive_letter()),
+            DeviceType::PhysicalDrive => path_str
+                .trim_start_matches(r"\\.\PhysicalDrive")
+                .parse::<u32>()
+                .is_ok_and(|number| system_disk_numbers().contains(&number)),
+            DeviceType::Volume => volume_name_for_mount_point(&format!(
+                "{}:\\",
+                system_drive_letter()
+            ))
+            .is_some_and(|system_volume| {
+                trim_volume_path(&system_volume).eq_ignore_ascii_case(trim_volume_path(&path_str))
+            }),
+            DeviceType::File => false,
+        }
+    }
+
+    /// Leading fields of STORAGE_DEVICE_DESCRIPTOR, which winapi does not define
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct StorageDeviceDescriptor {
+        version: u32,
+        size: u32,
+        device_type: u8,
+        device_type_modifier: u8,
+        removable_media: u8,
+        command_queueing: u8,
+        vendor_id_offset: u32,
+        product_id_offset: u32,
+        product_revision_offset: u32,
+        serial_number_offset: u32,
+        bus_type: u32,
+        raw_properties_length: u32,
+    }
+
+    /// Properties reported by the storage driver for a drive or volume
+    #[derive(Debug, Clone)]
+    pub struct StorageProperties {
+        pub removable: bool,
+        pub vendor: Option<String>,
+        pub model: Option<String>,
+        pub serial: Option<String>,
+    }
+
+    /// Query the storage device descriptor of a drive or volume
+    /// with IOCTL_STORAGE_QUERY_PROPERTY (StorageDeviceProperty)
+    pub fn storage_properties(drive_path: &str) -> Option<StorageProperties> {
+        use winapi::um::{
+            ioapiset::DeviceIoControl,
+            winioctl::{
+                PropertyStandardQuery, StorageDeviceProperty, IOCTL_STORAGE_QUERY_PROPERTY,
+                STORAGE_PROPERTY_QUERY,
+            },
+        };
+
+        let wide_path: Vec<u16> = trim_volume_path(drive_path)
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+
+        unsafe {
+            // No access rights are needed to query device properties
+            let handle = CreateFileW(
+                wide_path.as_ptr(),
+                0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                std::ptr::null_mut(),
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                std::ptr::null_mut(),
+            );
+
+            if handle == INVALID_HANDLE_VALUE {
+                return None;
+            }
+
+            let mut query: STORAGE_PROPERTY_QUERY = std::mem::zeroed();
+            query.PropertyId = StorageDeviceProperty;
+            query.QueryType = PropertyStandardQuery;
+
+            // The descriptor is followed by its strings, aligned for the header fields
+            let mut buffer = [0u32; 256];
+            let mut bytes_returned: DWORD = 0;
+
+            let success = DeviceIoControl(
+                handle,
+                IOCTL_STORAGE_QUERY_PROPERTY,
+                &mut query as *mut _ as LPVOID,
+                std::mem::size_of::<STORAGE_PROPERTY_QUERY>() as DWORD,
+                buffer.as_mut_ptr() as LPVOID,
+                std::mem::size_of_val(&buffer) as DWORD,
+                &mut bytes_returned,
+                std::ptr::null_mut(),
+            );
+
+            CloseHandle(handle);
+
+            if success == 0
+                || (bytes_returned as usize) < std::mem::size_of::<StorageDeviceDescriptor>()
+            {
+                return None;
+            }
+
+            let descriptor = &*(buffer.as_ptr() as *const StorageDeviceDescriptor);
+            let bytes =
+                std::slice::from_raw_parts(buffer.as_ptr() as *const u8, bytes_returned as usize);
+
+            // Strings are NUL-terminated and space-padded; an offset of 0 means absent
+            let read_string = |offset: u32| -> Option<String> {
+                let start = offset as usize;
+                if start == 0 || start >= bytes.len() {
+                    return None;
+                }
+                let end = bytes[start..]
+                    .iter()
+                    .position(|&b| b == 0)
+                    .map_or(bytes.len(), |len| start + len);
+                let value = String::from_utf8_lossy(&bytes[start..end])
+                    .trim()
+                    .to_string();
+                (!value.is_empty()).then_some(value)
+            };
+
+            Some(StorageProperties {
+                removable: descriptor.removable_media != 0,
+                vendor: read_string(descriptor.vendor_id_offset),
+                model: read_string(descriptor.product_id_offset),
+                serial: read_string(descriptor.serial_number_offset),
+            })
+        }
+    }
+
+    /// FILE_ALLOCATED_RANGE_BUFFER, which winapi does not define
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct FileAllocatedRangeBuffer {
+        file_offset: i64,
+        length: i64,
+    }
+
+    /// Allocated ranges of a (possibly sparse) file via FSCTL_QUERY_ALLOCATED_RANGES
+    pub fn query_allocated_ranges(file: &std::fs::File, size: u64) -> Result<Vec<(u64, u64)>> {
+        use anyhow::Context;
+        use std::os::windows::io::AsRawHandle;
+        use winapi::{
+            shared::winerror::ERROR_MORE_DATA,
+            um::{ioapiset::DeviceIoControl, winioctl::FSCTL_QUERY_ALLOCATED_RANGES},
+        };
+
+        let handle = file.as_raw_handle() as winapi::um::winnt::HANDLE;
+        let mut ranges = Vec::new();
+        let mut offset = 0u64;
+
+        while offset < size {
+            let mut query = FileAllocatedRangeBuffer {
+                file_offset: offset as i64,
+                length: (size - offset) as i64,
+            };
+            let mut output = [FileAllocatedRangeBuffer::default(); 512];
+            let mut bytes_returned: DWORD = 0;
+
+            let success = unsafe {
+                DeviceIoControl(
+                    handle,
+                    FSCTL_QUERY_ALLOCATED_RANGES,
+                    &mut query as *mut _ as LPVOID,
+                    std::mem::size_of::<FileAllocatedRangeBuffer>() as DWORD,
+                    output.as_mut_ptr() as LPVOID,
+                    std::mem::size_of_val(&output) as DWORD,
+                    &mut bytes_returned,
+                    std::ptr::null_mut(),
+                )
+            };
+
+            // ERROR_MORE_DATA: the output filled up, continue after the last range
+            let more_data = success == 0
+                && std::io::Error::last_os_error().raw_os_error() == Some(ERROR_MORE_DATA as i32);
+            if success == 0 && !more_data {
+                return Err(std::io::Error::last_os_error())
+                    .context("Failed to query allocated ranges (FSCTL_QUERY_ALLOCATED_RANGES)");
+            }
+
+            let count = bytes_returned as usize / std::mem::size_of::<FileAllocatedRangeBuffer>();
+            for range in &output[..count] {
+                ranges.push((range.file_offset as u64, range.length as u64));
+            }
+
+            match output[..count].last() {
+                Some(last) if more_data => {
+                    offset = (last.file_offset + last.length) as u64;
+                }
+                _ => break,
+            }
+        }
+
+        Ok(ranges)
+    }
+
+    /// Size in bytes of an open drive, volume, or file handle. Uses
+    /// IOCTL_DISK_GET_LENGTH_INFO, then GetFileSizeEx, and only falls back to the
+    /// drive geometry (which under-reports on some USB bridges) when both fail.
+    pub fn device_size(handle: HANDLE) -> Result<u64> {
+        use winapi::um::{
+            fileapi::GetFileSizeEx,
+            ioapiset::DeviceIoControl,
+            winioctl::{
+                DISK_GEOMETRY_EX, GET_LENGTH_INFORMATION, IOCTL_DISK_GET_DRIVE_GEOMETRY_EX,
+                IOCTL_DISK_GET_LENGTH_INFO,
+            },
+            winnt::LARGE_INTEGER,
+        };
+
+        unsafe {
+            let mut length_info: GET_LENGTH_INFORMATION = std::mem::zeroed();
+            let mut bytes_returned: DWORD = 0;
+            if DeviceIoControl(
+                handle,
+                IOCTL_DISK_GET_LENGTH_INFO,
+                std::ptr::null_mut(),
+                0,
+                &mut length_info as *mut _ as LPVOID,
+                std::mem::size_of::<GET_LENGTH_INFORMATION>() as DWORD,
+                &mut bytes_returned,
+                std::ptr::null_mut(),
+            ) != 0
+            {
+                return Ok(*length_info.Length.QuadPart() as u64);
+            }
+
+            // Regular files, and volumes whose driver does not implement the ioctl
+            let mut file_size: LARGE_INTEGER = std::mem::zeroed();
+            if GetFileSizeEx(handle, &mut file_size) != 0 && *file_size.QuadPart() > 0 {
+                return Ok(*file_size.QuadPart() as u64);
+            }
+
+            let mut geometry: DISK_GEOMETRY_EX = std::mem::zeroed();
+            if DeviceIoControl(
+                handle,
+                IOCTL_DISK_GET_DRIVE_GEOMETRY_EX,
+                std::ptr::null_mut(),
+                0,
+                &mut geometry as *mut _ as LPVOID,
+                std::mem::size_of::<DISK_GEOMETRY_EX>() as DWORD,
+                &mut bytes_returned,
+                std::ptr::null_mut(),
+            ) != 0
+            {
+                return Ok(*geometry.DiskSize.QuadPart() as u64);
+            }
+        }
+
+        let error = std::io::Error::last_os_error();
+        anyhow::bail!("Failed to get Windows disk size: {}", error)
+    }
+
+    /// Logical sector size of an open drive or volume handle, which unbuffered
+    /// I/O must align to. Falls back to 4096, a multiple of every common size.
+    pub fn sector_size(handle: HANDLE) -> u32 {
+        use winapi::um::{
+            ioapiset::DeviceIoControl,
+            winioctl::{DISK_GEOMETRY, IOCTL_DISK_GET_DRIVE_GEOMETRY},
+        };
+
+        unsafe {
+            let mut geometry: DISK_GEOMETRY = std::mem::zeroed();
+            let mut bytes_returned: DWORD = 0;
+            if DeviceIoControl(
+                handle,
+                IOCTL_DISK_GET_DRIVE_GEOMETRY,
+                std::ptr::null_mut(),
+                0,
+                &mut geometry as *mut _ as LPVOID,
+                std::mem::size_of::<DISK_GEOMETRY>() as DWORD,
+                &mut bytes_returned,
+                std::ptr::null_mut(),
+            ) != 0
+                && geometry.BytesPerSector.is_power_of_two()
+            {
+                return geometry.BytesPerSector;
+            }
+        }
+
+        4096
+    }
+
+    /// Size, label, and filesystem of a mounted volume
+    #[derive(Debug, Clone)]
+    pub struct VolumeInfo {
+        pub total_bytes: u64,
+        pub label: Option<String>,
+        pub file_system: Option<String>,
+    }
+
+    /// Query a drive letter's volume with GetDiskFreeSpaceExW and GetVolumeInformationW.
+    /// Returns `None` when the volume cannot be queried, e.g. a card reader without media.
+    pub fn volume_info(drive_letter: char) -> Option<VolumeInfo> {
+        volume_info_at(&format!("{}:\\", drive_letter))
+    }
+
+    /// Like `volume_info`, for a volume root such as `E:\` or `\\?\Volume{...}\`
+    pub fn volume_info_at(root_path: &str) -> Option<VolumeInfo> {
+        use winapi::um::{
+            fileapi::{GetDiskFreeSpaceExW, GetVolumeInformationW},
+            winnt::ULARGE_INTEGER,
+        };
+
+        let root_path: Vec<u16> = root_path.encode_utf16().chain(Some(0)).collect();
+
+        unsafe {
+            let mut total_bytes: ULARGE_INTEGER = std::mem::zeroed();
+            if GetDiskFreeSpaceExW(
+                root_path.as_ptr(),
+                std::ptr::null_mut(),
+                &mut total_bytes,
+                std::ptr::null_mut(),
+            ) == 0
+            {
+                return None;
+            }
+
+            let mut label = [0u16; 261];
+            let mut file_system = [0u16; 261];
+            let has_volume_info = GetVolumeInformationW(
+                root_path.as_ptr(),
+                label.as_mut_ptr(),
+                label.len() as DWORD,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                file_system.as_mut_ptr(),
+                file_system.len() as DWORD,
+            ) != 0;
+
+            let to_string = |buffer: &[u16]| {
+                let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+                let value = String::from_utf16_lossy(&buffer[..len]);
+                (has_volume_info && !value.is_empty()).then_some(value)
+            };
+
+            Some(VolumeInfo {
+                total_bytes: *total_bytes.QuadPart(),
+                label: to_string(&label),
+                file_system: to_string(&file_system),
+            })
+        }
+    }
+
+    /// Flush the buffers of a file or device through to the media. A failed
+    /// flush carries the Win32 error code from GetLastError.
+    pub fn flush_file_buffers(handle: HANDLE) -> std::io::Result<()> {
+        use winapi::um::fileapi::FlushFileBuffers;
+
+        if unsafe { FlushFileBuffers(handle) } == 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Issue a file system control code that takes no input or output buffers
+    fn volume_control(handle: HANDLE, control_code: DWORD) -> std::io::Result<()> {
+        use winapi::um::ioapiset::DeviceIoControl;
+
+        let mut bytes_returned: DWORD = 0;
+        let success = unsafe {
+            DeviceIoControl(
+                handle,
+                control_code,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null_mut(),
+                0,
+                &mut bytes_returned,
+                std::ptr::null_mut(),
+            )
+        };
+
+        if success == 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Lock a logical volume for exclusive access and dismount its filesystem, so the
+    /// OS does not write to it while we overwrite the raw volume. Returns whether the
+    /// lock is held; it must be released with `unlock_volume` when the wipe ends.
+    ///
+    /// If the volume is in use the lock fails: the error lists the processes likely
+    /// holding it, unless `force_dismount` is set, in which case the filesystem is
+    /// dismounted anyway (invalidating the other handles) and the lock retried.
+    pub fn lock_and_dismount_volume(
+        handle: HANDLE,
+        volume_path: &str,
+        force_dismount: bool,
+    ) -> Result<bool> {
+        use anyhow::Context;
+        use winapi::um::winioctl::{FSCTL_DISMOUNT_VOLUME, FSCTL_LOCK_VOLUME};
+
+        if let Err(lock_error) = volume_control(handle, FSCTL_LOCK_VOLUME) {
+            if !force_dismount {
+                let holders = processes_on_volume(volume_path);
+                let hint = if holders.is_empty() {
+                    String::new()
+                } else {
+                    format!("\nProcesses likely using it:\n  {}", holders.join("\n  "))
+                };
+                anyhow::bail!(
+                    "Failed to lock volume {}: {}. Close programs using it or pass --force-dismount{}",
+                    volume_path,
+                    lock_error,
+                    hint
+                );
+            }
+
+            volume_control(handle, FSCTL_DISMOUNT_VOLUME)
+                .with_context(|| format!("Failed to force dismount volume {}", volume_path))?;
+            return Ok(volume_control(handle, FSCTL_LOCK_VOLUME).is_ok());
+        }
+
+        volume_control(handle, FSCTL_DISMOUNT_VOLUME)
+            .with_context(|| format!("Failed to dismount volume {}", volume_path))?;
+        Ok(true)
+    }
+
+    /// Release a lock taken by `lock_and_dismount_volume`
+    pub fn unlock_volume(handle: HANDLE) {
+        use winapi::um::winioctl::FSCTL_UNLOCK_VOLUME;
+
+        let _ = volume_control(handle, FSCTL_UNLOCK_VOLUME);
+    }
+
+    /// Processes whose executable lives on the given volume, as "name (PID n)".
+    /// Only a hint: processes can hold files open on a volume they were not started from.
+    fn processes_on_volume(volume_path: &str) -> Vec<String> {
+        use winapi::um::{
+            processthreadsapi::OpenProcess,
+            tlhelp32::{
+                CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+                TH32CS_SNAPPROCESS,
+            },
+            winbase::QueryFullProcessImageNameW,
+            winnt::PROCESS_QUERY_LIMITED_INFORMATION,
+        };
+
+        let prefixes: Vec<String> = match get_device_type(Path::new(volume_path)) {
+            DeviceType::LogicalDrive => volume_path
+                .chars()
+                .nth(4)
+                .map(|letter| format!("{}:\\", letter.to_ascii_uppercase()))
+                .into_iter()
+                .collect(),
+            DeviceType::Volume => volume_mount_points(volume_path)
+                .iter()
+                .map(|mount_point| mount_point.to_ascii_uppercase())
+                .collect(),
+            _ => Vec::new(),
+        };
+        if prefixes.is_empty() {
+            return Vec::new();
+        }
+
+        let mut processes = Vec::new();
+
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+            if snapshot == INVALID_HANDLE_VALUE {
+                return processes;
+            }
+
+            let mut entry: PROCESSENTRY32W = std::mem::zeroed();
+            entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as DWORD;
+
+            let mut has_entry = Process32FirstW(snapshot, &mut entry) != 0;
+            while has_entry {
+                let process =
+                    OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, entry.th32ProcessID);
+                if !process.is_null() {
+                    let mut image_path = [0u16; 1024];
+                    let mut size = image_path.len() as DWORD;
+                    if QueryFullProcessImageNameW(process, 0, image_path.as_mut_ptr(), &mut size)
+                        != 0
+                    {
+                        let image_path = String::from_utf16_lossy(&image_path[..size as usize]);
+                        let image_path = image_path.to_ascii_uppercase();
+                        if prefixes.iter().any(|prefix| image_path.starts_with(prefix)) {
+                            let name_len = entry
+                                .szExeFile
+                                .iter()
+                                .position(|&c| c == 0)
+                                .unwrap_or(entry.szExeFile.len());
+                            processes.push(format!(
+                                "{} (PID {})",
+                                String::from_utf16_lossy(&entry.szExeFile[..name_len]),
+                                entry.th32ProcessID
+                            ));
+                        }
+                    }
+                    CloseHandle(process);
+                }
+                has_entry = Process32NextW(snapshot, &mut entry) != 0;
+            }
+
+            CloseHandle(snapshot);
+        }
+
+        processes
+    }
+
     /// List available physical drives on Windows
     pub fn list_physical_drives() -> Result<Vec<String>> {
         let mut drives = Vec::new();
@@ -78,6 +1484,87 @@ pub mod windows {
         Ok(drives)
     }
 
+    /// Volume GUID paths (`\\?\Volume{...}\`) of every volume on the system
+    pub fn list_volume_guid_paths() -> Vec<String> {
+        use winapi::um::fileapi::{FindFirstVolumeW, FindNextVolumeW, FindVolumeClose};
+
+        let mut volumes = Vec::new();
+        let mut name = [0u16; 261];
+
+        unsafe {
+            let find = FindFirstVolumeW(name.as_mut_ptr(), name.len() as DWORD);
+            if find == INVALID_HANDLE_VALUE {
+                return volumes;
+            }
+
+            loop {
+                let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+                volumes.push(String::from_utf16_lossy(&name[..len]));
+                if FindNextVolumeW(find, name.as_mut_ptr(), name.len() as DWORD) == 0 {
+                    break;
+                }
+            }
+
+            FindVolumeClose(find);
+        }
+
+        volumes
+    }
+
+    /// Paths a volume is mounted at (`E:\`, `C:\mnt\data\`); empty when it has none
+    pub fn volume_mount_points(volume_path: &str) -> Vec<String> {
+        use winapi::um::fileapi::GetVolumePathNamesForVolumeNameW;
+
+        // The volume name must end in a backslash here
+        let volume_name: Vec<u16> = format!("{}\\", trim_volume_path(volume_path))
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+        let mut names = vec![0u16; 1024];
+        let mut needed: DWORD = 0;
+
+        let success = unsafe {
+            GetVolumePathNamesForVolumeNameW(
+                volume_name.as_ptr(),
+                names.as_mut_ptr(),
+                names.len() as DWORD,
+                &mut needed,
+            )
+        };
+        if success == 0 {
+            return Vec::new();
+        }
+
+        // A sequence of null-terminated strings, ended by an empty one
+        names
+            .split(|&c| c == 0)
+            .take_while(|name| !name.is_empty())
+            .map(String::from_utf16_lossy)
+            .collect()
+    }
+
+    /// Volume GUID path of the volume mounted at `mount_point`, e.g. `C:\`
+    fn volume_name_for_mount_point(mount_point: &str) -> Option<String> {
+        use winapi::um::fileapi::GetVolumeNameForVolumeMountPointW;
+
+        let wide_mount_point: Vec<u16> = mount_point.encode_utf16().chain(Some(0)).collect();
+        let mut name = [0u16; 261];
+
+        let success = unsafe {
+            GetVolumeNameForVolumeMountPointW(
+                wide_mount_point.as_ptr(),
+                name.as_mut_ptr(),
+                name.len() as DWORD,
+            )
+        };
+        if success == 0 {
+            return None;
+        }
+
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        Some(String::from_utf16_lossy(&name[..len]))
+    }
+
     /// Test if we can access a drive (for enumeration)
     fn test_drive_access(drive_path: &str) -> bool {
         let wide_path: Vec<u16> = drive_path.encode_utf16().chain(Some(0)).collect();
@@ -141,18 +1628,25 @@ pub mod windows {
                 std::ptr::null_mut(),
             );
 
+            let size_bytes = device_size(handle).ok();
+
             CloseHandle(handle);
 
             if success == 0 {
                 return Err(anyhow::anyhow!("Failed to get drive geometry"));
             }
 
+            let properties = storage_properties(drive_path);
+
             Ok(DriveInfo {
                 path: drive_path.to_string(),
-                size_bytes: *geometry.DiskSize.QuadPart() as u64,
+                size_bytes: size_bytes.unwrap_or(*geometry.DiskSize.QuadPart() as u64),
                 cylinders: *geometry.Geometry.Cylinders.QuadPart() as u64,
                 sectors_per_track: geometry.Geometry.SectorsPerTrack,
                 bytes_per_sector: geometry.Geometry.BytesPerSector,
+                vendor: properties.as_ref().and_then(|p| p.vendor.clone()),
+                model: properties.as_ref().and_then(|p| p.model.clone()),
+                serial: properties.and_then(|p| p.serial),
             })
         }
     }
@@ -164,6 +1658,9 @@ pub mod windows {
         pub cylinders: u64,
         pub sectors_per_track: u32,
         pub bytes_per_sector: u32,
+        pub vendor: Option<String>,
+        pub model: Option<String>,
+        pub serial: Option<String>,
     }
 }
 
@@ -172,24 +1669,256 @@ pub mod windows {
     use anyhow::Result;
     use std::path::Path;
 
+    #[cfg_attr(not(windows), allow(dead_code))]
     pub fn is_windows_device_path(_path: &Path) -> bool {
         false
     }
 
+    #[cfg_attr(not(windows), allow(dead_code))]
     pub fn get_device_type(_path: &Path) -> DeviceType {
         DeviceType::File
     }
 
+    #[cfg_attr(not(windows), allow(dead_code))]
     #[derive(Debug, PartialEq)]
     pub enum DeviceType {
         File,
     }
 
+    #[cfg_attr(not(windows), allow(dead_code))]
     pub fn list_physical_drives() -> Result<Vec<String>> {
         Ok(vec![])
     }
 
+    #[cfg_attr(not(windows), allow(dead_code))]
     pub fn list_logical_drives() -> Result<Vec<String>> {
         Ok(vec![])
     }
 }
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::{
+        device_open_path,
+        windows::{device_size, get_device_type, normalize_device_path, DeviceType},
+    };
+    use std::{io::Write, os::windows::io::AsRawHandle, path::Path};
+
+    #[test]
+    fn test_device_size_of_regular_file() {
+        let path =
+            std::env::temp_dir().join(format!("secure_wipe_size_{}.bin", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(&[0u8; 12345]).unwrap();
+        file.sync_all().unwrap();
+
+        let size = device_size(file.as_raw_handle() as winapi::um::winnt::HANDLE);
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(size.unwrap(), 12345);
+    }
+
+    #[test]
+    fn test_volume_guid_paths() {
+        let volume = Path::new(r"\\?\Volume{3f5e2b1a-0000-0000-0000-100000000000}\");
+        assert_eq!(get_device_type(volume), DeviceType::Volume);
+        assert_eq!(
+            device_open_path(volume),
+            Path::new(r"\\?\Volume{3f5e2b1a-0000-0000-0000-100000000000}")
+        );
+        assert_eq!(
+            get_device_type(Path::new(r"\\?\C:\Users")),
+            DeviceType::File
+        );
+    }
+
+    #[test]
+    fn test_normalize_device_path() {
+        for (typed, device) in [
+            ("C:", r"\\.\C:"),
+            (r"C:\", r"\\.\C:"),
+            ("d:/", r"\\.\D:"),
+            ("2", r"\\.\PhysicalDrive2"),
+            ("PhysicalDrive2", r"\\.\PhysicalDrive2"),
+            ("physicaldrive10", r"\\.\PhysicalDrive10"),
+            (r"\\.\E:", r"\\.\E:"),
+            (r"\\.\PhysicalDrive0", r"\\.\PhysicalDrive0"),
+        ] {
+            assert_eq!(normalize_device_path(Path::new(typed)), Path::new(device));
+        }
+        assert_eq!(
+            get_device_type(&normalize_device_path(Path::new("C:"))),
+            DeviceType::LogicalDrive
+        );
+        assert_eq!(
+            get_device_type(&normalize_device_path(Path::new("3"))),
+            DeviceType::PhysicalDrive
+        );
+
+        for path in [
+            r"C:\Users\file.bin",
+            r"C:file.bin",
+            "CD:",
+            "PhysicalDrive",
+            "PhysicalDrive2a",
+            "disk.img",
+            r"\\?\Volume{3f5e2b1a-0000-0000-0000-100000000000}\",
+        ] {
+            assert_eq!(normalize_device_path(Path::new(path)), Path::new(path));
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "macos"))]
+mod macos_tests {
+    use super::{is_block_device, macos::device_size};
+    use std::{os::unix::io::AsRawFd, path::Path, process::Command};
+
+    #[test]
+    fn test_device_size_of_ram_disk() {
+        // 2048 blocks of 512 bytes, attached without mounting anything
+        let output = Command::new("hdiutil")
+            .args(["attach", "-nomount", "ram://2048"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "hdiutil attach failed");
+        let disk = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let raw_disk = disk.replace("/dev/disk", "/dev/rdisk");
+
+        let is_raw_block_device = is_block_device(Path::new(&raw_disk));
+        let size = std::fs::File::open(&raw_disk)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| device_size(file.as_raw_fd()));
+        let _ = Command::new("hdiutil").args(["detach", &disk]).status();
+
+        assert!(is_raw_block_device);
+        assert_eq!(size.unwrap(), 2048 * 512);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod preallocate_tests {
+    use super::preallocate;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_preallocate_reserves_blocks() {
+        const LEN: u64 = 1024 * 1024 + 100;
+
+        let file = tempfile::tempfile().unwrap();
+        preallocate(&file, LEN).unwrap();
+        let metadata = file.metadata().unwrap();
+        assert_eq!(metadata.len(), LEN);
+        // Filesystems that cannot reserve space leave the file sparse
+        if metadata.blocks() > 0 {
+            assert!(metadata.blocks() * 512 >= LEN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod serial_tests {
+    use super::vpd_unit_serial;
+
+    #[test]
+    fn test_vpd_unit_serial() {
+        let mut page = vec![0x00, 0x80, 0x00, 0x0c];
+        page.extend_from_slice(b"  WD-WX12345");
+        assert_eq!(vpd_unit_serial(&page).as_deref(), Some("WD-WX12345"));
+
+        // Wrong page code, and a length running past the data
+        assert_eq!(vpd_unit_serial(&[0x00, 0x83, 0x00, 0x00]), None);
+        assert_eq!(vpd_unit_serial(&[0x00, 0x80, 0x00, 0x40, b'A']), None);
+        assert_eq!(vpd_unit_serial(&[0x00, 0x80, 0x00, 0x02, b' ', b' ']), None);
+    }
+}
+
+#[cfg(test)]
+mod queue_tests {
+    use super::{QueueLimits, ZonedModel};
+
+    #[test]
+    fn test_queue_limits_align_buffer() {
+        let limits = QueueLimits {
+            max_request_kb: 1280,
+            optimal_io_bytes: 0,
+            logical_block_size: 512,
+            zoned: ZonedModel::None,
+        };
+        assert_eq!(limits.write_unit(), 1280 * 1024);
+        assert_eq!(limits.align_buffer(64 * 1024 * 1024), 51 * 1280 * 1024);
+        assert_eq!(limits.align_buffer(1024 * 1024), 1280 * 1024);
+
+        // A RAID stripe larger than one request: 8 requests make 5 stripes
+        let striped = QueueLimits {
+            optimal_io_bytes: 2 * 1024 * 1024,
+            ..limits
+        };
+        assert_eq!(striped.write_unit(), 10 * 1024 * 1024);
+        assert_eq!(striped.align_buffer(32 * 1024 * 1024), 30 * 1024 * 1024);
+
+        // Three 64 KiB chunks per stripe: 3 requests make 20 stripes
+        let three_disks = QueueLimits {
+            optimal_io_bytes: 192 * 1024,
+            ..limits
+        };
+        assert_eq!(three_disks.write_unit(), 3 * 1280 * 1024);
+        assert_eq!(three_disks.write_unit() % (192 * 1024), 0);
+
+        // A bogus optimal I/O size is ignored rather than making huge buffers
+        let bogus = QueueLimits {
+            optimal_io_bytes: 0xFFFF * 512,
+            ..limits
+        };
+        assert_eq!(bogus.write_unit(), 1280 * 1024);
+
+        let large_sectors = QueueLimits {
+            max_request_kb: 6,
+            optimal_io_bytes: 0,
+            logical_block_size: 4096,
+            zoned: ZonedModel::None,
+        };
+        assert_eq!(large_sectors.write_unit(), 12 * 1024);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_read_queue_limits() {
+        use super::linux::read_queue_limits;
+
+        let queue = tempfile::tempdir().unwrap();
+        let write =
+            |attr: &str, value: &str| std::fs::write(queue.path().join(attr), value).unwrap();
+        assert_eq!(read_queue_limits(queue.path()), None);
+
+        write("max_sectors_kb", "1280\n");
+        write("logical_block_size", "4096\n");
+        assert_eq!(
+            read_queue_limits(queue.path()),
+            Some(QueueLimits {
+                max_request_kb: 1280,
+                optimal_io_bytes: 0,
+                logical_block_size: 4096,
+                zoned: ZonedModel::None,
+            })
+        );
+
+        write("optimal_io_size", "1048576\n");
+        assert_eq!(
+            read_queue_limits(queue.path()).map(|limits| limits.optimal_io_bytes),
+            Some(1_048_576)
+        );
+
+        write("zoned", "host-managed\n");
+        assert_eq!(
+            read_queue_limits(queue.path()).map(|limits| limits.zoned),
+            Some(ZonedModel::HostManaged)
+        );
+        write("zoned", "none\n");
+        assert_eq!(
+            read_queue_limits(queue.path()).map(|limits| limits.zoned),
+            Some(ZonedModel::None)
+        );
+    }
+}
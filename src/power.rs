@@ -0,0 +1,166 @@
+/// Keeps the machine from going to sleep while a wipe runs. The request is
+/// released when the guard is dropped, so it also ends on errors, cancellation,
+/// and panics.
+pub struct SleepInhibitor {
+    /// `systemd-inhibit` holding the logind inhibitor lock until its stdin closes
+    #[cfg(target_os = "linux")]
+    lock: std::process::Child,
+    #[cfg(not(target_os = "linux"))]
+    _private: (),
+}
+
+impl SleepInhibitor {
+    /// Ask the OS not to sleep until the guard is dropped, giving `reason` as
+    /// the explanation where the OS shows one. `None` when the request fails or
+    /// the platform has no support for it.
+    pub fn acquire(reason: &str) -> Option<Self> {
+        #[cfg(windows)]
+        {
+            use winapi::um::{
+                winbase::SetThreadExecutionState,
+                winnt::{ES_CONTINUOUS, ES_SYSTEM_REQUIRED},
+            };
+
+            let _ = reason;
+            // The state belongs to the calling thread, which outlives the wipe
+            let previous = unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+            (previous != 0).then_some(SleepInhibitor { _private: () })
+        }
+        #[cfg(target_os = "linux")]
+        {
+            // No systemd, system bus or logind (containers, minimal servers)
+            // just means there is nothing that would suspend the machine
+            logind::inhibit(logind::SYSTEMD_INHIBIT, "sleep:shutdown", reason)
+                .ok()
+                .map(|lock| SleepInhibitor { lock })
+        }
+        #[cfg(not(any(windows, target_os = "linux")))]
+        {
+            let _ = reason;
+            None
+        }
+    }
+}
+
+impl Drop for SleepInhibitor {
+    fn drop(&mut self) {
+        #[cfg(windows)]
+        unsafe {
+            use winapi::um::{winbase::SetThreadExecutionState, winnt::ES_CONTINUOUS};
+
+            SetThreadExecutionState(ES_CONTINUOUS);
+        }
+        #[cfg(target_os = "linux")]
+        logind::release(&mut self.lock);
+    }
+}
+
+/// Inhibitor locks from logind, taken by running `systemd-inhibit` around a
+/// `cat` that waits on a pipe from this process. Closing the pipe, or this
+/// process dying, ends `cat` and with it the lock.
+#[cfg(target_os = "linux")]
+mod logind {
+    use std::{
+        ffi::OsStr,
+        io::{self, BufRead, BufReader, Write},
+        os::unix::process::CommandExt,
+        process::{Child, Command, Stdio},
+    };
+
+    pub const SYSTEMD_INHIBIT: &str = "systemd-inhibit";
+    /// Name logind shows as the holder of the lock
+    const WHO: &str = "secure-wipe-bin";
+
+    /// Take a blocking inhibitor lock for `what` (e.g. `sleep:shutdown`) with
+    /// `program`, returning once the lock is held. Fails when the program is
+    /// missing or exits without taking it, such as without a system bus or logind.
+    pub fn inhibit(program: impl AsRef<OsStr>, what: &str, why: &str) -> io::Result<Child> {
+        let mut child = Command::new(program)
+            .arg(format!("--what={}", what))
+            .arg(format!("--who={}", WHO))
+            .arg(format!("--why={}", why))
+            .arg("--mode=block")
+            .arg("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            // Its own process group, so that the Ctrl-C which cancels the wipe
+            // does not release the lock while the wipe is still winding down
+            .process_group(0)
+            .spawn()?;
+
+        // `cat` only runs once the lock is held, so its echo of a line confirms it
+        let echoed = child
+            .stdin
+            .as_mut()
+            .map_or(Ok(()), |stdin| stdin.write_all(b"\n"))
+            .and_then(|()| {
+                let mut line = String::new();
+                BufReader::new(child.stdout.as_mut().expect("piped stdout"))
+                    .read_line(&mut line)
+                    .map(|read| read > 0)
+            });
+        match echoed {
+            Ok(true) => Ok(child),
+            result => {
+                release(&mut child);
+                Err(result.err().unwrap_or_else(|| {
+                    io::Error::other("systemd-inhibit exited without taking the lock")
+                }))
+            }
+        }
+    }
+
+    /// Release a lock taken by `inhibit` and reap the process holding it
+    pub fn release(child: &mut Child) {
+        drop(child.stdin.take());
+        let _ = child.wait();
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::logind::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Stand-in for `systemd-inhibit` that records its options and runs the
+    /// command after them, as if the lock were granted
+    fn fake_inhibit(dir: &std::path::Path, script: &str) -> std::path::PathBuf {
+        let program = dir.join("systemd-inhibit");
+        std::fs::write(&program, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        program
+    }
+
+    #[test]
+    fn test_inhibit_holds_the_lock_until_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = dir.path().join("args");
+        let program = fake_inhibit(
+            dir.path(),
+            &format!(
+                "printf '%s\\n' \"$@\" > {}\nshift 4\nexec \"$@\"",
+                args.display()
+            ),
+        );
+
+        let mut lock = inhibit(&program, "sleep:shutdown", "Wiping /dev/sdx").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&args).unwrap(),
+            "--what=sleep:shutdown\n--who=secure-wipe-bin\n--why=Wiping /dev/sdx\n--mode=block\ncat\n"
+        );
+        assert!(lock.try_wait().unwrap().is_none(), "lock released early");
+        release(&mut lock);
+        assert!(lock.try_wait().unwrap().is_some(), "lock still held");
+    }
+
+    #[test]
+    fn test_inhibit_without_logind_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(inhibit(dir.path().join("missing"), "sleep", "test").is_err());
+
+        // What systemd-inhibit does without a bus to ask
+        let program = fake_inhibit(dir.path(), "echo 'Failed to connect to bus' >&2\nexit 1");
+        assert!(inhibit(&program, "sleep", "test").is_err());
+    }
+}
@@ -0,0 +1,260 @@
+//! Lowering the CPU and I/O priority of a wipe for `--nice`, and setting the
+//! I/O scheduling class for `--io-priority`
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::args::IoPriority;
+
+/// CPU niceness applied on Unix, the lowest priority there is
+#[cfg(unix)]
+const LOWEST_NICENESS: libc::c_int = 19;
+
+/// Priority the wipe runs at, as reported in the start event
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriorityMode {
+    /// Left as the process was started
+    Normal,
+    /// Idle I/O scheduling class and lowest CPU niceness (Linux)
+    IdleIo,
+    /// Throttled disk I/O policy and lowest CPU niceness (macOS)
+    ThrottledIo,
+    /// Background processing mode, lowering CPU, I/O and memory priority (Windows)
+    Background,
+    /// Lowest CPU niceness only, where the I/O priority cannot be changed
+    Nice,
+    /// Idle I/O scheduling class only, from `--io-priority idle` (Linux)
+    IdleIoClass,
+    /// Best-effort I/O scheduling class at level 4, from `--io-priority best-effort` (Linux)
+    BestEffortIoClass,
+    /// Real-time I/O scheduling class at level 4, from `--io-priority rt` (Linux)
+    RealtimeIoClass,
+}
+
+impl PriorityMode {
+    /// What the mode does, for the line printed before the wipe
+    pub fn description(&self) -> &'static str {
+        match self {
+            PriorityMode::Normal => "normal",
+            PriorityMode::IdleIo => "idle I/O scheduling class, nice 19",
+            PriorityMode::ThrottledIo => "throttled disk I/O policy, nice 19",
+            PriorityMode::Background => "background processing mode",
+            PriorityMode::Nice => "nice 19",
+            PriorityMode::IdleIoClass => "idle I/O scheduling class",
+            PriorityMode::BestEffortIoClass => "best-effort I/O scheduling class, level 4",
+            PriorityMode::RealtimeIoClass => "real-time I/O scheduling class, level 4",
+        }
+    }
+}
+
+/// Lower the CPU and I/O priority of the process so that a wipe does not hurt
+/// the latency of other work on the machine. On Linux both priorities belong to
+/// the calling thread and are inherited by threads it starts afterwards, so this
+/// must run before any writer or generator thread is started.
+pub fn lower_priority() -> Result<PriorityMode> {
+    #[cfg(unix)]
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, LOWEST_NICENESS) } != 0 {
+        anyhow::bail!(
+            "Failed to lower the CPU priority: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        linux::set_idle_io_class()?;
+        Ok(PriorityMode::IdleIo)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        macos::throttle_disk_io()?;
+        Ok(PriorityMode::ThrottledIo)
+    }
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+    {
+        Ok(PriorityMode::Nice)
+    }
+    #[cfg(windows)]
+    {
+        use winapi::um::{
+            processthreadsapi::GetCurrentProcess, winbase::PROCESS_MODE_BACKGROUND_BEGIN,
+        };
+
+        if unsafe {
+            winapi::um::processthreadsapi::SetPriorityClass(
+                GetCurrentProcess(),
+                PROCESS_MODE_BACKGROUND_BEGIN,
+            )
+        } == 0
+        {
+            anyhow::bail!(
+                "Failed to enter background processing mode: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(PriorityMode::Background)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        anyhow::bail!("Lowering the priority is not supported on this platform")
+    }
+}
+
+/// Put the process in the I/O scheduling class selected with `--io-priority`,
+/// leaving its CPU priority alone. Like `lower_priority`, this must run before
+/// any other thread is started. `None` where the class cannot be set.
+pub fn set_io_priority(class: IoPriority) -> Result<Option<PriorityMode>> {
+    #[cfg(target_os = "linux")]
+    {
+        let mode = match class {
+            IoPriority::Idle => {
+                linux::set_io_class(linux::IOPRIO_CLASS_IDLE, 0)?;
+                PriorityMode::IdleIoClass
+            }
+            IoPriority::BestEffort => {
+                linux::set_io_class(linux::IOPRIO_CLASS_BE, linux::IOPRIO_DEFAULT_LEVEL)?;
+                PriorityMode::BestEffortIoClass
+            }
+            IoPriority::Rt => {
+                linux::set_io_class(linux::IOPRIO_CLASS_RT, linux::IOPRIO_DEFAULT_LEVEL)?;
+                PriorityMode::RealtimeIoClass
+            }
+        };
+        Ok(Some(mode))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = class;
+        Ok(None)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use anyhow::Result;
+
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    pub const IOPRIO_CLASS_RT: libc::c_int = 1;
+    pub const IOPRIO_CLASS_BE: libc::c_int = 2;
+    pub const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    /// Middle of the 0 (highest) to 7 (lowest) levels of the real-time and best-effort classes
+    pub const IOPRIO_DEFAULT_LEVEL: libc::c_int = 4;
+
+    /// Put the calling thread in the idle I/O scheduling class, served only
+    /// when no other process needs the disk
+    pub fn set_idle_io_class() -> Result<()> {
+        set_io_class(IOPRIO_CLASS_IDLE, 0)
+    }
+
+    /// Put the calling thread in an I/O scheduling class at a level within it
+    pub fn set_io_class(class: libc::c_int, level: libc::c_int) -> Result<()> {
+        let ioprio = class << IOPRIO_CLASS_SHIFT | level;
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } != 0 {
+            let error = std::io::Error::last_os_error();
+            if class == IOPRIO_CLASS_RT && error.raw_os_error() == Some(libc::EPERM) {
+                anyhow::bail!(
+                    "Failed to set the real-time I/O scheduling class: it needs root (CAP_SYS_ADMIN)"
+                );
+            }
+            anyhow::bail!("Failed to set the I/O scheduling class: {}", error);
+        }
+        Ok(())
+    }
+
+    /// I/O scheduling class of the calling thread
+    #[cfg(test)]
+    pub fn io_class() -> libc::c_int {
+        let ioprio = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+        ioprio as libc::c_int >> IOPRIO_CLASS_SHIFT
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use anyhow::Result;
+
+    const IOPOL_TYPE_DISK: libc::c_int = 0;
+    const IOPOL_SCOPE_PROCESS: libc::c_int = 0;
+    const IOPOL_THROTTLE: libc::c_int = 3;
+
+    extern "C" {
+        fn setiopolicy_np(
+            iotype: libc::c_int,
+            scope: libc::c_int,
+            policy: libc::c_int,
+        ) -> libc::c_int;
+    }
+
+    /// Throttle the disk I/O of the whole process behind that of other processes
+    pub fn throttle_disk_io() -> Result<()> {
+        if unsafe { setiopolicy_np(IOPOL_TYPE_DISK, IOPOL_SCOPE_PROCESS, IOPOL_THROTTLE) } != 0 {
+            anyhow::bail!(
+                "Failed to throttle disk I/O: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lower_priority_is_applied() {
+        // Both priorities belong to the thread on Linux, so the test thread
+        // running alongside others keeps its own
+        std::thread::spawn(|| {
+            assert_eq!(lower_priority().unwrap(), PriorityMode::IdleIo);
+            assert_eq!(linux::io_class(), linux::IOPRIO_CLASS_IDLE);
+            assert_eq!(
+                unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) },
+                LOWEST_NICENESS
+            );
+
+            // Threads started afterwards, like the writers, inherit both
+            std::thread::spawn(|| {
+                assert_eq!(linux::io_class(), linux::IOPRIO_CLASS_IDLE);
+                assert_eq!(
+                    unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) },
+                    LOWEST_NICENESS
+                );
+            })
+            .join()
+            .unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_io_priority_leaves_cpu_priority() {
+        for (class, mode, expected_class) in [
+            (
+                IoPriority::Idle,
+                PriorityMode::IdleIoClass,
+                linux::IOPRIO_CLASS_IDLE,
+            ),
+            (
+                IoPriority::BestEffort,
+                PriorityMode::BestEffortIoClass,
+                linux::IOPRIO_CLASS_BE,
+            ),
+        ] {
+            std::thread::spawn(move || {
+                let niceness = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+                assert_eq!(set_io_priority(class).unwrap(), Some(mode));
+                assert_eq!(linux::io_class(), expected_class);
+                assert_eq!(
+                    unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) },
+                    niceness
+                );
+            })
+            .join()
+            .unwrap();
+        }
+    }
+}
@@ -1,21 +1,75 @@
 use serde::{Deserialize, Serialize};
-use std::io::{self, Write};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    args::SyncMode,
+    batch::TargetResult,
+    platform::QueueLimits,
+    priority::PriorityMode,
+    reports::{BitLockerInfo, HiddenArea},
+    system::MachineInfo,
+    targets::MatchedFile,
+    wipe::IoMode,
+};
+
+/// Version of the JSON event schema, reported in the start event and checked by
+/// `--protocol-version`. Bump it whenever an event or one of its fields changes.
+pub const PROTOCOL_VERSION: u32 = 21;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ProgressEvent {
     #[serde(rename = "start")]
     Start {
+        protocol_version: u32,
         algorithm: String,
+        /// Generator of the random data, `None` when no pass writes random data
+        rng: Option<String>,
         total_passes: usize,
         file_size_bytes: u64,
+        allocated_bytes: Option<u64>,
+        /// Bytes at the start and end of the target left untouched
+        preserve_head_bytes: u64,
+        preserve_tail_bytes: u64,
         buffer_size_kb: usize,
+        /// The same buffer size in bytes, as given to `--buffer-size` unless rounded for the device
+        buffer_size_bytes: usize,
+        sync_mode: SyncMode,
+        /// `--no-sync`: passes are not flushed to disk when they end
+        no_sync: bool,
+        machine: Option<Box<MachineInfo>>,
+        bitlocker: Option<BitLockerInfo>,
+        hidden_area: Option<HiddenArea>,
+        /// Request queue limits of a Linux block device target, which the buffer size follows
+        queue_limits: Option<QueueLimits>,
+        priority: PriorityMode,
+    },
+    #[serde(rename = "estimate")]
+    Estimate {
+        probe_bytes: u64,
+        bytes_per_second: f64,
+        estimated_seconds: f64,
     },
     #[serde(rename = "pass_start")]
     PassStart {
         pass: usize,
         total_passes: usize,
         pattern: String,
+        /// Size of the writes for the pass, `None` while buffer sizes are still
+        /// to be tried; a `buffer_tuned` event then reports the size chosen
+        buffer_size_kb: Option<usize>,
+    },
+    #[serde(rename = "buffer_tuned")]
+    BufferTuned {
+        pass: usize,
+        /// Size of the writes for the rest of the pass
+        buffer_size_kb: usize,
+        /// Throughput of each buffer size tried at the start of the pass
+        trials: Vec<BufferTrial>,
     },
     #[serde(rename = "progress")]
     Progress {
@@ -25,6 +79,20 @@ pub enum ProgressEvent {
         total_bytes: u64,
         percent: f64,
         bytes_per_second: f64,
+        smoothed_bytes_per_second: f64,
+    },
+    #[serde(rename = "sync_start")]
+    SyncStart { pass: usize },
+    #[serde(rename = "sync_progress")]
+    SyncProgress { pass: usize, elapsed_seconds: f64 },
+    #[serde(rename = "sync_complete")]
+    SyncComplete { pass: usize, duration_seconds: f64 },
+    #[serde(rename = "pass_verified")]
+    PassVerified {
+        pass: usize,
+        method: String,
+        bytes_verified: u64,
+        duration_seconds: f64,
     },
     #[serde(rename = "pass_complete")]
     PassComplete { pass: usize, total_passes: usize },
@@ -32,11 +100,75 @@ pub enum ProgressEvent {
     Complete {
         total_time_seconds: f64,
         average_throughput_mb_s: f64,
+        io_mode: IoMode,
+        sync_mode: SyncMode,
+        sleep_inhibited: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        final_pass_sha256: Option<String>,
+        /// Write rate cap set with `--max-rate`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_rate_bytes_per_second: Option<u64>,
+    },
+    #[serde(rename = "entropy_check")]
+    EntropyCheck {
+        samples: u64,
+        sampled_bytes: u64,
+        bits_per_byte: f64,
+        chi_square: f64,
+    },
+    #[serde(rename = "verify_progress")]
+    VerifyProgress {
+        bytes_verified: u64,
+        total_bytes: u64,
+        percent: f64,
     },
+    #[serde(rename = "verify_complete")]
+    VerifyComplete {
+        /// Expected pattern, as lowercase hex
+        pattern: String,
+        bytes_verified: u64,
+        duration_seconds: f64,
+        matched: bool,
+        /// Offset of the first byte that does not hold the pattern
+        mismatch_offset: Option<u64>,
+    },
+    #[serde(rename = "smart_health")]
+    SmartHealth {
+        /// "before" or "after" the wipe
+        phase: String,
+        passed: bool,
+        reallocated_sectors: Option<u64>,
+        pending_sectors: Option<u64>,
+        power_on_hours: Option<u64>,
+    },
+    #[serde(rename = "bitlocker_metadata_wiped")]
+    BitLockerMetadataWiped {
+        bytes_written: u64,
+        /// Offsets of the overwritten FVE metadata blocks within the volume
+        metadata_offsets: Vec<u64>,
+    },
+    #[serde(rename = "dry_run")]
+    DryRun {
+        total_bytes: u64,
+        files: Vec<MatchedFile>,
+    },
+    #[serde(rename = "cancelled")]
+    Cancelled { pass: usize, bytes_written: u64 },
     #[serde(rename = "error")]
-    Error { message: String },
+    Error {
+        message: String,
+        /// Machine-readable reason, for errors a caller may want to handle
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+        /// Pass that was running, for errors in the middle of a wipe
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pass: Option<usize>,
+        /// OS error number, for errors reported by a system call
+        #[serde(skip_serializing_if = "Option::is_none")]
+        errno: Option<i32>,
+    },
     #[serde(rename = "demo_file_created")]
-    DemoFileCreated { path: String, size_mb: u64 },
+    DemoFileCreated { path: String, size_bytes: u64 },
     #[serde(rename = "demo_file_creating")]
     DemoFileCreating {
         bytes_written: u64,
@@ -45,11 +177,136 @@ pub enum ProgressEvent {
     },
     #[serde(rename = "info")]
     Info { message: String },
+    #[serde(rename = "retry")]
+    Retry {
+        pass: usize,
+        offset: u64,
+        attempt: u32,
+        max_retries: u32,
+        delay_ms: u64,
+        message: String,
+    },
+    #[serde(rename = "write_error")]
+    WriteError {
+        pass: usize,
+        offset: u64,
+        message: String,
+    },
+    /// Every pass of a target, emitted just before its complete event
+    #[serde(rename = "pass_summary")]
+    PassSummary { passes: Vec<PassRecord> },
+    #[serde(rename = "summary")]
+    Summary {
+        succeeded: usize,
+        failed: usize,
+        /// Bytes written across all targets and passes
+        total_bytes_written: u64,
+        /// Combined size of all targets
+        total_size_bytes: u64,
+        targets: Vec<TargetResult>,
+    },
+    #[serde(rename = "failed_regions")]
+    FailedRegions {
+        failed_bytes: u64,
+        regions: Vec<FailedRange>,
+    },
+}
+
+/// A region of the target that could not be written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedRange {
+    pub pass: usize,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Outcome of one pass, for the table in the pass summary event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PassRecord {
+    pub pass: usize,
+    pub pattern: String,
+    pub bytes_written: u64,
+    /// Bytes written over the time from the start of the pass to the end of its sync
+    pub bytes_per_second: f64,
+    /// Read-back result with `--verify-each-pass`, `None` when the pass was not read back
+    pub verified: Option<bool>,
+}
+
+/// Throughput measured while writing the start of a pass with one buffer size
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BufferTrial {
+    pub buffer_size_kb: usize,
+    pub bytes_written: u64,
+    pub bytes_per_second: f64,
+}
+
+/// Number of progress intervals the smoothed throughput is averaged over
+const THROUGHPUT_WINDOW: usize = 5;
+
+/// Throughput averaged over the last few progress intervals, so displays
+/// do not jump around with every jittery interval
+#[derive(Debug, Default)]
+pub struct ThroughputWindow {
+    samples: VecDeque<(u64, f64)>,
+}
+
+impl ThroughputWindow {
+    /// Record the bytes written during an interval and return the windowed throughput
+    pub fn record(&mut self, bytes: u64, seconds: f64) -> f64 {
+        self.samples.push_back((bytes, seconds));
+        if self.samples.len() > THROUGHPUT_WINDOW {
+            self.samples.pop_front();
+        }
+
+        let (bytes, seconds) = self
+            .samples
+            .iter()
+            .fold((0u64, 0.0), |(b, s), (bytes, seconds)| {
+                (b + bytes, s + seconds)
+            });
+        if seconds > 0.0 {
+            bytes as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Set by `--json-pretty`; JSON output is compact, one object per line, otherwise
+static PRETTY_JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_pretty_json(pretty: bool) {
+    PRETTY_JSON.store(pretty, Ordering::Relaxed);
+}
+
+/// Serialize any JSON output of the tool, compact or indented as requested
+pub fn to_json<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    if PRETTY_JSON.load(Ordering::Relaxed) {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
 }
 
 pub fn emit_event(event: &ProgressEvent) -> io::Result<()> {
-    let json = serde_json::to_string(event)?;
-    println!("{}", json);
-    io::stdout().flush()?;
-    Ok(())
+    let json = to_json(event)?;
+    write_line(&json)
+}
+
+/// Emit an event tagged with the target it belongs to, so interleaved
+/// streams from concurrent wipes stay attributable
+pub fn emit_target_event(target: &str, event: &ProgressEvent) -> io::Result<()> {
+    let mut value = serde_json::to_value(event)?;
+    if let Some(object) = value.as_object_mut() {
+        object.insert("target".to_string(), target.into());
+    }
+    write_line(&to_json(&value)?)
+}
+
+/// Write a whole line (or pretty-printed object) under the stdout lock so
+/// concurrent writers never interleave
+fn write_line(line: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", line)?;
+    stdout.flush()
 }
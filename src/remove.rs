@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::{
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+use crate::progress::{emit_target_event, ProgressEvent};
+
+/// Stages of secure file removal, reported so a failure pinpoints where it stopped
+#[derive(Debug, Clone, Copy)]
+enum RemovalStage {
+    Rename,
+    Truncate,
+    SyncDirectory,
+    Unlink,
+}
+
+impl RemovalStage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RemovalStage::Rename => "rename",
+            RemovalStage::Truncate => "truncate",
+            RemovalStage::SyncDirectory => "sync directory",
+            RemovalStage::Unlink => "unlink",
+        }
+    }
+}
+
+/// Remove an already overwritten file so that neither its name nor its size survive
+/// in the directory entry: rename it to random names of decreasing length, truncate
+/// it to zero, sync the parent directory, then unlink it (similar to `shred -u`).
+pub fn remove_file_securely(path: &Path, json_mode: bool) -> Result<()> {
+    let target = path.display().to_string();
+    let mut current = path.to_path_buf();
+
+    let result = run_removal(&mut current, &target, json_mode);
+
+    result.map_err(|(stage, e)| {
+        let location = if current.exists() {
+            format!("file is now at {}", current.display())
+        } else {
+            "file no longer exists".to_string()
+        };
+        e.context(format!(
+            "Secure removal failed at stage '{}' ({})",
+            stage.as_str(),
+            location
+        ))
+    })
+}
+
+fn run_removal(
+    current: &mut PathBuf,
+    target: &str,
+    json_mode: bool,
+) -> std::result::Result<(), (RemovalStage, anyhow::Error)> {
+    let parent = current
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+
+    let name_len = current
+        .file_name()
+        .map(|name| name.len())
+        .unwrap_or(1)
+        .clamp(1, 255);
+
+    report_stage(target, json_mode, "Renaming file to obscure its name");
+    for len in (1..=name_len).rev() {
+        let renamed = parent.join(random_unused_name(&parent, len));
+        fs::rename(&*current, &renamed)
+            .with_context(|| format!("Failed to rename to {}", renamed.display()))
+            .map_err(|e| (RemovalStage::Rename, e))?;
+        *current = renamed;
+        sync_directory(&parent).map_err(|e| (RemovalStage::Rename, e))?;
+    }
+
+    report_stage(target, json_mode, "Truncating file to zero length");
+    OpenOptions::new()
+        .write(true)
+        .open(&*current)
+        .and_then(|file| {
+            file.set_len(0)?;
+            file.sync_all()
+        })
+        .context("Failed to truncate file")
+        .map_err(|e| (RemovalStage::Truncate, e))?;
+
+    report_stage(target, json_mode, "Syncing parent directory");
+    sync_directory(&parent).map_err(|e| (RemovalStage::SyncDirectory, e))?;
+
+    report_stage(target, json_mode, "Unlinking file");
+    fs::remove_file(&*current)
+        .context("Failed to unlink file")
+        .map_err(|e| (RemovalStage::Unlink, e))?;
+    sync_directory(&parent).map_err(|e| (RemovalStage::Unlink, e))?;
+
+    report_stage(target, json_mode, "File securely removed");
+    Ok(())
+}
+
+/// Pick a random alphanumeric name of `len` characters that doesn't exist in `dir`
+fn random_unused_name(dir: &Path, len: usize) -> String {
+    loop {
+        let name: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(len)
+            .map(char::from)
+            .collect();
+        if !dir.join(&name).exists() {
+            return name;
+        }
+    }
+}
+
+/// Flush directory entry changes to disk (no-op where directories can't be synced)
+fn sync_directory(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        fs::File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .with_context(|| format!("Failed to sync directory {}", dir.display()))?;
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+    }
+    Ok(())
+}
+
+fn report_stage(target: &str, json_mode: bool, message: &str) {
+    if json_mode {
+        let _ = emit_target_event(
+            target,
+            &ProgressEvent::Info {
+                message: message.to_string(),
+            },
+        );
+    } else {
+        println!("{}", message);
+    }
+}
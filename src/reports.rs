@@ -0,0 +1,58 @@
+//! State of a wipe target reported in events and results, on every platform,
+//! while the code that reads it is only built where it applies
+
+use serde::{Deserialize, Serialize};
+
+/// Capacity an ATA drive hides below its native size, found by `--reset-hpa`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HiddenArea {
+    pub sector_size: u32,
+    /// Sectors the drive reported before the reset
+    pub visible_sectors: u64,
+    /// Sectors up to the native max address, visible once the HPA is lifted
+    pub native_sectors: u64,
+    /// Bytes of the Host Protected Area, now part of the wiped capacity
+    pub hpa_bytes: u64,
+    /// Sectors the Device Configuration Overlay allows, if the drive reports them
+    pub dco_sectors: Option<u64>,
+    /// Bytes hidden by the DCO beyond the native max address. They stay hidden,
+    /// since removing a DCO (DEVICE CONFIGURATION RESTORE) is permanent.
+    pub dco_bytes: Option<u64>,
+}
+
+/// Key SMART values of an ATA drive at one point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartStatus {
+    /// Overall health assessment (SMART RETURN STATUS); `false` means FAILED
+    pub passed: bool,
+    pub reallocated_sectors: Option<u64>,
+    pub pending_sectors: Option<u64>,
+    pub power_on_hours: Option<u64>,
+}
+
+/// SMART status of a drive before and after it was wiped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartReport {
+    pub before: SmartStatus,
+    pub after: Option<SmartStatus>,
+    /// The reallocated or pending sector count went up during the wipe, a sign
+    /// that the drive is failing
+    pub counts_changed: bool,
+}
+
+/// BitLocker state of a volume target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BitLockerInfo {
+    /// The volume has not been unlocked, so its filesystem is inaccessible
+    pub locked: bool,
+}
+
+impl BitLockerInfo {
+    pub fn state(&self) -> &'static str {
+        if self.locked {
+            "locked"
+        } else {
+            "unlocked"
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! SMART health checks of ATA disks before and after a wipe, for `--smart` on Linux
+
+use anyhow::{Context, Result};
+use std::{fs::OpenOptions, path::Path};
+
+use crate::{
+    ata::{return_lba, send, AtaCommand},
+    reports::{SmartReport, SmartStatus},
+};
+
+/// SMART attribute IDs read from the drive
+const REALLOCATED_SECTORS: u8 = 5;
+const POWER_ON_HOURS: u8 = 9;
+const PENDING_SECTORS: u8 = 197;
+
+const ATA_SMART: u8 = 0xb0;
+const SMART_READ_DATA: u16 = 0xd0;
+const SMART_RETURN_STATUS: u16 = 0xda;
+/// LBA mid/high signature (0x4f/0xc2) that SMART commands must carry
+const SMART_LBA: u64 = 0xc2_4f00;
+/// LBA mid/high returned by SMART RETURN STATUS when a threshold is exceeded
+const SMART_THRESHOLD_EXCEEDED: u64 = 0x2c_f400;
+
+impl SmartReport {
+    pub fn new(before: SmartStatus) -> Self {
+        SmartReport {
+            before,
+            after: None,
+            counts_changed: false,
+        }
+    }
+
+    pub fn record_after(&mut self, after: SmartStatus) {
+        let increased = |before: Option<u64>, after: Option<u64>| matches!((before, after), (Some(before), Some(after)) if after > before);
+        self.counts_changed = increased(self.before.reallocated_sectors, after.reallocated_sectors)
+            || increased(self.before.pending_sectors, after.pending_sectors);
+        self.after = Some(after);
+    }
+}
+
+/// Build a status from the health assessment and the 512-byte SMART READ DATA
+/// response, whose attribute table holds 30 entries of 12 bytes from offset 2
+fn status_from_smart_data(passed: bool, data: &[u8; 512]) -> SmartStatus {
+    let raw_value = |id: u8, bytes: usize| {
+        data[2..362]
+            .chunks_exact(12)
+            .find(|attribute| attribute[0] == id)
+            .map(|attribute| {
+                attribute[5..5 + bytes]
+                    .iter()
+                    .rev()
+                    .fold(0u64, |value, &byte| value << 8 | u64::from(byte))
+            })
+    };
+
+    SmartStatus {
+        passed,
+        reallocated_sectors: raw_value(REALLOCATED_SECTORS, 6),
+        pending_sectors: raw_value(PENDING_SECTORS, 6),
+        // Some vendors keep minutes or milliseconds in the upper raw bytes
+        power_on_hours: raw_value(POWER_ON_HOURS, 4),
+    }
+}
+
+/// Read the SMART health and key attributes of the ATA disk `path`
+pub fn read_smart(path: &Path) -> Result<SmartStatus> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {} for SMART", path.display()))?;
+
+    let mut data = [0u8; 512];
+    let sense = send(
+        &file,
+        &AtaCommand {
+            command: ATA_SMART,
+            features: SMART_RETURN_STATUS,
+            lba: SMART_LBA,
+            extend: false,
+            data_in: false,
+        },
+        &mut data,
+    )
+    .with_context(|| {
+        format!(
+            "{} did not return its SMART status; is it an ATA drive with SMART enabled?",
+            path.display()
+        )
+    })?;
+    let passed = return_lba(&sense).is_none_or(|lba| lba & 0xff_ff00 != SMART_THRESHOLD_EXCEEDED);
+
+    send(
+        &file,
+        &AtaCommand {
+            command: ATA_SMART,
+            features: SMART_READ_DATA,
+            lba: SMART_LBA,
+            extend: false,
+            data_in: true,
+        },
+        &mut data,
+    )
+    .with_context(|| format!("Failed to read the SMART data of {}", path.display()))?;
+
+    Ok(status_from_smart_data(passed, &data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn smart_data(attributes: &[(u8, [u8; 6])]) -> [u8; 512] {
+        let mut data = [0u8; 512];
+        for (index, (id, raw)) in attributes.iter().enumerate() {
+            let entry = &mut data[2 + index * 12..2 + (index + 1) * 12];
+            entry[0] = *id;
+            entry[3] = 100;
+            entry[5..11].copy_from_slice(raw);
+        }
+        data
+    }
+
+    #[test]
+    fn test_smart_attributes_and_changes() {
+        let before = status_from_smart_data(
+            true,
+            &smart_data(&[
+                (REALLOCATED_SECTORS, [0, 0, 0, 0, 0, 0]),
+                // 12345 hours, with minutes in the upper bytes
+                (POWER_ON_HOURS, [0x39, 0x30, 0, 0, 0x1e, 0]),
+                (PENDING_SECTORS, [2, 0, 0, 0, 0, 0]),
+            ]),
+        );
+        assert_eq!(before.reallocated_sectors, Some(0));
+        assert_eq!(before.power_on_hours, Some(12345));
+        assert_eq!(before.pending_sectors, Some(2));
+
+        let mut report = SmartReport::new(before.clone());
+        report.record_after(before.clone());
+        assert!(!report.counts_changed);
+
+        report.record_after(status_from_smart_data(
+            true,
+            &smart_data(&[
+                (REALLOCATED_SECTORS, [8, 0, 0, 0, 0, 0]),
+                (PENDING_SECTORS, [0, 0, 0, 0, 0, 0]),
+            ]),
+        ));
+        assert!(report.counts_changed);
+        assert_eq!(report.after.unwrap().power_on_hours, None);
+    }
+}
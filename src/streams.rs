@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use indicatif::MultiProgress;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    os::windows::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+use winapi::{
+    shared::{
+        minwindef::LPVOID,
+        winerror::{ERROR_HANDLE_EOF, ERROR_INVALID_PARAMETER},
+    },
+    um::{
+        fileapi::{FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard},
+        handleapi::INVALID_HANDLE_VALUE,
+    },
+};
+
+use crate::wipe::{WipeContext, WipeOptions};
+
+/// Name of the unnamed main stream, which is wiped as the file itself
+const MAIN_STREAM_NAME: &str = "::$DATA";
+
+/// An NTFS alternate data stream of a wiped file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlternateStream {
+    /// Stream name as reported by NTFS, e.g. `:Zone.Identifier:$DATA`
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+/// WIN32_FIND_STREAM_DATA, which winapi does not define
+#[repr(C)]
+struct Win32FindStreamData {
+    stream_size: i64,
+    stream_name: [u16; 260 + 36],
+}
+
+/// Enumerate the named data streams of a file with FindFirstStreamW/FindNextStreamW
+pub fn list_alternate_streams(path: &Path) -> Result<Vec<AlternateStream>> {
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut data: Win32FindStreamData = unsafe { std::mem::zeroed() };
+
+    let handle = unsafe {
+        FindFirstStreamW(
+            wide_path.as_ptr(),
+            FindStreamInfoStandard,
+            &mut data as *mut _ as LPVOID,
+            0,
+        )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+        let error = io::Error::last_os_error();
+        // No streams at all, or a filesystem without stream support (FAT, network shares)
+        if matches!(
+            error.raw_os_error(),
+            Some(code) if code == ERROR_HANDLE_EOF as i32 || code == ERROR_INVALID_PARAMETER as i32
+        ) {
+            return Ok(Vec::new());
+        }
+        return Err(error)
+            .with_context(|| format!("Failed to enumerate data streams of {}", path.display()));
+    }
+
+    let mut streams = Vec::new();
+    loop {
+        let name_len = data
+            .stream_name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(data.stream_name.len());
+        let name = String::from_utf16_lossy(&data.stream_name[..name_len]);
+
+        if name != MAIN_STREAM_NAME {
+            streams.push(AlternateStream {
+                name,
+                size_bytes: data.stream_size as u64,
+            });
+        }
+
+        if unsafe { FindNextStreamW(handle, &mut data as *mut _ as LPVOID) } == 0 {
+            break;
+        }
+    }
+
+    unsafe {
+        FindClose(handle);
+    }
+
+    Ok(streams)
+}
+
+/// Overwrite every alternate data stream of a file with the selected algorithm
+pub fn wipe_alternate_streams(
+    path: &Path,
+    options: &WipeOptions,
+    multi_progress: Option<&MultiProgress>,
+) -> Result<Vec<AlternateStream>> {
+    let streams = list_alternate_streams(path)?;
+
+    // Streams go away with the file, so they are only overwritten, never removed
+    let stream_options = WipeOptions {
+        remove: false,
+        sparse_aware: false,
+        fill_to_size: None,
+        ..options.clone()
+    };
+
+    for stream in streams.iter().filter(|stream| stream.size_bytes > 0) {
+        let mut stream_path = path.as_os_str().to_owned();
+        stream_path.push(&stream.name);
+        let stream_path = PathBuf::from(stream_path);
+
+        if !options.json_mode && multi_progress.is_none() {
+            println!(
+                "\nWiping alternate data stream {} ({} bytes)",
+                stream.name, stream.size_bytes
+            );
+        }
+
+        let context = WipeContext::new(&stream_path, false, stream_options.clone())?;
+        let mut context = match multi_progress {
+            Some(multi_progress) => context.with_multi_progress(multi_progress.clone()),
+            None => context,
+        };
+        context
+            .wipe()
+            .with_context(|| format!("Failed to wipe alternate data stream {}", stream.name))?;
+    }
+
+    Ok(streams)
+}
@@ -1,5 +1,8 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::progress::to_json;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemInfo {
@@ -30,6 +33,56 @@ pub struct StorageDevice {
     pub device_type: String,
     pub mount_point: Option<String>,
     pub file_system: Option<String>,
+    pub removable: Option<bool>,
+}
+
+/// Lightweight machine context attached to wipe events, without the storage enumeration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachineInfo {
+    pub hostname: String,
+    pub os_name: String,
+    pub os_version: String,
+    pub architecture: String,
+    pub target_device: Option<DeviceIdentity>,
+}
+
+/// Identity of the physical device backing a wipe target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceIdentity {
+    pub device_path: String,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+}
+
+impl DeviceIdentity {
+    /// Human-readable "vendor model, S/N serial" label, if anything is known
+    pub fn label(&self) -> Option<String> {
+        describe_device(
+            self.vendor.as_deref(),
+            self.model.as_deref(),
+            self.serial.as_deref(),
+        )
+    }
+}
+
+/// Format whichever of vendor, model and serial are known into one label
+pub fn describe_device(
+    vendor: Option<&str>,
+    model: Option<&str>,
+    serial: Option<&str>,
+) -> Option<String> {
+    let name = [vendor, model]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    match (name.is_empty(), serial) {
+        (false, Some(serial)) => Some(format!("{}, S/N {}", name, serial)),
+        (false, None) => Some(name),
+        (true, Some(serial)) => Some(format!("S/N {}", serial)),
+        (true, None) => None,
+    }
 }
 
 pub fn get_system_info() -> Result<SystemInfo> {
@@ -51,6 +104,95 @@ pub fn get_system_info() -> Result<SystemInfo> {
     })
 }
 
+/// Gather the machine context for a wipe of `target`
+pub fn get_machine_info(target: &Path) -> MachineInfo {
+    let os_info = get_os_info();
+
+    MachineInfo {
+        hostname: get_hostname(),
+        os_name: os_info.0,
+        os_version: os_info.1,
+        architecture: std::env::consts::ARCH.to_string(),
+        target_device: get_device_identity(target),
+    }
+}
+
+/// Resolve the vendor/model/serial of the disk backing a device or file
+#[cfg(target_os = "linux")]
+pub fn get_device_identity(path: &Path) -> Option<DeviceIdentity> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let metadata = std::fs::metadata(path).ok()?;
+    // Block devices identify themselves through rdev, regular files through their filesystem
+    let dev = if metadata.file_type().is_block_device() {
+        metadata.rdev()
+    } else {
+        metadata.dev()
+    };
+
+    let mut sys_dir = std::fs::canonicalize(format!(
+        "/sys/dev/block/{}:{}",
+        libc::major(dev),
+        libc::minor(dev)
+    ))
+    .ok()?;
+
+    // Partitions carry no model/serial, the parent disk does
+    if sys_dir.join("partition").exists() {
+        sys_dir = sys_dir.parent()?.to_path_buf();
+    }
+
+    let read_attr = |attr: &str| {
+        std::fs::read_to_string(sys_dir.join(attr))
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+
+    Some(DeviceIdentity {
+        device_path: format!("/dev/{}", sys_dir.file_name()?.to_string_lossy()),
+        vendor: read_attr("device/vendor"),
+        model: read_attr("device/model"),
+        serial: crate::platform::linux::disk_serial(&sys_dir),
+    })
+}
+
+/// Resolve the vendor/model/serial of a drive or volume through the storage driver.
+/// Files resolve to the volume of their drive letter.
+#[cfg(windows)]
+pub fn get_device_identity(path: &Path) -> Option<DeviceIdentity> {
+    use crate::platform::windows;
+    use std::path::{Component, Prefix};
+
+    let device_path = if windows::is_windows_device_path(path) {
+        path.to_string_lossy().to_string()
+    } else {
+        let canonical = std::fs::canonicalize(path).ok()?;
+        match canonical.components().next()? {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                    format!(r"\\.\{}:", letter as char)
+                }
+                _ => return None,
+            },
+            _ => return None,
+        }
+    };
+
+    let properties = windows::storage_properties(&device_path)?;
+    Some(DeviceIdentity {
+        device_path,
+        vendor: properties.vendor,
+        model: properties.model,
+        serial: properties.serial,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn get_device_identity(_path: &Path) -> Option<DeviceIdentity> {
+    None
+}
+
 fn get_os_info() -> (String, String) {
     #[cfg(unix)]
     {
@@ -116,7 +258,7 @@ fn get_os_info() -> (String, String) {
     }
 }
 
-fn get_hostname() -> String {
+pub fn get_hostname() -> String {
     #[cfg(unix)]
     {
         use std::ffi::CStr;
@@ -135,14 +277,26 @@ fn get_hostname() -> String {
 
     #[cfg(windows)]
     {
-        use std::process::Command;
+        use winapi::um::sysinfoapi::{ComputerNamePhysicalDnsHostname, GetComputerNameExW};
 
-        Command::new("hostname")
-            .output()
-            .ok()
-            .and_then(|output| String::from_utf8(output.stdout).ok())
-            .map(|s| s.trim().to_string())
-            .unwrap_or_else(|| "unknown".to_string())
+        unsafe {
+            // The first call fails and reports the length needed, including the NUL
+            let mut len = 0;
+            GetComputerNameExW(
+                ComputerNamePhysicalDnsHostname,
+                std::ptr::null_mut(),
+                &mut len,
+            );
+            let mut name = vec![0u16; len as usize];
+            if len > 0
+                && GetComputerNameExW(ComputerNamePhysicalDnsHostname, name.as_mut_ptr(), &mut len)
+                    != 0
+            {
+                String::from_utf16_lossy(&name[..len as usize])
+            } else {
+                "unknown".to_string()
+            }
+        }
     }
 
     #[cfg(not(any(unix, windows)))]
@@ -273,64 +427,7 @@ fn get_cpu_info() -> Result<CpuInfo> {
 
     #[cfg(target_os = "windows")]
     {
-        use std::process::Command;
-
-        let model_name = Command::new("wmic")
-            .args(&["cpu", "get", "name", "/value"])
-            .output()
-            .ok()
-            .and_then(|output| String::from_utf8(output.stdout).ok())
-            .and_then(|s| {
-                for line in s.lines() {
-                    if line.starts_with("Name=") {
-                        return Some(line.strip_prefix("Name=").unwrap_or("").trim().to_string());
-                    }
-                }
-                None
-            });
-
-        let physical_cores = Command::new("wmic")
-            .args(&["cpu", "get", "NumberOfCores", "/value"])
-            .output()
-            .ok()
-            .and_then(|output| String::from_utf8(output.stdout).ok())
-            .and_then(|s| {
-                for line in s.lines() {
-                    if line.starts_with("NumberOfCores=") {
-                        return line.strip_prefix("NumberOfCores=")
-                            .unwrap_or("")
-                            .trim()
-                            .parse()
-                            .ok();
-                    }
-                }
-                None
-            });
-
-        let frequency_mhz = Command::new("wmic")
-            .args(&["cpu", "get", "MaxClockSpeed", "/value"])
-            .output()
-            .ok()
-            .and_then(|output| String::from_utf8(output.stdout).ok())
-            .and_then(|s| {
-                for line in s.lines() {
-                    if line.starts_with("MaxClockSpeed=") {
-                        return line.strip_prefix("MaxClockSpeed=")
-                            .unwrap_or("")
-                            .trim()
-                            .parse()
-                            .ok();
-                    }
-                }
-                None
-            });
-
-        Ok(CpuInfo {
-            logical_cores,
-            physical_cores,
-            model_name,
-            frequency_mhz,
-        })
+        Ok(windows_cpu_info(&read_windows_cpu_sources(), logical_cores))
     }
 
     #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
@@ -344,6 +441,129 @@ fn get_cpu_info() -> Result<CpuInfo> {
     }
 }
 
+/// Raw data behind the Windows CPU information, gathered by
+/// `read_windows_cpu_sources` and interpreted by `windows_cpu_info`
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Debug, Default)]
+struct WindowsCpuSources {
+    /// `ProcessorNameString` of `HKLM\HARDWARE\DESCRIPTION\System\CentralProcessor\0`
+    processor_name: Option<String>,
+    /// `~MHz` of the same key, the rated processor frequency
+    mhz: Option<u32>,
+    /// GetLogicalProcessorInformationEx(RelationProcessorCore) output
+    core_records: Vec<u8>,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+fn windows_cpu_info(sources: &WindowsCpuSources, logical_cores: usize) -> CpuInfo {
+    let physical_cores = count_processor_core_records(&sources.core_records);
+
+    CpuInfo {
+        logical_cores,
+        physical_cores: (physical_cores > 0).then_some(physical_cores),
+        model_name: sources
+            .processor_name
+            .as_deref()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string),
+        frequency_mhz: sources.mhz.filter(|&mhz| mhz > 0).map(u64::from),
+    }
+}
+
+/// Count the processor core entries in a GetLogicalProcessorInformationEx buffer.
+/// Entries have variable length and start with their relationship and size.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn count_processor_core_records(buffer: &[u8]) -> usize {
+    const RELATION_PROCESSOR_CORE: u32 = 0;
+
+    let mut cores = 0;
+    let mut offset = 0;
+    while let Some(header) = buffer.get(offset..offset + 8) {
+        let relationship = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if size < 8 {
+            break;
+        }
+        if relationship == RELATION_PROCESSOR_CORE {
+            cores += 1;
+        }
+        offset += size;
+    }
+    cores
+}
+
+/// Read the CPU registry values and core records. `wmic` is gone from recent
+/// Windows releases, so this uses the registry and Win32 APIs directly.
+#[cfg(windows)]
+fn read_windows_cpu_sources() -> WindowsCpuSources {
+    use winapi::{
+        shared::{minwindef::DWORD, winerror::ERROR_SUCCESS},
+        um::{
+            sysinfoapi::GetLogicalProcessorInformationEx,
+            winnt::RelationProcessorCore,
+            winreg::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD, RRF_RT_REG_SZ},
+        },
+    };
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    let key = wide(r"HARDWARE\DESCRIPTION\System\CentralProcessor\0");
+    let mut sources = WindowsCpuSources::default();
+
+    unsafe {
+        let mut name = [0u16; 256];
+        let mut size = std::mem::size_of_val(&name) as DWORD;
+        if RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            key.as_ptr(),
+            wide("ProcessorNameString").as_ptr(),
+            RRF_RT_REG_SZ,
+            std::ptr::null_mut(),
+            name.as_mut_ptr() as *mut _,
+            &mut size,
+        ) == ERROR_SUCCESS as i32
+        {
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            sources.processor_name = Some(String::from_utf16_lossy(&name[..len]));
+        }
+
+        let mut mhz: DWORD = 0;
+        let mut size = std::mem::size_of::<DWORD>() as DWORD;
+        if RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            key.as_ptr(),
+            wide("~MHz").as_ptr(),
+            RRF_RT_REG_DWORD,
+            std::ptr::null_mut(),
+            &mut mhz as *mut DWORD as *mut _,
+            &mut size,
+        ) == ERROR_SUCCESS as i32
+        {
+            sources.mhz = Some(mhz);
+        }
+
+        // The first call fails and reports the buffer size needed
+        let mut length: DWORD = 0;
+        GetLogicalProcessorInformationEx(RelationProcessorCore, std::ptr::null_mut(), &mut length);
+        let mut records = vec![0u8; length as usize];
+        if length > 0
+            && GetLogicalProcessorInformationEx(
+                RelationProcessorCore,
+                records.as_mut_ptr() as *mut _,
+                &mut length,
+            ) != 0
+        {
+            records.truncate(length as usize);
+            sources.core_records = records;
+        }
+    }
+
+    sources
+}
+
 fn get_memory_info() -> (Option<u64>, Option<u64>) {
     #[cfg(target_os = "linux")]
     {
@@ -355,7 +575,7 @@ fn get_memory_info() -> (Option<u64>, Option<u64>) {
         for line in meminfo.lines() {
             if let Some((key, value)) = line.split_once(':') {
                 let key = key.trim();
-                let value_parts: Vec<&str> = value.trim().split_whitespace().collect();
+                let value_parts: Vec<&str> = value.split_whitespace().collect();
 
                 if let Some(value_str) = value_parts.first() {
                     if let Ok(kb) = value_str.parse::<u64>() {
@@ -429,7 +649,7 @@ fn get_storage_devices() -> Result<Vec<StorageDevice>> {
                     let size_kb = parts[2].parse::<u64>().unwrap_or(0);
 
                     // Skip partitions of main devices (simple heuristic)
-                    if !device_name.chars().last().map_or(false, |c| c.is_numeric()) {
+                    if !device_name.chars().last().is_some_and(|c| c.is_numeric()) {
                         continue;
                     }
 
@@ -444,6 +664,8 @@ fn get_storage_devices() -> Result<Vec<StorageDevice>> {
                     let mount_point = get_mount_point(&device_path);
                     let file_system = get_file_system(&device_path);
 
+                    let removable = crate::platform::is_removable_device(Path::new(&device_path));
+
                     devices.push(StorageDevice {
                         name: device_name.to_string(),
                         device_path,
@@ -451,6 +673,7 @@ fn get_storage_devices() -> Result<Vec<StorageDevice>> {
                         device_type: "block".to_string(),
                         mount_point,
                         file_system,
+                        removable,
                     });
                 }
             }
@@ -465,6 +688,7 @@ fn get_storage_devices() -> Result<Vec<StorageDevice>> {
                 if let Ok(info) = crate::platform::windows::get_drive_info(&drive_path) {
                     devices.push(StorageDevice {
                         name: drive_path.clone(),
+                        removable: crate::platform::is_removable_device(Path::new(&drive_path)),
                         device_path: drive_path,
                         size_bytes: Some(info.size_bytes),
                         device_type: "physical".to_string(),
@@ -479,6 +703,7 @@ fn get_storage_devices() -> Result<Vec<StorageDevice>> {
             for drive_path in logical_drives {
                 devices.push(StorageDevice {
                     name: drive_path.clone(),
+                    removable: crate::platform::is_removable_device(Path::new(&drive_path)),
                     device_path: drive_path,
                     size_bytes: None,
                     device_type: "logical".to_string(),
@@ -489,9 +714,77 @@ fn get_storage_devices() -> Result<Vec<StorageDevice>> {
         }
     }
 
+    #[cfg(target_os = "macos")]
+    {
+        use std::process::Command;
+
+        let diskutil_plist = |args: &[&str]| -> Option<plist::Dictionary> {
+            let output = Command::new("diskutil").args(args).output().ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            plist::from_bytes::<plist::Value>(&output.stdout)
+                .ok()?
+                .into_dictionary()
+        };
+
+        // Disks, partitions, and APFS volumes alike
+        let identifiers: Vec<String> = diskutil_plist(&["list", "-plist"])
+            .and_then(|list| list.get("AllDisks")?.as_array().cloned())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|identifier| identifier.into_string())
+            .collect();
+
+        for identifier in identifiers {
+            if let Some(info) = diskutil_plist(&["info", "-plist", &identifier]) {
+                devices.extend(storage_device_from_diskutil_info(&info));
+            }
+        }
+    }
+
     Ok(devices)
 }
 
+/// Build a storage device from the `diskutil info -plist` output for one disk,
+/// partition, or volume. The type combines where the disk is attached with its
+/// medium when macOS knows it, e.g. `internal-ssd` or `external`.
+#[cfg(unix)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn storage_device_from_diskutil_info(info: &plist::Dictionary) -> Option<StorageDevice> {
+    let string = |key: &str| {
+        info.get(key)
+            .and_then(|value| value.as_string())
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let boolean = |key: &str| info.get(key).and_then(|value| value.as_boolean());
+
+    let name = string("DeviceIdentifier")?;
+    let location = match boolean("Internal") {
+        Some(true) => "internal",
+        Some(false) => "external",
+        None => "disk",
+    };
+    let device_type = match boolean("SolidState") {
+        Some(true) => format!("{}-ssd", location),
+        Some(false) => format!("{}-hdd", location),
+        None => location.to_string(),
+    };
+
+    Some(StorageDevice {
+        device_path: string("DeviceNode").unwrap_or_else(|| format!("/dev/{}", name)),
+        name,
+        size_bytes: ["TotalSize", "Size"]
+            .into_iter()
+            .find_map(|key| info.get(key)?.as_unsigned_integer()),
+        device_type,
+        mount_point: string("MountPoint"),
+        file_system: string("FilesystemType"),
+        removable: boolean("RemovableMedia").or(boolean("Removable")),
+    })
+}
+
 #[cfg(target_os = "linux")]
 fn get_mount_point(device_path: &str) -> Option<String> {
     if let Ok(mounts) = std::fs::read_to_string("/proc/mounts") {
@@ -530,7 +823,7 @@ fn get_file_system(_device_path: &str) -> Option<String> {
 
 pub fn display_system_info(system_info: &SystemInfo, json: bool) -> Result<()> {
     if json {
-        let json_str = serde_json::to_string_pretty(system_info)?;
+        let json_str = to_json(system_info)?;
         println!("{}", json_str);
     } else {
         println!("System Information:");
@@ -577,6 +870,9 @@ pub fn display_system_info(system_info: &SystemInfo, json: bool) -> Result<()> {
                 if let Some(ref fs) = device.file_system {
                     print!(" ({})", fs);
                 }
+                if device.removable == Some(true) {
+                    print!(" [removable]");
+                }
                 println!();
             }
         }
@@ -584,3 +880,73 @@ pub fn display_system_info(system_info: &SystemInfo, json: bool) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_storage_device_from_diskutil_info() {
+        let info: plist::Dictionary =
+            plist::from_bytes(include_bytes!("../tests/fixtures/diskutil_info.plist")).unwrap();
+        let device = storage_device_from_diskutil_info(&info).unwrap();
+
+        assert_eq!(device.name, "disk3s5");
+        assert_eq!(device.device_path, "/dev/disk3s5");
+        assert_eq!(device.size_bytes, Some(499963174912));
+        assert_eq!(device.device_type, "internal-ssd");
+        assert_eq!(device.mount_point.as_deref(), Some("/System/Volumes/Data"));
+        assert_eq!(device.file_system.as_deref(), Some("apfs"));
+        assert_eq!(device.removable, Some(false));
+    }
+
+    /// A GetLogicalProcessorInformationEx entry of `size` bytes
+    fn processor_record(relationship: u32, size: u32) -> Vec<u8> {
+        let mut record = vec![0u8; size as usize];
+        record[0..4].copy_from_slice(&relationship.to_le_bytes());
+        record[4..8].copy_from_slice(&size.to_le_bytes());
+        record
+    }
+
+    #[test]
+    fn test_windows_cpu_info_from_sources() {
+        // Four cores, with a cache entry mixed in that must not be counted
+        let mut core_records = Vec::new();
+        for _ in 0..4 {
+            core_records.extend(processor_record(0, 48));
+        }
+        core_records.extend(processor_record(2, 56));
+
+        let info = windows_cpu_info(
+            &WindowsCpuSources {
+                processor_name: Some("Intel(R) Core(TM) i7-8650U CPU @ 1.90GHz   ".to_string()),
+                mhz: Some(2112),
+                core_records,
+            },
+            8,
+        );
+        assert_eq!(info.logical_cores, 8);
+        assert_eq!(info.physical_cores, Some(4));
+        assert_eq!(
+            info.model_name.as_deref(),
+            Some("Intel(R) Core(TM) i7-8650U CPU @ 1.90GHz")
+        );
+        assert_eq!(info.frequency_mhz, Some(2112));
+
+        // Missing registry values and API output leave the fields unset
+        let info = windows_cpu_info(&WindowsCpuSources::default(), 2);
+        assert_eq!(info.physical_cores, None);
+        assert_eq!(info.model_name, None);
+        assert_eq!(info.frequency_mhz, None);
+
+        // Truncated or zero-sized entries end the scan instead of looping
+        assert_eq!(
+            count_processor_core_records(&processor_record(0, 48)[..6]),
+            0
+        );
+        let mut record = processor_record(0, 48);
+        record[4..8].fill(0);
+        assert_eq!(count_processor_core_records(&record), 0);
+    }
+}
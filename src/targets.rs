@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Parse a duration such as `90d`, `12h`, `30m`, `45s` or `2w`
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}', expected e.g. 90d or 12h", value))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" | "" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid duration unit '{}', expected one of s, m, h, d, w",
+                unit
+            ))
+        }
+    };
+
+    Ok(Duration::from_secs(number * seconds))
+}
+
+/// Criteria deciding which files found under the targets get wiped
+#[derive(Debug, Clone, Default)]
+pub struct TargetFilter {
+    /// Only files last modified at least this long ago
+    pub older_than: Option<Duration>,
+    /// Only files last modified less than this long ago
+    pub newer_than: Option<Duration>,
+}
+
+impl TargetFilter {
+    fn is_active(&self) -> bool {
+        self.older_than.is_some() || self.newer_than.is_some()
+    }
+
+    fn matches(&self, metadata: &fs::Metadata, now: SystemTime) -> bool {
+        if !self.is_active() {
+            return true;
+        }
+
+        let Ok(modified) = metadata.modified() else {
+            return false;
+        };
+        // SystemTime is UTC-based; a modification time in the future (clock skew)
+        // counts as modified just now
+        let age = now.duration_since(modified).unwrap_or(Duration::ZERO);
+
+        self.older_than.is_none_or(|older_than| age >= older_than)
+            && self.newer_than.is_none_or(|newer_than| age < newer_than)
+    }
+}
+
+/// A file selected for wiping, as reported by `--dry-run`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchedFile {
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+/// Expand directory targets into the files beneath them (with `recursive`) and apply
+/// the age filter to regular files. Devices and missing paths are passed through
+/// unchanged so the caller can validate them.
+pub fn expand_targets(
+    targets: &[PathBuf],
+    recursive: bool,
+    filter: &TargetFilter,
+) -> Result<Vec<PathBuf>> {
+    let now = SystemTime::now();
+    let mut expanded = Vec::new();
+
+    for target in targets {
+        let Ok(metadata) = fs::symlink_metadata(target) else {
+            expanded.push(target.clone());
+            continue;
+        };
+
+        if metadata.is_dir() {
+            if !recursive {
+                anyhow::bail!(
+                    "{} is a directory. Use --recursive to wipe the files beneath it",
+                    target.display()
+                );
+            }
+            collect_files(target, filter, now, &mut expanded)?;
+        } else if metadata.is_file() {
+            if filter.matches(&metadata, now) {
+                expanded.push(target.clone());
+            }
+        } else {
+            expanded.push(target.clone());
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Resolve disks named by serial number (`--by-id`) or through a udev link under
+/// `/dev/disk/` to their current `/dev` node, which can change between boots
+#[cfg(target_os = "linux")]
+pub fn resolve_disk_targets(targets: &[PathBuf], serials: &[String]) -> Result<Vec<PathBuf>> {
+    let mut resolved = Vec::with_capacity(targets.len() + serials.len());
+    for target in targets {
+        if target.starts_with("/dev/disk/") {
+            resolved
+                .push(fs::canonicalize(target).with_context(|| {
+                    format!("Failed to resolve disk link {}", target.display())
+                })?);
+        } else {
+            resolved.push(target.clone());
+        }
+    }
+    for serial in serials {
+        resolved.push(crate::platform::linux::disk_by_serial(serial)?);
+    }
+    Ok(resolved)
+}
+
+/// Walk a directory tree without following symlinks, collecting matching regular files
+fn collect_files(
+    dir: &Path,
+    filter: &TargetFilter,
+    now: SystemTime,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+    // Deterministic order for the prompt, dry runs, and the summary
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let metadata = fs::symlink_metadata(&path)
+            .with_context(|| format!("Failed to read metadata of {}", path.display()))?;
+
+        if metadata.is_dir() {
+            collect_files(&path, filter, now, files)?;
+        } else if metadata.is_file() && filter.matches(&metadata, now) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Sizes of the selected targets; devices and missing paths count as zero
+pub fn matched_files(targets: &[PathBuf]) -> Vec<MatchedFile> {
+    targets
+        .iter()
+        .map(|path| MatchedFile {
+            path: path.display().to_string(),
+            size_bytes: fs::metadata(path)
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .map_or(0, |metadata| metadata.len()),
+        })
+        .collect()
+}
@@ -0,0 +1,120 @@
+//! Capping the write rate of a wipe for `--max-rate`
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::cancel;
+
+/// Writes may run ahead of the rate by as many bytes as it allows in this long
+const BURST: Duration = Duration::from_millis(100);
+/// Longest single sleep, so that a cancellation is still noticed promptly
+const MAX_SLEEP: Duration = Duration::from_millis(100);
+
+/// Token bucket holding the average write rate at or below a cap. Every write
+/// takes its size in tokens, which refill at the capped rate; a write that
+/// overdraws the bucket is followed by a sleep until it is paid back.
+pub struct RateLimiter {
+    bytes_per_second: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second as f64;
+        RateLimiter {
+            bytes_per_second,
+            tokens: bytes_per_second * BURST.as_secs_f64(),
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take `bytes` just written from the bucket, sleeping as long as it is overdrawn
+    pub fn throttle(&mut self, bytes: u64) {
+        self.refill();
+        self.tokens = self.tokens.min(self.bytes_per_second * BURST.as_secs_f64()) - bytes as f64;
+
+        while self.tokens < 0.0 && !cancel::is_cancelled() {
+            thread::sleep(
+                Duration::from_secs_f64(-self.tokens / self.bytes_per_second).min(MAX_SLEEP),
+            );
+            self.refill();
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        self.tokens += now.duration_since(self.last_refill).as_secs_f64() * self.bytes_per_second;
+        self.last_refill = now;
+    }
+}
+
+/// Parse a rate in bytes per second, e.g. `500K`, `200M` or `1.5G`. Suffixes
+/// are binary (M is MiB) like the rates shown in the progress bars, and may be
+/// followed by `B` and `/s`.
+pub fn parse_rate(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let trimmed = trimmed.strip_suffix("/s").unwrap_or(trimmed);
+    let trimmed = trimmed
+        .strip_suffix(['B', 'b'])
+        .filter(|number| !number.is_empty())
+        .unwrap_or(trimmed);
+    let (number, multiplier) = match trimmed.char_indices().last() {
+        Some((index, unit)) if unit.is_ascii_alphabetic() => {
+            let multiplier = match unit.to_ascii_uppercase() {
+                'K' => 1u64 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                'T' => 1 << 40,
+                _ => return Err(format!("unknown unit '{}', expected K, M, G or T", unit)),
+            };
+            (&trimmed[..index], multiplier)
+        }
+        _ => (trimmed, 1),
+    };
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid rate '{}', expected e.g. 200M or 1G", value))?;
+    let bytes_per_second = (number * multiplier as f64) as u64;
+    if !number.is_finite() || bytes_per_second == 0 {
+        return Err("the rate must be at least 1 byte per second".to_string());
+    }
+    Ok(bytes_per_second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("200M"), Ok(200 * 1024 * 1024));
+        assert_eq!(parse_rate("1G"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_rate("1.5g"), Ok(1536 * 1024 * 1024));
+        assert_eq!(parse_rate("500KB/s"), Ok(500 * 1024));
+        assert_eq!(parse_rate("4096"), Ok(4096));
+        assert!(parse_rate("").is_err());
+        assert!(parse_rate("0M").is_err());
+        assert!(parse_rate("10X").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_holds_the_cap() {
+        const RATE: u64 = 4 * 1024 * 1024;
+        let mut limiter = RateLimiter::new(RATE);
+        let start = Instant::now();
+        for _ in 0..32 {
+            limiter.throttle(64 * 1024);
+        }
+
+        // 2 MiB at 4 MiB/s, less the burst written up front
+        let elapsed = start.elapsed().as_secs_f64();
+        let minimum = (2.0 * 1024.0 * 1024.0 - RATE as f64 * BURST.as_secs_f64()) / RATE as f64;
+        assert!(elapsed >= minimum * 0.95, "took only {:.3}s", elapsed);
+        assert!(elapsed < 2.0, "took {:.3}s", elapsed);
+    }
+}
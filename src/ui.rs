@@ -1,29 +1,190 @@
 use anyhow::Result;
+
+#[cfg(windows)]
+use crate::bitlocker;
+use crate::{
+    platform::{has_device_privileges, is_block_device, is_system_device},
+    progress::{emit_event, ProgressEvent, PROTOCOL_VERSION},
+    system::get_device_identity,
+    targets::{matched_files, MatchedFile},
+};
 use crossterm::{
     style::{Color, ResetColor, SetForegroundColor},
     ExecutableCommand,
 };
+use rand::Rng;
 use std::{
     io::{self, Write},
-    path::Path,
+    path::PathBuf,
 };
 
-pub fn confirm_wipe(path: &Path, demo_mode: bool) -> Result<bool> {
+/// Targets listed individually in the confirmation prompt before summarizing the rest
+const MAX_LISTED_TARGETS: usize = 20;
+
+/// Ask the user to confirm the wipe by typing "WIPE", or with `confirm_code` a
+/// random 6-digit code that cannot be typed from muscle memory
+pub fn confirm_wipe(paths: &[PathBuf], demo_mode: bool, confirm_code: bool) -> Result<bool> {
     if demo_mode {
         return Ok(true);
     }
 
     io::stdout().execute(SetForegroundColor(Color::Red))?;
     println!("WARNING: This will PERMANENTLY destroy all data on:");
-    println!("   {}", path.display());
+    for path in paths.iter().take(MAX_LISTED_TARGETS) {
+        // Show which physical device is behind the path so the user can double-check it
+        match get_device_identity(path).and_then(|identity| identity.label()) {
+            Some(label) => println!("   {} ({})", path.display(), label),
+            None => println!("   {}", path.display()),
+        }
+        #[cfg(windows)]
+        if let Some(bitlocker) = bitlocker::detect(path) {
+            println!(
+                "   ^ {} is a BitLocker-encrypted volume ({})",
+                path.display(),
+                bitlocker.state()
+            );
+        }
+    }
+    if paths.len() > MAX_LISTED_TARGETS {
+        println!("   ... and {} more", paths.len() - MAX_LISTED_TARGETS);
+    }
+    if paths.len() > 1 {
+        let total_bytes: u64 = matched_files(paths).iter().map(|f| f.size_bytes).sum();
+        println!(
+            "{} targets, {} bytes ({:.2} MB) of files combined",
+            paths.len(),
+            total_bytes,
+            total_bytes as f64 / 1_048_576.0
+        );
+    }
     println!("This operation CANNOT be undone!");
     io::stdout().execute(ResetColor)?;
     println!();
-    print!("Type 'WIPE' to confirm: ");
+    let expected = if confirm_code {
+        format!("{:06}", rand::thread_rng().gen_range(0..1_000_000))
+    } else {
+        "WIPE".to_string()
+    };
+    if confirm_code {
+        print!("Type the code {} to confirm: ", expected);
+    } else {
+        print!("Type 'WIPE' to confirm: ");
+    }
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+
+    Ok(input.trim() == expected)
+}
+/// Ask the user whether to create a demo file that takes up much of the free space
+pub fn confirm_demo_size(message: &str) -> Result<bool> {
+    print!("{}. Create it anyway? [y/N]: ", message);
     io::stdout().flush()?;
 
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
 
-    Ok(input.trim() == "WIPE")
-}
\ No newline at end of file
+    Ok(matches!(input.trim(), "y" | "Y" | "yes"))
+}
+
+/// Refuse to wipe the device holding the running system unless explicitly allowed
+pub fn check_system_targets(paths: &[PathBuf], allow_system: bool) -> Result<()> {
+    for path in paths.iter().filter(|path| is_system_device(path)) {
+        if !allow_system {
+            anyhow::bail!(
+                "{} holds the running system. Refusing to wipe it without --allow-system",
+                path.display()
+            );
+        }
+
+        io::stdout().execute(SetForegroundColor(Color::Red))?;
+        println!(
+            "WARNING: {} holds the running system! The machine will not boot after this wipe.",
+            path.display()
+        );
+        io::stdout().execute(ResetColor)?;
+    }
+
+    Ok(())
+}
+
+/// Refuse to run when a frontend expects a different JSON event protocol than
+/// this binary emits, before anything is touched
+pub fn check_protocol_version(requested: u32, json_mode: bool) -> Result<()> {
+    if requested == PROTOCOL_VERSION {
+        return Ok(());
+    }
+
+    let message = format!(
+        "Protocol version {} was requested, but this binary emits version {}",
+        requested, PROTOCOL_VERSION
+    );
+    if json_mode {
+        let _ = emit_event(&ProgressEvent::Error {
+            message: message.clone(),
+            code: Some("protocol_mismatch".to_string()),
+            pass: None,
+            errno: None,
+        });
+    }
+    anyhow::bail!(message)
+}
+
+/// Fail early with a clear message when block device targets cannot be opened
+/// for lack of privileges, instead of an access denied error mid-way
+pub fn check_privileges(paths: &[PathBuf], json_mode: bool) -> Result<()> {
+    let Some(path) = paths.iter().find(|path| is_block_device(path)) else {
+        return Ok(());
+    };
+    if has_device_privileges() {
+        return Ok(());
+    }
+
+    let message = if cfg!(windows) {
+        format!(
+            "Wiping {} requires administrator rights. Run the command from an elevated prompt (Run as administrator)",
+            path.display()
+        )
+    } else {
+        format!(
+            "Wiping {} requires root privileges (or CAP_SYS_ADMIN). Try again with sudo",
+            path.display()
+        )
+    };
+
+    if json_mode {
+        let _ = emit_event(&ProgressEvent::Error {
+            message: message.clone(),
+            code: Some("insufficient_privileges".to_string()),
+            pass: None,
+            errno: None,
+        });
+    }
+    anyhow::bail!(message)
+}
+
+/// List the files a wipe would touch without wiping them
+pub fn print_dry_run(files: &[MatchedFile], json: bool) -> Result<()> {
+    let total_bytes = files.iter().map(|f| f.size_bytes).sum();
+
+    if json {
+        emit_event(&ProgressEvent::DryRun {
+            total_bytes,
+            files: files.to_vec(),
+        })?;
+        return Ok(());
+    }
+
+    println!("Dry run: the following targets would be wiped:");
+    for file in files {
+        println!("   {} ({} bytes)", file.path, file.size_bytes);
+    }
+    println!(
+        "{} target(s), {:.2} MB combined. Nothing was written.",
+        files.len(),
+        total_bytes as f64 / 1_048_576.0
+    );
+
+    Ok(())
+}
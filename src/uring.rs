@@ -0,0 +1,149 @@
+//! Queued writes through io_uring, for `--io-backend uring` on Linux
+
+use aligned_vec::{avec_rt, AVec, RuntimeAlign};
+use io_uring::{opcode, types, IoUring};
+use std::{fs::File, io, os::unix::io::AsRawFd};
+
+/// Writes from a fixed set of buffers, keeping up to one write per buffer in
+/// flight. The target file is registered with the ring, and so are the buffers
+/// when the kernel allows it (RLIMIT_MEMLOCK on older kernels); otherwise plain
+/// writes are submitted from the same buffers.
+pub struct UringWriter {
+    ring: IoUring,
+    buffers: Vec<AVec<u8, RuntimeAlign>>,
+    // Indices of buffers that are not part of a write in flight
+    free: Vec<usize>,
+    // Length submitted from each buffer, to detect short writes
+    lengths: Vec<usize>,
+    fixed_buffers: bool,
+}
+
+impl UringWriter {
+    /// Set up a ring with `queue_depth` buffers of `buffer_len` bytes, aligned
+    /// to `alignment` for O_DIRECT
+    pub fn new(
+        file: &File,
+        queue_depth: usize,
+        buffer_len: usize,
+        alignment: usize,
+    ) -> io::Result<Self> {
+        let ring = IoUring::new(queue_depth as u32)?;
+        ring.submitter().register_files(&[file.as_raw_fd()])?;
+
+        let mut buffers: Vec<_> = (0..queue_depth)
+            .map(|_| avec_rt!([alignment]| 0u8; buffer_len))
+            .collect();
+        let iovecs: Vec<libc::iovec> = buffers
+            .iter_mut()
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr().cast(),
+                iov_len: buffer.len(),
+            })
+            .collect();
+        // The buffers live as long as the ring, which unregisters them when dropped
+        let fixed_buffers = unsafe { ring.submitter().register_buffers(&iovecs) }.is_ok();
+
+        Ok(UringWriter {
+            ring,
+            buffers,
+            free: (0..queue_depth).rev().collect(),
+            lengths: vec![0; queue_depth],
+            fixed_buffers,
+        })
+    }
+
+    /// Size of each buffer
+    pub fn buffer_len(&self) -> usize {
+        self.buffers[0].len()
+    }
+
+    /// Number of buffers, and so the maximum number of writes in flight
+    pub fn queue_depth(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Number of writes submitted but not completed yet
+    pub fn in_flight(&self) -> usize {
+        self.queue_depth() - self.free.len()
+    }
+
+    /// A buffer that is free to be filled for the next write
+    pub fn free_buffer(&mut self) -> Option<(usize, &mut [u8])> {
+        let index = *self.free.last()?;
+        Some((index, self.buffers[index].as_mut_slice()))
+    }
+
+    /// Fill every buffer, e.g. with a fixed pattern that stays the same for the whole pass
+    pub fn fill_buffers(&mut self, fill: impl Fn(&mut [u8])) {
+        for buffer in &mut self.buffers {
+            fill(buffer.as_mut_slice());
+        }
+    }
+
+    /// Queue a write of the first `len` bytes of buffer `index` at `offset`. The
+    /// buffer must have come from `free_buffer`.
+    pub fn submit(&mut self, index: usize, offset: u64, len: usize) -> io::Result<()> {
+        let buffer = self.buffers[index].as_ptr();
+        let entry = if self.fixed_buffers {
+            opcode::WriteFixed::new(types::Fixed(0), buffer, len as u32, index as u16)
+                .offset(offset)
+                .build()
+        } else {
+            opcode::Write::new(types::Fixed(0), buffer, len as u32)
+                .offset(offset)
+                .build()
+        };
+
+        // The buffer is not touched again until its completion has been reaped.
+        // Queued entries are submitted together by the next wait_completion.
+        unsafe { self.ring.submission().push(&entry.user_data(index as u64)) }
+            .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+
+        self.free.retain(|&free| free != index);
+        self.lengths[index] = len;
+        Ok(())
+    }
+
+    /// Wait for the next write to complete and free its buffer, returning the
+    /// buffer's index. A failed or short write is an error.
+    pub fn wait_completion(&mut self) -> io::Result<usize> {
+        let want = usize::from(self.ring.completion().is_empty());
+        self.ring.submit_and_wait(want)?;
+        let cqe = self
+            .ring
+            .completion()
+            .next()
+            .ok_or_else(|| io::Error::other("io_uring returned no completion"))?;
+
+        let index = cqe.user_data() as usize;
+        self.free.push(index);
+        let expected = self.lengths[index];
+        match cqe.result() {
+            result if result < 0 => Err(io::Error::from_raw_os_error(-result)),
+            result if (result as usize) < expected => Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                format!("short write of {} of {} bytes", result, expected),
+            )),
+            _ => Ok(index),
+        }
+    }
+
+    /// Wait for every write in flight, discarding their results, so that an
+    /// aborted pass leaves no write behind
+    pub fn drain(&mut self) {
+        while self.in_flight() > 0 {
+            let in_flight = self.in_flight();
+            let _ = self.wait_completion();
+            if self.in_flight() == in_flight {
+                break;
+            }
+        }
+    }
+}
+
+impl Drop for UringWriter {
+    fn drop(&mut self) {
+        // The kernel may still be reading from the buffers
+        self.drain();
+    }
+}
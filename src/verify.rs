@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use std::{
+    fs::File,
+    io::Read,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cancel,
+    pattern::fill_repeating,
+    platform::{self, device_open_path},
+    progress::{emit_target_event, ProgressEvent},
+    wipe::block_device_size,
+};
+
+/// Size of each read from the target
+const VERIFY_CHUNK: usize = 4 * 1024 * 1024;
+/// Interval between progress events in JSON mode
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Outcome of checking a target against a pattern with `--verify-pattern`
+#[derive(Debug, Clone)]
+pub struct PatternCheck {
+    pub size_bytes: u64,
+    pub bytes_verified: u64,
+    /// Offset of the first byte that does not hold the pattern
+    pub mismatch_offset: Option<u64>,
+}
+
+/// Parse a pattern given as hex digits, with an optional `0x` prefix, e.g. `00` or `0xdeadbeef`
+pub fn parse_hex_pattern(value: &str) -> Result<Vec<u8>, String> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err("expected an even number of hex digits, e.g. 00 or 0xdeadbeef".to_string());
+    }
+    if let Some(digit) = digits.chars().find(|digit| !digit.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a hex digit", digit));
+    }
+    if digits.len() > 128 {
+        return Err("a pattern can be at most 64 bytes long".to_string());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&digits[index..index + 2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Lowercase hex form of a pattern, as reported in events and errors
+pub fn pattern_hex(pattern: &[u8]) -> String {
+    pattern.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Read `path` from start to end and check that it holds `pattern` repeated
+/// from offset 0, stopping at the first mismatch. Nothing is written, so this
+/// confirms a wipe done elsewhere without wiping again.
+pub fn verify_pattern(
+    path: &Path,
+    pattern: &[u8],
+    json_mode: bool,
+    multi_progress: Option<&MultiProgress>,
+) -> Result<PatternCheck> {
+    let start = Instant::now();
+    let target = path.display().to_string();
+    let mut file = File::open(device_open_path(path))
+        .with_context(|| format!("Failed to open {} for verification", path.display()))?;
+    let size_bytes = if platform::is_block_device(path) {
+        block_device_size(&file)?
+    } else {
+        file.metadata()
+            .with_context(|| format!("Failed to read metadata of {}", path.display()))?
+            .len()
+    };
+
+    // The expected data runs one pattern length past a chunk, so a chunk that
+    // starts mid-pattern compares against a shifted window
+    let mut expected = vec![0u8; VERIFY_CHUNK + pattern.len()];
+    fill_repeating(&mut expected, pattern);
+    let mut buffer = vec![0u8; VERIFY_CHUNK];
+
+    let pb = if !json_mode {
+        let prefix = if multi_progress.is_some() {
+            format!("{} ", target)
+        } else {
+            String::new()
+        };
+        let pb = ProgressBar::new(size_bytes).with_prefix("0 B/s");
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(&format!(
+                    "{}Verify [{}] {{bar:40.cyan/blue}} {{bytes}}/{{total_bytes}} ({{prefix}}) {{msg}}",
+                    prefix,
+                    pattern_hex(pattern)
+                ))?
+                .progress_chars("█▉▊▋▌▍▎▏  "),
+        );
+        Some(match multi_progress {
+            Some(multi_progress) => multi_progress.add(pb),
+            None => pb,
+        })
+    } else {
+        None
+    };
+
+    let mut offset = 0u64;
+    let mut mismatch_offset = None;
+    let mut last_report = Instant::now();
+    while offset < size_bytes {
+        if cancel::is_cancelled() {
+            anyhow::bail!("Verification cancelled by user at offset {}", offset);
+        }
+
+        let len = std::cmp::min(VERIFY_CHUNK as u64, size_bytes - offset) as usize;
+        file.read_exact(&mut buffer[..len])
+            .with_context(|| format!("Failed to read {} at offset {}", path.display(), offset))?;
+
+        let phase = (offset % pattern.len() as u64) as usize;
+        let window = &expected[phase..phase + len];
+        if buffer[..len] != *window {
+            let index = buffer[..len]
+                .iter()
+                .zip(window)
+                .position(|(actual, expected)| actual != expected)
+                .unwrap_or(0);
+            mismatch_offset = Some(offset + index as u64);
+            offset += index as u64;
+            break;
+        }
+        offset += len as u64;
+
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            let rate = offset as f64 / start.elapsed().as_secs_f64();
+            if let Some(ref pb) = pb {
+                pb.set_position(offset);
+                pb.set_prefix(format!("{}/s", HumanBytes(rate as u64)));
+            } else {
+                let _ = emit_target_event(
+                    &target,
+                    &ProgressEvent::VerifyProgress {
+                        bytes_verified: offset,
+                        total_bytes: size_bytes,
+                        percent: offset as f64 / size_bytes as f64 * 100.0,
+                    },
+                );
+            }
+            last_report = Instant::now();
+        }
+    }
+
+    let matched = mismatch_offset.is_none();
+    if let Some(pb) = pb {
+        pb.set_position(offset);
+        pb.finish_with_message(if matched { "Matched" } else { "Mismatch" });
+    } else {
+        let _ = emit_target_event(
+            &target,
+            &ProgressEvent::VerifyComplete {
+                pattern: pattern_hex(pattern),
+                bytes_verified: offset,
+                duration_seconds: start.elapsed().as_secs_f64(),
+                matched,
+                mismatch_offset,
+            },
+        );
+    }
+
+    Ok(PatternCheck {
+        size_bytes,
+        bytes_verified: offset,
+        mismatch_offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_parse_hex_pattern() {
+        assert_eq!(parse_hex_pattern("00"), Ok(vec![0x00]));
+        assert_eq!(
+            parse_hex_pattern("0xDEadBeef"),
+            Ok(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert!(parse_hex_pattern("").is_err());
+        assert!(parse_hex_pattern("0x").is_err());
+        assert!(parse_hex_pattern("abc").is_err());
+        assert!(parse_hex_pattern("zz").is_err());
+        assert!(parse_hex_pattern("aé0").is_err());
+        assert!(parse_hex_pattern(&"00".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_verify_pattern_finds_first_mismatch() {
+        // Larger than one chunk and not a multiple of the pattern length
+        let pattern = [0x92, 0x49, 0x24];
+        let size = VERIFY_CHUNK * 2 + 1000;
+        let data: Vec<u8> = (0..size).map(|i| pattern[i % pattern.len()]).collect();
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&data).unwrap();
+        let check = verify_pattern(file.path(), &pattern, true, None).unwrap();
+        assert_eq!(check.size_bytes, size as u64);
+        assert_eq!(check.bytes_verified, size as u64);
+        assert_eq!(check.mismatch_offset, None);
+
+        let check = verify_pattern(file.path(), &[0x00], true, None).unwrap();
+        assert_eq!(check.mismatch_offset, Some(0));
+
+        let flipped = VERIFY_CHUNK as u64 + 12345;
+        file.seek(SeekFrom::Start(flipped)).unwrap();
+        file.write_all(&[!data[flipped as usize]]).unwrap();
+        let check = verify_pattern(file.path(), &pattern, true, None).unwrap();
+        assert_eq!(check.mismatch_offset, Some(flipped));
+        assert_eq!(check.bytes_verified, flipped);
+    }
+}
@@ -1,21 +1,38 @@
+use aligned_vec::avec_rt;
 use anyhow::{Context, Result};
 use crossterm::{
     style::{Color, ResetColor, SetForegroundColor},
     ExecutableCommand,
 };
-use indicatif::{ProgressBar, ProgressStyle};
-use rand::{thread_rng, RngCore};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
     fs::{File, OpenOptions},
-    io::{self, Seek, SeekFrom, Write},
+    io::{self, Read, Seek, SeekFrom, Write},
     path::Path,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
     time::{Duration, Instant},
 };
 
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::{
-    algorithms::{get_algorithm_pass_count, get_pass_pattern, get_pattern_name, WipePattern},
-    args::WipeAlgorithm,
-    progress::{emit_event, ProgressEvent},
+    algorithms::{get_pass_plan, PassSpec, WipePattern},
+    args::{SyncMode, WipeAlgorithm},
+    cancel,
+    generator::{range_chunks, PoolBuffer, RandomPool, RandomStream, RngKind},
+    platform::{allocated_ranges, device_open_path, QueueLimits, ZonedModel},
+    priority::PriorityMode,
+    progress::{
+        emit_target_event, BufferTrial, FailedRange, PassRecord, ProgressEvent, ThroughputWindow,
+        PROTOCOL_VERSION,
+    },
+    reports::{BitLockerInfo, HiddenArea},
+    system::{get_machine_info, MachineInfo},
+    throttle::RateLimiter,
 };
 
 #[cfg(unix)]
@@ -24,28 +41,125 @@ use std::os::unix::fs::OpenOptionsExt;
 #[cfg(unix)]
 use std::os::unix::io::AsRawFd;
 
-/// Get optimal buffer size based on device type and available memory
-fn get_optimal_buffer_size(is_block_device: bool, requested_size: usize) -> usize {
+#[cfg(target_os = "linux")]
+use crate::{args::IoBackend, uring::UringWriter};
+
+/// Smallest buffer `--buffer-size` accepts, in KB
+const MIN_BUFFER_KB: usize = 4;
+
+/// Largest buffer `--buffer-size` accepts, in KB, before the available memory is even checked
+const MAX_BUFFER_KB: usize = 4 * 1024 * 1024;
+
+/// Parse a size such as `64K`, `4M`, `1.5G` or `1T` into bytes. Suffixes are
+/// binary and may be followed by `B`, and a number with just `B` is in bytes. A
+/// plain number is a number of `plain_unit` bytes, so that flags which took a
+/// number of KB or MB still do.
+pub fn parse_size(value: &str, plain_unit: u64) -> Result<u64, String> {
+    let expected = || format!("invalid size '{}', expected a size like 8M", value);
+    let trimmed = value.trim();
+    let (trimmed, plain_unit) = match trimmed
+        .strip_suffix(['B', 'b'])
+        .filter(|number| !number.is_empty())
+    {
+        Some(number) => (number, 1),
+        None => (trimmed, plain_unit),
+    };
+    let (number, multiplier) = match trimmed.char_indices().last() {
+        Some((index, unit)) if unit.is_ascii_alphabetic() => {
+            let multiplier = match unit.to_ascii_uppercase() {
+                'K' => 1u64 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                'T' => 1 << 40,
+                _ => {
+                    return Err(format!(
+                        "unknown unit '{}' in '{}', expected K, M, G or T as in 8M",
+                        unit, value
+                    ))
+                }
+            };
+            (&trimmed[..index], multiplier)
+        }
+        _ => (trimmed, plain_unit),
+    };
+
+    let number: f64 = number.parse().map_err(|_| expected())?;
+    let bytes = number * multiplier as f64;
+    // Sizes past 2^63 bytes are no more meaningful than negative ones
+    if !bytes.is_finite() || number.is_sign_negative() || bytes >= (1u64 << 63) as f64 {
+        return Err(expected());
+    }
+    Ok(bytes as u64)
+}
+
+/// Parse a `--buffer-size` value into bytes, e.g. `64K`, `4M` or `1.5G`; a plain
+/// number is a number of KB, as the flag took before suffixes were accepted.
+pub fn parse_buffer_size(value: &str) -> Result<usize, String> {
+    let bytes = parse_size(value, 1024)?;
+    if bytes < (MIN_BUFFER_KB * 1024) as u64 {
+        return Err(format!("the buffer must be at least {} KB", MIN_BUFFER_KB));
+    }
+    if bytes > (MAX_BUFFER_KB * 1024) as u64 {
+        return Err(format!(
+            "the buffer can be at most {} GB",
+            MAX_BUFFER_KB / 1024 / 1024
+        ));
+    }
+    Ok(bytes as usize)
+}
+
+/// Get optimal buffer size in bytes based on device type and available memory,
+/// in whole requests of the device queue when its limits are known, or the size
+/// requested. A requested size must fit in the share of the available
+/// memory `--max-buffer-memory` allows each of the concurrent wipes.
+fn get_optimal_buffer_bytes(
+    is_block_device: bool,
+    options: &WipeOptions,
+    queue_limits: Option<&QueueLimits>,
+) -> Result<usize> {
+    let jobs = std::cmp::max(options.jobs, 1);
+
     // If user specified a size, use it
-    if requested_size != 1024 {
-        return requested_size;
+    if let Some(requested_bytes) = options.buffer_size_bytes {
+        if requested_bytes < MIN_BUFFER_KB * 1024 {
+            anyhow::bail!("The buffer must be at least {} KB", MIN_BUFFER_KB);
+        }
+        if let Some(available_kb) = get_available_memory_kb() {
+            let percent = options.max_buffer_memory_percent;
+            let limit_kb = available_kb / 100 * usize::from(percent) / jobs;
+            if requested_bytes > limit_kb * 1024 {
+                anyhow::bail!(
+                    "--buffer-size {} is more than {}% of the available memory ({} MB){}; use at most {} KB or raise --max-buffer-memory",
+                    HumanBytes(requested_bytes as u64),
+                    percent,
+                    available_kb / 1024,
+                    if jobs > 1 {
+                        format!(", shared by {} parallel jobs", jobs)
+                    } else {
+                        String::new()
+                    },
+                    limit_kb
+                );
+            }
+        }
+        return Ok(requested_bytes);
     }
 
-    // Try to determine available system memory
-    let system_memory_kb = get_available_memory_kb().unwrap_or(8 * 1024 * 1024); // Default to 8GB
+    // Try to determine available system memory, shared between concurrent wipes
+    let system_memory_kb = get_available_memory_kb().unwrap_or(8 * 1024 * 1024) / jobs; // Default to 8GB
 
     // Calculate optimal buffer size - be more aggressive for better performance
-    let optimal_kb = if is_block_device {
-        // For block devices, use larger buffers (8-64MB) for maximum throughput
+    if is_block_device {
+        // For block devices, use larger buffers (8-64MB) for maximum throughput,
+        // in whole largest requests so the kernel never splits off a short one
         let max_buffer = std::cmp::min(64 * 1024, system_memory_kb / 50); // Max 64MB or 2% of system memory
-        std::cmp::max(8 * 1024, max_buffer) // Min 8MB
+        let buffer_bytes = std::cmp::max(8 * 1024, max_buffer) * 1024; // Min 8MB
+        Ok(queue_limits.map_or(buffer_bytes, |limits| limits.align_buffer(buffer_bytes)))
     } else {
         // For files, use moderate buffers (4-32MB)
         let max_buffer = std::cmp::min(32 * 1024, system_memory_kb / 100); // Max 32MB or 1% of system memory
-        std::cmp::max(4 * 1024, max_buffer) // Min 4MB
-    };
-
-    optimal_kb
+        Ok(std::cmp::max(4 * 1024, max_buffer) * 1024) // Min 4MB
+    }
 }
 
 /// Get available system memory in KB
@@ -65,7 +179,8 @@ fn get_available_memory_kb() -> Option<usize> {
             }
         }
 
-        // Fallback: use sysconf on Unix systems
+        // Fallback: use sysconf on Linux (macOS has no _SC_AVPHYS_PAGES)
+        #[cfg(target_os = "linux")]
         unsafe {
             let pages = libc::sysconf(libc::_SC_AVPHYS_PAGES);
             let page_size = libc::sysconf(libc::_SC_PAGE_SIZE);
@@ -94,332 +209,3217 @@ fn get_available_memory_kb() -> Option<usize> {
     None
 }
 
-#[cfg(windows)]
-use winapi::{
-    shared::minwindef::{DWORD, LPVOID},
-    um::{
-        ioapiset::DeviceIoControl,
-        winioctl::{DISK_GEOMETRY_EX, IOCTL_DISK_GET_DRIVE_GEOMETRY_EX},
-    },
-};
+/// Size of the sub-blocks a failed write is retried in when skipping errors
+const ERROR_CHUNK_SIZE: usize = 4096;
 
-pub struct WipeContext {
-    file: File,
-    size: u64,
-    buffer_size: usize,
-    algorithm: WipeAlgorithm,
-    passes: usize,
-    json_mode: bool,
-    fast_mode: bool,
-    #[allow(dead_code)]
-    is_block_device: bool,
-    // Pre-allocated reusable buffer to avoid repeated allocations
-    write_buffer: Vec<u8>,
-}
+/// Bytes written between two releases of the written pages from the page cache
+const CACHE_RELEASE_INTERVAL: u64 = 64 * 1024 * 1024;
 
-impl WipeContext {
-    pub fn new(
-        path: &Path,
-        algorithm: WipeAlgorithm,
-        passes: usize,
-        buffer_size: usize,
-        json_mode: bool,
-        is_block_device: bool,
-        fast_mode: bool,
-    ) -> Result<Self> {
-        let mut options = OpenOptions::new();
-        options.write(true).read(true);
+/// Interval of the sync progress events sent while a flush to disk blocks
+const SYNC_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
 
-        #[cfg(unix)]
+/// Smallest chunk of unreproducible random data that a uniform fill fails to
+/// verify; shorter chunks are uniform by chance too often (one byte always is)
+const UNIFORM_CHECK_MIN_BYTES: usize = 4096;
+
+/// Shortest `--progress-interval`, so a frontend cannot flood itself with events
+pub const MIN_PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Delay before the first retry of a failed write, doubled on each further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+/// Longest delay before a retry, however many attempts came before
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Delay before retry number `attempt` (from 1) of a failed write
+fn retry_delay(attempt: u32) -> Duration {
+    2u32.checked_pow(attempt.saturating_sub(1))
+        .and_then(|factor| RETRY_BASE_DELAY.checked_mul(factor))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+}
+
+/// Size of an opened block device, from the platform's device size query
+pub fn block_device_size(file: &File) -> Result<u64> {
+    let size = {
+        #[cfg(target_os = "macos")]
+        {
+            use std::os::unix::io::AsRawFd;
+            crate::platform::macos::device_size(file.as_raw_fd())?
+        }
+        #[cfg(target_os = "freebsd")]
+        {
+            use std::os::unix::io::AsRawFd;
+            crate::platform::freebsd::media_size(file.as_raw_fd())?
+        }
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "freebsd"))))]
         {
-            // Only use O_SYNC for block devices in non-fast mode for data integrity
-            // Remove O_SYNC for files to improve performance - we'll sync at the end of each pass
-            if is_block_device && !fast_mode {
-                options.custom_flags(libc::O_SYNC);
+            use std::os::unix::io::AsRawFd;
+            let fd = file.as_raw_fd();
+            let mut size: u64 = 0;
+            unsafe {
+                // BLKGETSIZE64 ioctl
+                if libc::ioctl(fd, 0x80081272, &mut size) == 0 {
+                    size
+                } else {
+                    return Err(anyhow::anyhow!("Failed to get block device size"));
+                }
             }
-            // Consider O_DIRECT for block devices if buffer alignment is handled properly
-            // This would bypass the kernel page cache for better performance with large sequential writes
         }
+        #[cfg(windows)]
+        {
+            use std::os::windows::io::AsRawHandle;
+            crate::platform::windows::device_size(file.as_raw_handle() as winapi::um::winnt::HANDLE)?
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            return Err(anyhow::anyhow!(
+                "Block device wiping is not supported on this platform"
+            ));
+        }
+    };
+    Ok(size)
+}
 
-        let file = options
-            .open(path)
-            .with_context(|| format!("Failed to open file or device: {}", path.display()))?;
+/// Whether a write error may succeed if retried (e.g. EIO on a flaky USB bridge).
+/// Errors like a full disk or a read-only device will fail the same way every time.
+fn is_transient_write_error(error: &io::Error) -> bool {
+    !matches!(
+        error.kind(),
+        io::ErrorKind::StorageFull
+            | io::ErrorKind::ReadOnlyFilesystem
+            | io::ErrorKind::FileTooLarge
+            | io::ErrorKind::PermissionDenied
+            | io::ErrorKind::InvalidInput
+            | io::ErrorKind::NotFound
+            | io::ErrorKind::Unsupported
+    )
+}
 
-        // Get optimal buffer size
-        let optimal_buffer_size = get_optimal_buffer_size(is_block_device, buffer_size);
+/// Set once the journaling filesystem warning has been shown, so that a
+/// directory of files on one filesystem does not repeat it for every file
+#[cfg(target_os = "linux")]
+static JOURNAL_WARNING_SHOWN: AtomicBool = AtomicBool::new(false);
 
-        // For block devices, get size using platform-specific methods
-        let size = if is_block_device {
-            #[cfg(unix)]
-            {
-                use std::os::unix::io::AsRawFd;
-                let fd = file.as_raw_fd();
-                let mut size: u64 = 0;
-                unsafe {
-                    // BLKGETSIZE64 ioctl
-                    if libc::ioctl(fd, 0x80081272, &mut size) == 0 {
-                        size
-                    } else {
-                        return Err(anyhow::anyhow!("Failed to get block device size"));
-                    }
-                }
-            }
-            #[cfg(windows)]
-            {
-                use std::os::windows::io::AsRawHandle;
-                let handle = file.as_raw_handle();
-                let mut geometry: DISK_GEOMETRY_EX = unsafe { std::mem::zeroed() };
-                let mut bytes_returned: DWORD = 0;
-
-                unsafe {
-                    use winapi::ctypes::c_void;
-                    if DeviceIoControl(
-                        handle as *mut c_void,
-                        IOCTL_DISK_GET_DRIVE_GEOMETRY_EX,
-                        std::ptr::null_mut(),
-                        0,
-                        &mut geometry as *mut _ as LPVOID,
-                        std::mem::size_of::<DISK_GEOMETRY_EX>() as DWORD,
-                        &mut bytes_returned,
-                        std::ptr::null_mut(),
-                    ) != 0
-                    {
-                        // Convert LARGE_INTEGER to u64 properly
-                        let size = *geometry.DiskSize.QuadPart();
-                        size as u64
-                    } else {
-                        return Err(anyhow::anyhow!("Failed to get Windows disk size"));
-                    }
-                }
-            }
-            #[cfg(not(any(unix, windows)))]
-            {
-                return Err(anyhow::anyhow!(
-                    "Block device wiping is not supported on this platform"
-                ));
-            }
-        } else {
-            let metadata = file
-                .metadata()
-                .with_context(|| "Failed to get file metadata")?;
-            metadata.len()
-        };
+/// Bytes of the first pass timed to estimate the duration of the whole wipe
+const PROBE_BYTES: u64 = 128 * 1024 * 1024;
 
-        // Pre-allocate buffer once to avoid repeated allocations during wiping
-        let write_buffer = vec![0u8; optimal_buffer_size * 1024];
+/// Smallest buffer size tried by adaptive sizing, and the first one written with
+const ADAPTIVE_START_BYTES: usize = 4 * 1024 * 1024;
+/// Writes timed for each buffer size tried by adaptive sizing
+const TRIAL_WRITES: usize = 16;
+/// Most bytes written with one buffer size while trying it
+const TRIAL_MAX_BYTES: usize = 128 * 1024 * 1024;
 
-        Ok(WipeContext {
-            file,
-            size,
-            buffer_size: optimal_buffer_size,
-            algorithm,
-            passes,
-            json_mode,
-            fast_mode,
-            is_block_device,
-            write_buffer,
-        })
-    }
+/// Buffer sizes tried at the start of each pass when `--buffer-size` is not
+/// given, smallest first: 4 MiB, then four times larger up to the largest size
+/// the available memory allows, each rounded up to whole blocks
+fn buffer_candidates(max_bytes: usize, block_size: Option<usize>) -> Vec<usize> {
+    let mut candidates: Vec<usize> =
+        std::iter::successors(Some(ADAPTIVE_START_BYTES), |size| Some(size * 4))
+            .take_while(|&size| size < max_bytes)
+            .chain(std::iter::once(max_bytes))
+            .map(|size| block_size.map_or(size, |block_size| size.next_multiple_of(block_size)))
+            .collect();
+    candidates.dedup();
+    candidates
+}
 
-    pub fn wipe(&mut self) -> Result<()> {
-        let total_passes = get_algorithm_pass_count(&self.algorithm, self.passes);
+/// Bytes written with a buffer size while trying it: enough writes to time, up to a cap
+fn trial_bytes(buffer_len: usize) -> u64 {
+    std::cmp::min(buffer_len * TRIAL_WRITES, TRIAL_MAX_BYTES) as u64
+}
 
-        if self.json_mode {
-            let _ = emit_event(&ProgressEvent::Start {
-                algorithm: format!("{:?}", self.algorithm),
-                total_passes,
-                file_size_bytes: self.size,
-                buffer_size_kb: self.buffer_size,
-            });
-        } else {
-            println!(
-                "Starting secure wipe using {:?} algorithm ({} passes)",
-                self.algorithm, total_passes
+/// Clip `ranges` of a `size`-byte target to the region between the bytes kept
+/// by `--preserve-head` and `--preserve-tail`. On block devices both must be
+/// whole sectors, so that every write still starts on a sector boundary.
+fn preserve_ranges(
+    ranges: Vec<(u64, u64)>,
+    size: u64,
+    options: &WipeOptions,
+    block_size: Option<usize>,
+) -> Result<Vec<(u64, u64)>> {
+    let (head, tail) = (options.preserve_head, options.preserve_tail);
+    if head == 0 && tail == 0 {
+        return Ok(ranges);
+    }
+    if let Some(block_size) = block_size {
+        if head % block_size as u64 != 0 || tail % block_size as u64 != 0 {
+            anyhow::bail!(
+                "--preserve-head and --preserve-tail must be multiples of the {}-byte sector size",
+                block_size
             );
-            println!("File size: {:.2} MB", self.size as f64 / 1_048_576.0);
-            println!("Buffer size: {} KB", self.buffer_size);
-            println!();
         }
+    }
+    if head.saturating_add(tail) >= size {
+        anyhow::bail!(
+            "Preserving {} bytes at the start and {} at the end leaves nothing of the {} bytes to wipe",
+            head,
+            tail,
+            size
+        );
+    }
 
-        let start_time = Instant::now();
+    let end = size - tail;
+    Ok(ranges
+        .into_iter()
+        .filter_map(|(offset, length)| {
+            let start = offset.max(head);
+            let stop = (offset + length).min(end);
+            (start < stop).then(|| (start, stop - start))
+        })
+        .collect())
+}
 
-        for pass in 1..=total_passes {
-            self.wipe_pass(pass, total_passes)?;
+/// Remove the first `len` bytes of `ranges` and return them as ranges of their own
+fn take_ranges(ranges: &mut Vec<(u64, u64)>, len: u64) -> Vec<(u64, u64)> {
+    let mut taken = Vec::new();
+    let mut left = len;
+    while let Some(&(offset, length)) = ranges.first().filter(|_| left > 0) {
+        let take = std::cmp::min(left, length);
+        taken.push((offset, take));
+        if take == length {
+            ranges.remove(0);
+        } else {
+            ranges[0] = (offset + take, length - take);
         }
+        left -= take;
+    }
+    taken
+}
 
-        let elapsed = start_time.elapsed();
-        let throughput =
-            (self.size as f64 * total_passes as f64) / elapsed.as_secs_f64() / 1_048_576.0;
+/// Render a duration as e.g. `2h 05m 09s`, or `42.5s` under a minute
+pub fn format_duration(seconds: f64) -> String {
+    if seconds < 59.95 {
+        return format!("{:.1}s", seconds.max(0.0));
+    }
+    let total = seconds.round() as u64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else {
+        format!("{}m {:02}s", minutes, seconds)
+    }
+}
 
-        if self.json_mode {
-            let _ = emit_event(&ProgressEvent::Complete {
-                total_time_seconds: elapsed.as_secs_f64(),
-                average_throughput_mb_s: throughput,
-            });
-        } else {
-            println!();
-            io::stdout().execute(SetForegroundColor(Color::Green))?;
-            println!("Secure wipe completed successfully!");
-            io::stdout().execute(ResetColor)?;
-            println!("Total time: {:.2} seconds", elapsed.as_secs_f64());
-            println!("Average throughput: {:.2} MB/s", throughput);
-        }
+/// Render a throughput in MB/s, or in GB/s from 1 GB/s up
+pub fn format_throughput(bytes_per_second: f64) -> String {
+    let mb_per_second = bytes_per_second / 1_048_576.0;
+    if mb_per_second >= 1024.0 {
+        format!("{:.2} GB/s", mb_per_second / 1024.0)
+    } else {
+        format!("{:.2} MB/s", mb_per_second)
+    }
+}
 
-        Ok(())
+/// Report a problem with a target that does not stop the wipe, as an info event
+/// in JSON mode or a warning line otherwise
+#[cfg_attr(not(any(target_os = "linux", target_os = "macos")), allow(dead_code))]
+fn warn_target(path: &Path, message: String, json_mode: bool) {
+    if json_mode {
+        let _ = emit_target_event(
+            &path.display().to_string(),
+            &ProgressEvent::Info { message },
+        );
+    } else {
+        println!("Warning: {}", message);
     }
+}
 
-    fn wipe_pass(&mut self, pass: usize, total_passes: usize) -> Result<()> {
-        self.file
-            .seek(SeekFrom::Start(0))
-            .with_context(|| "Failed to seek to beginning of file")?;
+/// Length of a transfer of `len` bytes rounded up to whole sectors, for I/O that
+/// bypasses the OS cache
+fn sector_padded_len(len: usize, sector_size: Option<usize>) -> usize {
+    sector_size.map_or(len, |sector_size| len.next_multiple_of(sector_size))
+}
 
-        let pattern = get_pass_pattern(&self.algorithm, pass);
-        let pattern_name = get_pattern_name(&self.algorithm, pass);
+/// How writes reach the device, reported in the completion summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoMode {
+    /// Through the OS cache, flushed as the sync mode says
+    Cached,
+    /// Through the OS cache with O_SYNC or write-through, so each write waits for the device
+    Synchronous,
+    /// Bypassing the OS cache with sector-aligned, write-through writes (Windows devices)
+    Unbuffered,
+    /// Bypassing the page cache with O_DIRECT and block-aligned writes (Linux devices, --direct-io)
+    Direct,
+}
 
-        if self.json_mode {
-            let _ = emit_event(&ProgressEvent::PassStart {
-                pass,
-                total_passes,
-                pattern: pattern_name.to_string(),
-            });
+impl IoMode {
+    pub fn description(&self) -> &'static str {
+        match self {
+            IoMode::Cached => "cached",
+            IoMode::Synchronous => "synchronous (O_SYNC or write-through)",
+            IoMode::Unbuffered => "unbuffered write-through",
+            IoMode::Direct => "direct (O_DIRECT)",
         }
+    }
+}
 
-        let pb = if !self.json_mode {
-            let pb = ProgressBar::new(self.size);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template(&format!(
-                        "Pass {}/{} [{}] {{bar:40.cyan/blue}} {{bytes}}/{{total_bytes}} ({{bytes_per_sec}}) {{msg}}",
-                        pass, total_passes, pattern_name
-                    ))?
-                    .progress_chars("█▉▊▋▌▍▎▏  "),
-            );
-            Some(pb)
-        } else {
-            None
+/// User-selected settings for a wipe operation
+#[derive(Debug, Clone)]
+pub struct WipeOptions {
+    pub algorithm: WipeAlgorithm,
+    pub passes: usize,
+    /// Patterns of the passes given with `--passes-spec`, run instead of the algorithm
+    pub passes_spec: Option<Vec<WipePattern>>,
+    /// Label naming each pass `LABEL n/N` in progress output instead of its pattern
+    pub pattern_name: Option<String>,
+    /// Requested buffer size in bytes; `None` picks one from the target type and available memory
+    pub buffer_size_bytes: Option<usize>,
+    /// Largest share of the available memory, in percent, that requested buffers may take up
+    pub max_buffer_memory_percent: u8,
+    pub json_mode: bool,
+    /// When written data is forced to disk; `None` picks it from the target type
+    pub sync_mode: Option<SyncMode>,
+    /// MB written through the cache between two periodic write-backs with
+    /// `SyncMode::Interval`; 0 disables them
+    pub sync_interval_mb: u64,
+    /// Skip the flush at the end of each pass, whatever the sync mode
+    pub no_sync: bool,
+    /// Record failed blocks and keep going instead of aborting the wipe
+    pub skip_errors: bool,
+    /// Number of wipes running concurrently, used to share the memory budget
+    pub jobs: usize,
+    /// How many times a transient write error is retried before giving up
+    pub retries: u32,
+    /// Cap on the write rate of each target, in bytes per second
+    pub max_rate: Option<u64>,
+    /// Rename, truncate, and unlink file targets after overwriting them
+    pub remove: bool,
+    /// Only overwrite the allocated extents of sparse files, skipping holes
+    pub sparse_aware: bool,
+    /// Extend file targets to this many bytes before wiping, to cover blocks past EOF
+    pub fill_to_size: Option<u64>,
+    /// Bytes at the start of each target that are left as they are
+    pub preserve_head: u64,
+    /// Bytes at the end of each target that are left as they are
+    pub preserve_tail: u64,
+    /// Sample the target after wiping and report how random its contents look
+    pub entropy_check: bool,
+    /// Finish with an extra 0x00 pass so the target is left zeroed
+    pub last_pass_zero: bool,
+    /// Threads generating random data; 1 generates on the writing thread
+    pub threads: usize,
+    /// Threads filling each buffer of random data in parallel
+    pub rng_threads: usize,
+    /// Generate the next buffer of random data while the current one is
+    /// written; `None` does so when there is a spare CPU or several threads
+    pub pipeline: Option<bool>,
+    /// Generator of the data written by random passes
+    pub rng: RngKind,
+    /// Leave NTFS alternate data streams of file targets untouched
+    #[cfg(windows)]
+    pub skip_ads: bool,
+    /// Dismount a logical volume even when it cannot be locked because it is in use
+    #[cfg(windows)]
+    pub force_dismount: bool,
+    /// Only overwrite the header and FVE metadata of a BitLocker volume (crypto-erase)
+    #[cfg(windows)]
+    pub bitlocker_metadata_only: bool,
+    /// Lift the Host Protected Area of ATA disks before sizing them
+    #[cfg(target_os = "linux")]
+    pub reset_hpa: bool,
+    /// Open block devices with O_DIRECT and write from block-aligned memory
+    #[cfg(target_os = "linux")]
+    pub direct_io: bool,
+    /// Keep written data out of the page cache; unset means only for block devices
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub no_cache: Option<bool>,
+    /// Issue writes one at a time or queue them through io_uring
+    #[cfg(target_os = "linux")]
+    pub io_backend: IoBackend,
+    /// Writes kept in flight by the io_uring backend
+    #[cfg(target_os = "linux")]
+    pub queue_depth: usize,
+    /// Read SMART data of ATA disks before and after the wipe
+    #[cfg(target_os = "linux")]
+    pub smart: bool,
+    /// The OS has been asked not to sleep while the wipe runs, reported in the summary
+    pub sleep_inhibited: bool,
+    /// Priority the process was lowered to with `--nice`, reported in the start event
+    pub priority: PriorityMode,
+    /// Compute a SHA-256 digest of the data written by the final pass
+    pub hash_final_pass: bool,
+    /// Read every pass back before the next one starts and abort on a mismatch
+    pub verify_each_pass: bool,
+    /// Only check that each target holds this pattern, without writing to it
+    pub verify_pattern: Option<Vec<u8>>,
+    /// Time between two progress updates; `None` picks it from the output and sync mode
+    pub progress_interval: Option<Duration>,
+}
+
+impl WipeOptions {
+    /// Passes of the selected algorithm, plus the trailing zero pass when requested
+    pub fn total_passes(&self) -> usize {
+        self.pass_plan().len()
+    }
+
+    /// Every pass the wipe runs, in order: those of `--passes-spec` or the
+    /// algorithm, then the trailing zero pass, named after their pattern or the
+    /// `--pattern-name` label and pass number
+    pub fn pass_plan(&self) -> Vec<PassSpec> {
+        let mut plan = match &self.passes_spec {
+            Some(spec) => spec
+                .iter()
+                .enumerate()
+                .map(|(index, pattern)| PassSpec {
+                    pass: index + 1,
+                    pattern: pattern.clone(),
+                    name: pattern.name(),
+                    verify: false,
+                })
+                .collect(),
+            None => get_pass_plan(&self.algorithm, self.passes),
         };
+        if self.last_pass_zero {
+            plan.push(PassSpec {
+                pass: plan.len() + 1,
+                pattern: WipePattern::Fixed(0x00),
+                name: "0x00".to_string(),
+                verify: false,
+            });
+        }
 
-        // Pre-fill buffer with pattern to avoid repeated pattern generation
-        // This significantly improves performance for fixed patterns
-        match &pattern {
-            WipePattern::Fixed(byte) => {
-                self.write_buffer.fill(*byte);
-            }
-            WipePattern::Gutmann(patterns) => {
-                let pattern_idx = (pass - 1) % patterns.len();
-                if patterns[pattern_idx].len() == 1 {
-                    self.write_buffer.fill(patterns[pattern_idx][0]);
-                } else {
-                    for (i, byte) in self.write_buffer.iter_mut().enumerate() {
-                        *byte = patterns[pattern_idx][i % patterns[pattern_idx].len()];
-                    }
-                }
-            }
-            WipePattern::Random => {
-                // For random patterns, we'll generate fresh random data each iteration
-                // to avoid predictable patterns
+        let total_passes = plan.len();
+        for pass in &mut plan {
+            pass.verify |= self.verify_each_pass;
+            if let Some(label) = &self.pattern_name {
+                pass.name = format!("{} {}/{}", label, pass.pass, total_passes);
             }
         }
+        plan
+    }
+
+    /// Name of the algorithm, as reported in the start event and summary
+    pub fn algorithm_name(&self) -> String {
+        if self.passes_spec.is_some() {
+            "PassesSpec".to_string()
+        } else {
+            format!("{:?}", self.algorithm)
+        }
+    }
+
+    /// Name of the generator of the random data, if any pass writes random data
+    pub fn random_generator(&self) -> Option<&'static str> {
+        self.pass_plan()
+            .iter()
+            .any(|pass| pass.pattern.is_random())
+            .then(|| self.rng.name())
+    }
+
+    /// Whether random data is generated on worker threads, one buffer ahead of
+    /// the writes. On a single CPU the workers would only take turns with the
+    /// writer, so by default the data is then generated inline.
+    pub fn pipelines_random_data(&self) -> bool {
+        self.pipeline.unwrap_or_else(|| {
+            self.threads > 1
+                || std::thread::available_parallelism().is_ok_and(|cpus| cpus.get() > 1)
+        })
+    }
 
-        let mut total_written = 0u64;
-        let mut last_progress_time = Instant::now();
-        let mut last_bytes = 0u64;
+    /// Whether written data is kept out of the page cache of this target
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub fn bypasses_cache(&self, is_block_device: bool) -> bool {
+        self.no_cache.unwrap_or(is_block_device)
+    }
 
-        // Optimize progress reporting frequency based on mode
-        let progress_interval = if self.fast_mode {
-            Duration::from_secs(2) // Much less frequent in fast mode
-        } else if self.json_mode {
-            Duration::from_millis(500)
+    /// Sync mode of a target: the requested one, else synchronous writes for
+    /// block devices and periodic write-back for files
+    pub fn sync_mode_for(&self, is_block_device: bool) -> SyncMode {
+        self.sync_mode.unwrap_or(if is_block_device {
+            SyncMode::Always
         } else {
-            Duration::from_millis(200)
-        };
+            SyncMode::Interval
+        })
+    }
 
-        // Main write loop - optimized for performance
-        while total_written < self.size {
-            let write_size = std::cmp::min(
-                self.write_buffer.len(),
-                (self.size - total_written) as usize,
-            );
+    /// Time between two progress updates: the requested one, no shorter than
+    /// `MIN_PROGRESS_INTERVAL`, else `default`
+    pub fn progress_interval_or(&self, default: Duration) -> Duration {
+        self.progress_interval
+            .map_or(default, |interval| interval.max(MIN_PROGRESS_INTERVAL))
+    }
 
-            // Generate fresh random data only when needed
-            if matches!(pattern, WipePattern::Random) {
-                thread_rng().fill_bytes(&mut self.write_buffer[..write_size]);
-            }
+    /// Pattern written on `pass` and its display name
+    pub fn pass_pattern(&self, pass: usize) -> (WipePattern, String) {
+        let spec = self.pass_plan().swap_remove(pass - 1);
+        (spec.pattern, spec.name)
+    }
+}
 
-            // Direct write to file without BufWriter to avoid double buffering overhead
-            self.file
-                .write_all(&self.write_buffer[..write_size])
-                .with_context(|| "Failed to write data")?;
+impl Default for WipeOptions {
+    fn default() -> Self {
+        WipeOptions {
+            algorithm: WipeAlgorithm::Random,
+            passes: 3,
+            passes_spec: None,
+            pattern_name: None,
+            buffer_size_bytes: None,
+            max_buffer_memory_percent: 25,
+            json_mode: false,
+            sync_mode: None,
+            sync_interval_mb: 256,
+            no_sync: false,
+            skip_errors: false,
+            jobs: 1,
+            retries: 3,
+            max_rate: None,
+            remove: false,
+            sparse_aware: false,
+            fill_to_size: None,
+            preserve_head: 0,
+            preserve_tail: 0,
+            entropy_check: false,
+            last_pass_zero: false,
+            threads: 1,
+            rng_threads: 1,
+            pipeline: None,
+            rng: RngKind::Chacha,
+            #[cfg(windows)]
+            skip_ads: false,
+            #[cfg(windows)]
+            force_dismount: false,
+            #[cfg(windows)]
+            bitlocker_metadata_only: false,
+            #[cfg(target_os = "linux")]
+            reset_hpa: false,
+            #[cfg(target_os = "linux")]
+            direct_io: false,
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            no_cache: None,
+            #[cfg(target_os = "linux")]
+            io_backend: IoBackend::Sync,
+            #[cfg(target_os = "linux")]
+            queue_depth: 8,
+            #[cfg(target_os = "linux")]
+            smart: false,
+            sleep_inhibited: false,
+            priority: PriorityMode::Normal,
+            hash_final_pass: false,
+            verify_each_pass: false,
+            verify_pattern: None,
+            progress_interval: None,
+        }
+    }
+}
 
-            total_written += write_size as u64;
+/// Where a wipe writes: the opened file or device, or an in-memory buffer in tests.
+/// Syncing and cache control only mean something for real files and devices.
+pub trait WipeTarget: Read + Write + Seek {
+    /// Flush written data through OS and device caches. A failure means data
+    /// already written may never reach the device.
+    fn flush_to_disk(&self) -> io::Result<()> {
+        Ok(())
+    }
 
-            // Update progress less frequently to reduce overhead
-            let now = Instant::now();
-            let should_update_progress =
-                now.duration_since(last_progress_time) >= progress_interval;
+    /// Drop cached pages so that reads come from the device
+    fn drop_cache(&self) {}
 
-            if should_update_progress {
-                // Update progress bar
-                if let Some(ref pb) = pb {
-                    pb.set_position(total_written);
-                }
+    /// Start writing back the region at `offset` so that dirty data does not
+    /// pile up until the end of the pass; a length of 0 reaches to the end
+    fn sync_range(&self, _offset: u64, _len: u64) {}
 
-                // Emit JSON progress events
-                if self.json_mode {
-                    let elapsed = now.duration_since(last_progress_time);
-                    let bytes_diff = total_written - last_bytes;
-                    let bytes_per_second = if elapsed.as_secs_f64() > 0.0 {
-                        bytes_diff as f64 / elapsed.as_secs_f64()
-                    } else {
-                        0.0
-                    };
+    /// Write back the region at `offset` and drop it from the page cache; a
+    /// length of 0 reaches to the end of the target
+    fn release_cache(&self, _offset: u64, _len: u64) {}
 
-                    let _ = emit_event(&ProgressEvent::Progress {
-                        pass,
-                        total_passes,
-                        bytes_written: total_written,
-                        total_bytes: self.size,
-                        percent: (total_written as f64 / self.size as f64) * 100.0,
-                        bytes_per_second,
-                    });
-                }
+    /// Write back everything cached for the filesystem holding a file target,
+    /// which makes a journaling filesystem commit its journal
+    fn sync_filesystem(&self) -> io::Result<()> {
+        Ok(())
+    }
 
-                last_progress_time = now;
-                last_bytes = total_written;
-            }
+    /// Release the volume lock taken when the target was opened
+    #[cfg(windows)]
+    fn unlock_volume(&self) {}
+}
+
+impl WipeTarget for File {
+    fn flush_to_disk(&self) -> io::Result<()> {
+        #[cfg(unix)]
+        if unsafe { libc::fsync(self.as_raw_fd()) } != 0 {
+            return Err(io::Error::last_os_error());
         }
 
-        // Sync only at the end of each pass, not during writes
-        // This provides a good balance between performance and data integrity
-        if !self.fast_mode {
-            #[cfg(unix)]
-            unsafe {
-                libc::fsync(self.file.as_raw_fd());
-            }
+        #[cfg(windows)]
+        {
+            use std::os::windows::io::AsRawHandle;
+            use winapi::um::{handleapi::INVALID_HANDLE_VALUE, winnt::HANDLE};
 
-            #[cfg(windows)]
-            {
-                use std::os::windows::io::AsRawHandle;
-                use winapi::um::{fileapi::FlushFileBuffers, handleapi::INVALID_HANDLE_VALUE};
-
-                unsafe {
-                    use winapi::ctypes::c_void;
-                    let handle = self.file.as_raw_handle() as *mut c_void;
-                    if handle != INVALID_HANDLE_VALUE as *mut c_void {
-                        FlushFileBuffers(handle);
-                    }
-                }
+            let handle = self.as_raw_handle() as HANDLE;
+            if handle != INVALID_HANDLE_VALUE {
+                crate::platform::windows::flush_file_buffers(handle)?;
             }
         }
 
-        if let Some(pb) = pb {
-            pb.finish_with_message("Completed");
-        }
+        Ok(())
+    }
 
-        if self.json_mode {
-            let _ = emit_event(&ProgressEvent::PassComplete { pass, total_passes });
+    fn sync_filesystem(&self) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if unsafe { libc::syncfs(self.as_raw_fd()) } != 0 {
+            return Err(io::Error::last_os_error());
         }
 
         Ok(())
     }
+
+    fn drop_cache(&self) {
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::posix_fadvise(self.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn sync_range(&self, offset: u64, len: u64) {
+        // Only queues the write-back; the end-of-pass fsync still waits for it
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::sync_file_range(
+                self.as_raw_fd(),
+                offset as libc::off64_t,
+                len as libc::off64_t,
+                libc::SYNC_FILE_RANGE_WRITE,
+            );
+        }
+
+        // Neither can flush a range, so the whole file is written back
+        #[cfg(target_os = "macos")]
+        unsafe {
+            libc::fcntl(self.as_raw_fd(), libc::F_FULLFSYNC);
+        }
+
+        // A failure here shows up again in the end-of-pass flush
+        #[cfg(windows)]
+        let _ = self.flush_to_disk();
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    fn release_cache(&self, offset: u64, len: u64) {
+        // DONTNEED only drops clean pages, so wait for the region to be written back first
+        #[cfg(target_os = "linux")]
+        unsafe {
+            let fd = self.as_raw_fd();
+            libc::sync_file_range(
+                fd,
+                offset as libc::off64_t,
+                len as libc::off64_t,
+                libc::SYNC_FILE_RANGE_WAIT_BEFORE
+                    | libc::SYNC_FILE_RANGE_WRITE
+                    | libc::SYNC_FILE_RANGE_WAIT_AFTER,
+            );
+            libc::posix_fadvise(
+                fd,
+                offset as libc::off_t,
+                len as libc::off_t,
+                libc::POSIX_FADV_DONTNEED,
+            );
+        }
+    }
+
+    #[cfg(windows)]
+    fn unlock_volume(&self) {
+        use std::os::windows::io::AsRawHandle;
+
+        crate::platform::windows::unlock_volume(self.as_raw_handle() as winapi::um::winnt::HANDLE);
+    }
+}
+
+impl<T> WipeTarget for io::Cursor<T>
+where
+    T: AsRef<[u8]>,
+    io::Cursor<T>: Write,
+{
+}
+
+/// Bytes written so far by a pass, reported to its progress bar or as events
+/// at most once per interval
+struct PassProgress {
+    pass: usize,
+    total_passes: usize,
+    pass_bytes: u64,
+    bar: Option<ProgressBar>,
+    interval: Duration,
+    total_written: u64,
+    last_bytes: u64,
+    last_time: Instant,
+    throughput: ThroughputWindow,
+    started: Instant,
+    // Whether the duration of the wipe is still to be estimated from this pass
+    estimating: bool,
+}
+
+impl PassProgress {
+    /// Bytes still to be written by this pass and the passes after it
+    fn remaining_bytes(&self) -> u64 {
+        (self.total_passes - self.pass) as u64 * self.pass_bytes
+            + self.pass_bytes.saturating_sub(self.total_written)
+    }
+}
+
+pub struct WipeContext<W: WipeTarget = File> {
+    file: W,
+    target: String,
+    size: u64,
+    // Byte ranges overwritten on each pass; the whole target unless sparse-aware
+    ranges: Vec<(u64, u64)>,
+    // Bytes covered by `ranges`, only set in sparse-aware mode
+    allocated_bytes: Option<u64>,
+    options: WipeOptions,
+    // Resolved from the options and the target type
+    sync_mode: SyncMode,
+    is_block_device: bool,
+    // Pre-allocated reusable buffer to avoid repeated allocations, sized for the
+    // largest buffer size; smaller writes use the start of it. Sector-aligned
+    // for unbuffered device I/O, which writes from it directly.
+    write_buffer: PoolBuffer,
+    // Buffer sizes tried at the start of each pass, empty with --buffer-size
+    buffer_candidates: Vec<usize>,
+    // Device sector size when writes bypass the OS cache
+    sector_size: Option<usize>,
+    // Machine context for the start event, only gathered in JSON mode
+    machine_info: Option<Box<MachineInfo>>,
+    // BitLocker state of a Windows volume target
+    bitlocker: Option<BitLockerInfo>,
+    // Host Protected Area lifted before sizing the target, with --reset-hpa
+    hidden_area: Option<HiddenArea>,
+    // Request queue limits of a Linux block device, used to size the writes
+    queue_limits: Option<QueueLimits>,
+    // Regions skipped because of write errors (only populated with skip_errors)
+    failed_ranges: Vec<FailedRange>,
+    // Shared progress display when several targets are wiped at once
+    multi_progress: Option<MultiProgress>,
+    passes_completed: usize,
+    bytes_written: u64,
+    // Read-back verification outcome: passes verified, and whether one failed
+    passes_verified: usize,
+    verification_failed: bool,
+    // SHA-256 of the stream written by the final pass, when requested
+    final_pass_sha256: Option<String>,
+    // What each completed pass wrote, for the pass summary event
+    pass_records: Vec<PassRecord>,
+    // Sleeps after writes to hold the rate under --max-rate
+    throttle: Option<RateLimiter>,
+    // Ring and buffers for queued writes with --io-backend uring
+    #[cfg(target_os = "linux")]
+    uring: Option<UringWriter>,
+    // Whether we hold FSCTL_LOCK_VOLUME on a logical drive, released on drop
+    #[cfg(windows)]
+    volume_locked: bool,
+}
+
+impl WipeContext<File> {
+    pub fn new(path: &Path, is_block_device: bool, options: WipeOptions) -> Result<Self> {
+        // Detect before the volume is locked, which shuts out the extra handles
+        #[cfg(windows)]
+        let bitlocker = if is_block_device {
+            crate::bitlocker::detect(path)
+        } else {
+            None
+        };
+        #[cfg(not(windows))]
+        let bitlocker: Option<BitLockerInfo> = None;
+
+        let sync_mode = options.sync_mode_for(is_block_device);
+        let throttle = options.max_rate.map(RateLimiter::new);
+        let mut open_options = OpenOptions::new();
+        open_options.write(true).read(true);
+
+        #[cfg(unix)]
+        {
+            // By default only block devices are written with O_SYNC, for data integrity;
+            // files are synced as they go and at the end of each pass, which is much faster
+            if sync_mode == SyncMode::Always {
+                open_options.custom_flags(libc::O_SYNC);
+            }
+        }
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            use winapi::um::winbase::{FILE_FLAG_NO_BUFFERING, FILE_FLAG_WRITE_THROUGH};
+
+            // Cached device writes make throughput erratic and leave a huge backlog for
+            // FlushFileBuffers at the end of a pass; write straight to the device instead
+            if sync_mode == SyncMode::Always {
+                open_options.custom_flags(if is_block_device {
+                    FILE_FLAG_NO_BUFFERING | FILE_FLAG_WRITE_THROUGH
+                } else {
+                    FILE_FLAG_WRITE_THROUGH
+                });
+            }
+        }
+
+        // O_DIRECT keeps a multi-terabyte wipe from evicting the host's page cache,
+        // but not every device driver accepts it
+        #[cfg(target_os = "linux")]
+        let direct_file = if is_block_device && options.direct_io {
+            let mut direct_options = open_options.clone();
+            let sync_flag = if sync_mode == SyncMode::Always {
+                libc::O_SYNC
+            } else {
+                0
+            };
+            direct_options.custom_flags(sync_flag | libc::O_DIRECT);
+            match direct_options.open(device_open_path(path)) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    warn_target(
+                        path,
+                        format!(
+                            "Cannot open {} with O_DIRECT ({}); falling back to writes through the page cache",
+                            path.display(),
+                            e
+                        ),
+                        options.json_mode,
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        #[cfg(not(target_os = "linux"))]
+        let direct_file: Option<File> = None;
+        #[cfg(target_os = "linux")]
+        let direct_io = direct_file.is_some();
+
+        let file = match direct_file {
+            Some(file) => file,
+            None => open_options
+                .open(device_open_path(path))
+                .with_context(|| format!("Failed to open file or device: {}", path.display()))?,
+        };
+
+        // macOS has no fadvise, but can keep a descriptor's I/O out of the cache
+        #[cfg(target_os = "macos")]
+        if options.bypasses_cache(is_block_device) {
+            unsafe {
+                libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1);
+            }
+        }
+
+        // A mounted filesystem keeps writing to its volume underneath us, so take
+        // the volume away from it before the first pass
+        #[cfg(windows)]
+        let volume_locked = {
+            use crate::platform::windows::{get_device_type, lock_and_dismount_volume, DeviceType};
+            use std::os::windows::io::AsRawHandle;
+
+            if matches!(
+                get_device_type(path),
+                DeviceType::LogicalDrive | DeviceType::Volume
+            ) {
+                let locked = lock_and_dismount_volume(
+                    file.as_raw_handle() as winapi::um::winnt::HANDLE,
+                    &path.to_string_lossy(),
+                    options.force_dismount,
+                )?;
+                if !options.json_mode {
+                    println!(
+                        "Volume {} dismounted{}",
+                        path.display(),
+                        if locked {
+                            " and locked"
+                        } else {
+                            " (lock not held)"
+                        }
+                    );
+                }
+                locked
+            } else {
+                false
+            }
+        };
+
+        // The write sizes the device queue prefers, to size writes that are not split up
+        #[cfg(target_os = "linux")]
+        let queue_limits = if is_block_device {
+            use std::os::unix::fs::MetadataExt;
+            file.metadata()
+                .ok()
+                .and_then(|metadata| crate::platform::linux::queue_limits(metadata.rdev()))
+        } else {
+            None
+        };
+        #[cfg(not(target_os = "linux"))]
+        let queue_limits: Option<QueueLimits> = None;
+
+        // Host-managed zones reject writes anywhere but at their write pointer, so
+        // the first pass would fail partway and later passes could not rewrite them
+        if queue_limits.is_some_and(|limits| limits.zoned == ZonedModel::HostManaged) {
+            anyhow::bail!(
+                "{} is a host-managed zoned device (SMR or NVMe ZNS), which only accepts sequential writes and must reset a zone before rewriting it; overwriting it is not supported. Use the drive's sanitize or secure erase command instead",
+                path.display()
+            );
+        }
+
+        // Get optimal buffer size
+        let optimal_buffer_bytes =
+            get_optimal_buffer_bytes(is_block_device, &options, queue_limits.as_ref())?;
+
+        // Disk devices are written in whole logical sectors: macOS and FreeBSD only
+        // accept those, Windows volumes and drives refuse anything else even when
+        // buffered, and on Linux a partial sector costs a read-modify-write
+        #[cfg(target_os = "linux")]
+        let block_size = if is_block_device {
+            use std::os::unix::io::AsRawFd;
+            Some(crate::platform::linux::logical_block_size(file.as_raw_fd())? as usize)
+        } else {
+            None
+        };
+        #[cfg(windows)]
+        let block_size = is_block_device.then(|| {
+            use std::os::windows::io::AsRawHandle;
+            crate::platform::windows::sector_size(file.as_raw_handle() as winapi::um::winnt::HANDLE)
+                as usize
+        });
+        #[cfg(target_os = "macos")]
+        let block_size = if is_block_device {
+            use std::os::unix::io::AsRawFd;
+            Some(crate::platform::macos::block_size(file.as_raw_fd())? as usize)
+        } else {
+            None
+        };
+        #[cfg(target_os = "freebsd")]
+        let block_size = if is_block_device {
+            use std::os::unix::io::AsRawFd;
+            Some(crate::platform::freebsd::sector_size(file.as_raw_fd())? as usize)
+        } else {
+            None
+        };
+        #[cfg(not(any(
+            target_os = "linux",
+            windows,
+            target_os = "macos",
+            target_os = "freebsd"
+        )))]
+        let block_size: Option<usize> = None;
+
+        // Unbuffered writes must cover whole sectors from sector-aligned memory
+        #[cfg(windows)]
+        let sector_size = block_size.filter(|_| sync_mode == SyncMode::Always);
+        // O_DIRECT transfers must be aligned to the logical block size in memory and on disk
+        #[cfg(target_os = "linux")]
+        let sector_size = block_size.filter(|_| direct_io);
+        #[cfg(not(any(windows, target_os = "linux")))]
+        let sector_size: Option<usize> = None;
+
+        // Writes through the buffered /dev/diskN node are far slower than through /dev/rdiskN
+        #[cfg(target_os = "macos")]
+        if let Some(raw_path) = crate::platform::macos::raw_disk_path(path) {
+            warn_target(
+                path,
+                format!(
+                    "{} is the buffered disk device; {} (raw) is typically up to 20x faster (see --auto-raw)",
+                    path.display(),
+                    raw_path
+                ),
+                options.json_mode,
+            );
+        }
+
+        // The HPA must be lifted before the device is sized, or its sectors are left out
+        #[cfg(target_os = "linux")]
+        let hidden_area = if is_block_device && options.reset_hpa {
+            let hidden_area = crate::hpa::reset_hidden_area(path, &file)?;
+            if !options.json_mode {
+                println!(
+                    "Host Protected Area: {} bytes ({} of {} native sectors visible before the reset)",
+                    hidden_area.hpa_bytes, hidden_area.visible_sectors, hidden_area.native_sectors
+                );
+                if let Some(dco_bytes) = hidden_area.dco_bytes.filter(|&bytes| bytes > 0) {
+                    println!(
+                        "Warning: a Device Configuration Overlay still hides {} bytes; removing it is permanent and not done here",
+                        dco_bytes
+                    );
+                }
+            }
+            Some(hidden_area)
+        } else {
+            None
+        };
+        #[cfg(not(target_os = "linux"))]
+        let hidden_area: Option<HiddenArea> = None;
+
+        // For block devices, get size using platform-specific methods
+        let size = if is_block_device {
+            block_device_size(&file)?
+        } else {
+            let metadata = file
+                .metadata()
+                .with_context(|| "Failed to get file metadata")?;
+            // Blocks past EOF may still hold data from when the file was larger
+            match options.fill_to_size {
+                Some(fill_to_size) if fill_to_size > metadata.len() => {
+                    file.set_len(fill_to_size).with_context(|| {
+                        format!(
+                            "Failed to extend {} to {} bytes",
+                            path.display(),
+                            fill_to_size
+                        )
+                    })?;
+                    fill_to_size
+                }
+                _ => metadata.len(),
+            }
+        };
+
+        // Holes in sparse files hold no data, so only their allocated extents need overwriting
+        let (ranges, allocated_bytes) = if options.sparse_aware && !is_block_device {
+            let ranges = allocated_ranges(&file, size).with_context(|| {
+                format!("Failed to map allocated extents of {}", path.display())
+            })?;
+            let allocated_bytes = ranges.iter().map(|(_, length)| length).sum();
+            (ranges, Some(allocated_bytes))
+        } else {
+            (vec![(0, size)], None)
+        };
+        let ranges = preserve_ranges(ranges, size, &options, block_size)?;
+
+        // Pre-allocate buffer once to avoid repeated allocations during wiping,
+        // in whole sectors: a --buffer-size is rounded down, never past what was asked
+        let buffer_bytes = match sector_size.or(block_size) {
+            Some(block_size) if options.buffer_size_bytes.is_some() => {
+                std::cmp::max(optimal_buffer_bytes / block_size, 1) * block_size
+            }
+            Some(block_size) => optimal_buffer_bytes.next_multiple_of(block_size),
+            None => optimal_buffer_bytes,
+        };
+        let write_buffer = avec_rt!([sector_size.unwrap_or(1)]| 0u8; buffer_bytes);
+        let buffer_candidates = if options.buffer_size_bytes.is_none() {
+            let write_unit = queue_limits.as_ref().map(QueueLimits::write_unit);
+            buffer_candidates(buffer_bytes, write_unit.or(sector_size).or(block_size))
+        } else {
+            Vec::new()
+        };
+
+        // Each ring buffer gets a share of the write buffer, so memory use stays the same
+        #[cfg(target_os = "linux")]
+        let uring = if options.io_backend == IoBackend::Uring {
+            let alignment = sector_size.unwrap_or(4096);
+            let ring_buffer_bytes =
+                (buffer_bytes / options.queue_depth).next_multiple_of(alignment);
+            match UringWriter::new(&file, options.queue_depth, ring_buffer_bytes, alignment) {
+                Ok(uring) => Some(uring),
+                Err(e) => {
+                    warn_target(
+                        path,
+                        format!(
+                            "Cannot set up io_uring ({}); falling back to synchronous writes",
+                            e
+                        ),
+                        options.json_mode,
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let machine_info = if options.json_mode {
+            Some(Box::new(get_machine_info(path)))
+        } else {
+            None
+        };
+
+        Ok(WipeContext {
+            file,
+            target: path.display().to_string(),
+            size,
+            ranges,
+            allocated_bytes,
+            options,
+            sync_mode,
+            is_block_device,
+            write_buffer,
+            buffer_candidates,
+            sector_size,
+            machine_info,
+            bitlocker,
+            hidden_area,
+            queue_limits,
+            failed_ranges: Vec::new(),
+            multi_progress: None,
+            passes_completed: 0,
+            bytes_written: 0,
+            passes_verified: 0,
+            verification_failed: false,
+            final_pass_sha256: None,
+            pass_records: Vec::new(),
+            throttle,
+            #[cfg(target_os = "linux")]
+            uring,
+            #[cfg(windows)]
+            volume_locked,
+        })
+    }
+}
+
+impl<W: WipeTarget> WipeContext<W> {
+    /// Wipe an already opened target named `target` in events, such as a fill
+    /// file of a free-space wipe or an in-memory buffer. The whole target, apart
+    /// from any preserved head and tail, is overwritten in cached mode.
+    pub fn from_target(mut file: W, target: &str, options: WipeOptions) -> Result<Self> {
+        let size = file
+            .seek(SeekFrom::End(0))
+            .and_then(|size| file.rewind().map(|_| size))
+            .with_context(|| format!("Failed to determine the size of {}", target))?;
+        let buffer_bytes = get_optimal_buffer_bytes(false, &options, None)?;
+        let buffer_candidates = if options.buffer_size_bytes.is_none() {
+            buffer_candidates(buffer_bytes, None)
+        } else {
+            Vec::new()
+        };
+        let sync_mode = options.sync_mode_for(false);
+        let throttle = options.max_rate.map(RateLimiter::new);
+        let ranges = preserve_ranges(vec![(0, size)], size, &options, None)?;
+
+        Ok(WipeContext {
+            file,
+            target: target.to_string(),
+            size,
+            ranges,
+            allocated_bytes: None,
+            options,
+            sync_mode,
+            is_block_device: false,
+            write_buffer: avec_rt!([1]| 0u8; buffer_bytes),
+            buffer_candidates,
+            sector_size: None,
+            machine_info: None,
+            bitlocker: None,
+            hidden_area: None,
+            queue_limits: None,
+            failed_ranges: Vec::new(),
+            multi_progress: None,
+            passes_completed: 0,
+            bytes_written: 0,
+            passes_verified: 0,
+            verification_failed: false,
+            final_pass_sha256: None,
+            pass_records: Vec::new(),
+            throttle,
+            #[cfg(target_os = "linux")]
+            uring: None,
+            #[cfg(windows)]
+            volume_locked: false,
+        })
+    }
+
+    /// Try these buffer sizes at the start of each pass instead of the ones
+    /// picked from the available memory, sizing the write buffer for the largest
+    #[cfg(test)]
+    pub fn with_buffer_candidates(mut self, candidates: Vec<usize>) -> Self {
+        if let Some(&largest) = candidates.iter().max() {
+            self.write_buffer.resize(largest, 0);
+        }
+        self.buffer_candidates = candidates;
+        self
+    }
+
+    /// Render progress bars into a shared display instead of directly to the terminal
+    pub fn with_multi_progress(mut self, multi_progress: MultiProgress) -> Self {
+        self.multi_progress = Some(multi_progress);
+        self
+    }
+
+    pub fn passes_completed(&self) -> usize {
+        self.passes_completed
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Whether every pass read back correctly with `verify_each_pass`: `false`
+    /// once a pass fails verification, and unknown when not all passes were checked
+    pub fn verified(&self) -> Option<bool> {
+        if self.verification_failed {
+            Some(false)
+        } else if self.options.verify_each_pass
+            && self.passes_verified == self.options.total_passes()
+        {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// Pattern, bytes written, throughput and read-back result of each completed pass
+    #[cfg(test)]
+    pub fn pass_records(&self) -> &[PassRecord] {
+        &self.pass_records
+    }
+
+    /// Hex SHA-256 of the data written by the final pass, with `hash_final_pass`
+    pub fn final_pass_sha256(&self) -> Option<&str> {
+        self.final_pass_sha256.as_deref()
+    }
+
+    /// Size of the write buffer in bytes, after rounding up to whole device blocks
+    pub fn buffer_len(&self) -> usize {
+        self.write_buffer.len()
+    }
+
+    /// Logical size of the target
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Allocated size of the target, known only in sparse-aware mode
+    pub fn allocated_bytes(&self) -> Option<u64> {
+        self.allocated_bytes
+    }
+
+    /// Whether each pass starts by trying the candidate buffer sizes. Not with
+    /// queued writes, whose ring buffers are fixed, nor under `--max-rate`,
+    /// which makes every size look alike, nor when the trials would take up
+    /// more than half of the pass.
+    fn tunes_buffer_size(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if self.uring.is_some() {
+            return false;
+        }
+        let trials: u64 = self
+            .buffer_candidates
+            .iter()
+            .map(|&len| trial_bytes(len))
+            .sum();
+        self.buffer_candidates.len() > 1
+            && self.options.max_rate.is_none()
+            && trials * 2 <= self.pass_bytes()
+    }
+
+    /// Size of the writes made with a `buffer_len` buffer: io_uring writes come
+    /// from the ring's own buffers, which are smaller
+    fn chunk_len(&self, buffer_len: usize) -> usize {
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = self.uring.as_ref() {
+            return uring.buffer_len();
+        }
+        buffer_len
+    }
+
+    /// Bytes overwritten by each pass: the allocated extents in sparse-aware
+    /// mode, less any preserved head and tail
+    fn pass_bytes(&self) -> u64 {
+        self.ranges.iter().map(|(_, length)| length).sum()
+    }
+
+    fn emit(&self, event: &ProgressEvent) {
+        let _ = emit_target_event(&self.target, event);
+    }
+
+    /// Whether written regions are dropped from the page cache during each pass;
+    /// O_DIRECT writes never enter it
+    fn releases_cache(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self.sector_size.is_none() && self.options.bypasses_cache(self.is_block_device);
+        #[cfg(not(target_os = "linux"))]
+        false
+    }
+
+    /// Whether each pass is flushed to disk before the next one starts
+    fn flushes_passes(&self) -> bool {
+        self.sync_mode != SyncMode::None && !self.options.no_sync
+    }
+
+    /// Bytes written between two periodic write-backs, when writes go through
+    /// the cache and are not dropped from it already
+    fn sync_interval(&self) -> Option<u64> {
+        let cached = self.io_mode() == IoMode::Cached && !self.releases_cache();
+        (cached && self.sync_mode == SyncMode::Interval && self.options.sync_interval_mb > 0)
+            .then(|| self.options.sync_interval_mb * 1024 * 1024)
+    }
+
+    /// How writes reach the target
+    pub fn io_mode(&self) -> IoMode {
+        if self.sector_size.is_some() {
+            if cfg!(windows) {
+                IoMode::Unbuffered
+            } else {
+                IoMode::Direct
+            }
+        } else if self.sync_mode == SyncMode::Always {
+            IoMode::Synchronous
+        } else {
+            IoMode::Cached
+        }
+    }
+
+    /// Write `len` bytes of the write buffer from `start` at the current position.
+    /// Unbuffered I/O writes whole sectors, so a partial last sector is padded
+    /// with the rest of the buffered pattern.
+    fn write_from_buffer(&mut self, start: usize, len: usize) -> io::Result<()> {
+        let len = sector_padded_len(len, self.sector_size);
+        self.file.write_all(&self.write_buffer[start..start + len])
+    }
+
+    pub fn wipe(&mut self) -> Result<()> {
+        let total_passes = self.options.total_passes();
+
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::Start {
+                protocol_version: PROTOCOL_VERSION,
+                algorithm: self.options.algorithm_name(),
+                rng: self.options.random_generator().map(str::to_string),
+                total_passes,
+                file_size_bytes: self.size,
+                allocated_bytes: self.allocated_bytes,
+                preserve_head_bytes: self.options.preserve_head,
+                preserve_tail_bytes: self.options.preserve_tail,
+                buffer_size_kb: self.buffer_len() / 1024,
+                buffer_size_bytes: self.buffer_len(),
+                sync_mode: self.sync_mode,
+                no_sync: self.options.no_sync,
+                machine: self.machine_info.clone(),
+                bitlocker: self.bitlocker.clone(),
+                hidden_area: self.hidden_area.clone(),
+                queue_limits: self.queue_limits,
+                priority: self.options.priority,
+            });
+        } else if self.multi_progress.is_none() {
+            println!(
+                "Starting secure wipe using {} algorithm ({} passes)",
+                self.options.algorithm_name(),
+                total_passes
+            );
+            println!("File size: {:.2} MB", self.size as f64 / 1_048_576.0);
+            if self.options.preserve_head > 0 || self.options.preserve_tail > 0 {
+                println!(
+                    "Preserved: first {} and last {} bytes, {:.2} MB in between is wiped",
+                    self.options.preserve_head,
+                    self.options.preserve_tail,
+                    self.pass_bytes() as f64 / 1_048_576.0
+                );
+            }
+            if let Some(allocated_bytes) = self.allocated_bytes {
+                println!(
+                    "Allocated: {:.2} MB in {} extent(s), holes are skipped",
+                    allocated_bytes as f64 / 1_048_576.0,
+                    self.ranges.len()
+                );
+            }
+            if self.tunes_buffer_size() {
+                println!(
+                    "Buffer size: adaptive, {} to {} KB picked at the start of each pass",
+                    self.buffer_candidates[0] / 1024,
+                    self.buffer_len() / 1024
+                );
+            } else {
+                println!("Buffer size: {} KB", self.buffer_len() / 1024);
+            }
+            if let Some(limits) = &self.queue_limits {
+                print!(
+                    "Device queue: {} KB max request, {}-byte logical blocks",
+                    limits.max_request_kb, limits.logical_block_size
+                );
+                if limits.optimal_io_bytes > 0 {
+                    print!(", {} KB optimal I/O", limits.optimal_io_bytes / 1024);
+                }
+                if limits.zoned == ZonedModel::HostAware {
+                    print!(", host-aware zoned");
+                }
+                println!();
+            }
+            if self.options.priority != PriorityMode::Normal {
+                println!("Priority: {}", self.options.priority.description());
+            }
+            if let Some(bitlocker) = &self.bitlocker {
+                println!("BitLocker: encrypted volume ({})", bitlocker.state());
+            }
+            println!();
+        }
+
+        let start_time = Instant::now();
+
+        for spec in self.options.pass_plan() {
+            let pass = spec.pass;
+            if let Err(e) = self.wipe_pass(spec, total_passes) {
+                // What a cancelled pass wrote is pushed to disk before stopping
+                if cancel::is_cancelled() {
+                    let _ = self.file.flush_to_disk();
+                }
+                return Err(e);
+            }
+            self.passes_completed = pass;
+        }
+
+        // Push the overwritten file and then its whole filesystem to disk, even
+        // with --sync-mode none, so the journal commits the new data instead of
+        // holding it, and a removed file's pages cannot be dropped unwritten
+        if !self.is_block_device {
+            self.file
+                .flush_to_disk()
+                .with_context(|| format!("Failed to sync {} to disk", self.target))?;
+            self.file.sync_filesystem().with_context(|| {
+                format!("Failed to sync the filesystem holding {}", self.target)
+            })?;
+            #[cfg(target_os = "linux")]
+            self.warn_journaled_filesystem();
+        }
+
+        let elapsed = start_time.elapsed();
+        let throughput =
+            (self.pass_bytes() as f64 * total_passes as f64) / elapsed.as_secs_f64() / 1_048_576.0;
+
+        if !self.failed_ranges.is_empty() {
+            self.report_failed_ranges()?;
+        }
+
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::PassSummary {
+                passes: self.pass_records.clone(),
+            });
+            self.emit(&ProgressEvent::Complete {
+                total_time_seconds: elapsed.as_secs_f64(),
+                average_throughput_mb_s: throughput,
+                io_mode: self.io_mode(),
+                sync_mode: self.sync_mode,
+                sleep_inhibited: self.options.sleep_inhibited,
+                final_pass_sha256: self.final_pass_sha256.clone(),
+                max_rate_bytes_per_second: self.options.max_rate,
+            });
+        } else if self.multi_progress.is_none() {
+            println!();
+            io::stdout().execute(SetForegroundColor(Color::Green))?;
+            println!("Secure wipe completed successfully!");
+            io::stdout().execute(ResetColor)?;
+            if let Some(allocated_bytes) = self.allocated_bytes {
+                println!(
+                    "Logical size: {} bytes, allocated (overwritten): {} bytes",
+                    self.size, allocated_bytes
+                );
+            }
+            println!("Total time: {}", format_duration(elapsed.as_secs_f64()));
+            println!(
+                "Average throughput: {}",
+                format_throughput(throughput * 1_048_576.0)
+            );
+            if let Some(max_rate) = self.options.max_rate {
+                println!("Rate limit: {}/s", HumanBytes(max_rate));
+            }
+            match self.sector_size {
+                Some(sector_size) => println!(
+                    "I/O mode: {} ({}-byte sectors)",
+                    self.io_mode().description(),
+                    sector_size
+                ),
+                None => println!("I/O mode: {}", self.io_mode().description()),
+            }
+            // Synchronous writes keep their guarantee without the end-of-pass flush
+            if self.flushes_passes() || matches!(self.sync_mode, SyncMode::None | SyncMode::Always)
+            {
+                println!(
+                    "Sync mode: {} ({})",
+                    self.sync_mode.name(),
+                    self.sync_mode.guarantee()
+                );
+            } else {
+                println!(
+                    "Sync mode: {} without end-of-pass flushes (--no-sync; {})",
+                    self.sync_mode.name(),
+                    SyncMode::None.guarantee()
+                );
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(uring) = &self.uring {
+                println!(
+                    "I/O backend: io_uring with {} writes in flight",
+                    uring.queue_depth()
+                );
+            }
+            if let Some(rng) = self.options.random_generator() {
+                println!("Random data: {}", rng);
+            }
+            if self.options.sleep_inhibited {
+                println!("System sleep was inhibited during the wipe");
+            }
+            if let Some(digest) = &self.final_pass_sha256 {
+                println!("Final pass SHA-256: {}", digest);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn wipe_pass(&mut self, spec: PassSpec, total_passes: usize) -> Result<()> {
+        let pass_start = Instant::now();
+        let PassSpec {
+            pass,
+            pattern,
+            name: pattern_name,
+            verify,
+        } = spec;
+
+        // Prefix each bar with its target when several are displayed together
+        let prefix = if self.multi_progress.is_some() {
+            format!("{} ", self.target)
+        } else {
+            String::new()
+        };
+        let pb = if !self.options.json_mode {
+            let pb = ProgressBar::new(self.pass_bytes()).with_prefix("0 B/s");
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template(&format!(
+                        "{}Pass {}/{} [{}] {{bar:40.cyan/blue}} {{bytes}}/{{total_bytes}} ({{prefix}}) {{msg}}",
+                        prefix, pass, total_passes, pattern_name
+                    ))?
+                    .progress_chars("█▉▊▋▌▍▎▏  "),
+            );
+            match &self.multi_progress {
+                Some(multi_progress) => Some(multi_progress.add(pb)),
+                None => Some(pb),
+            }
+        } else {
+            None
+        };
+
+        // Pre-fill buffer with pattern to avoid repeated pattern generation
+        // This significantly improves performance for fixed patterns
+        // For random patterns, we'll generate fresh random data each iteration
+        // to avoid predictable patterns
+        if !pattern.is_random() {
+            pattern.fill(&mut self.write_buffer, pass);
+        }
+
+        let random_stream = pattern
+            .is_random()
+            .then(|| RandomStream::new(self.options.rng, self.options.rng_threads));
+
+        // Digest of every buffer handed to the device on the final pass, in write order
+        let mut hasher = (self.options.hash_final_pass && pass == total_passes).then(Sha256::new);
+
+        let pass_bytes = self.pass_bytes();
+        let mut progress = PassProgress {
+            pass,
+            total_passes,
+            pass_bytes,
+            bar: pb,
+            // Optimize progress reporting frequency based on mode
+            interval: self
+                .options
+                .progress_interval_or(if self.sync_mode == SyncMode::None {
+                    Duration::from_secs(2) // Much less frequent in fast mode
+                } else if self.options.json_mode {
+                    Duration::from_millis(500)
+                } else {
+                    Duration::from_millis(200)
+                }),
+            total_written: 0,
+            last_bytes: 0,
+            last_time: Instant::now(),
+            throughput: ThroughputWindow::default(),
+            started: Instant::now(),
+            estimating: pass == 1 && pass_bytes > PROBE_BYTES,
+        };
+
+        let tunes_buffer_size = self.tunes_buffer_size();
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::PassStart {
+                pass,
+                total_passes,
+                pattern: pattern_name.to_string(),
+                buffer_size_kb: (!tunes_buffer_size)
+                    .then(|| self.chunk_len(self.write_buffer.len()) / 1024),
+            });
+        }
+
+        // The ranges of the pass with the buffer size they were written in, in
+        // write order, so that the read-back starts each chunk where a write did
+        let mut segments = Vec::new();
+        let mut remaining = self.ranges.clone();
+
+        // Write the start of the pass with each candidate buffer size in turn and
+        // keep the fastest for the rest of it
+        let mut trials = Vec::new();
+        if tunes_buffer_size {
+            for buffer_len in self.buffer_candidates.clone() {
+                let trial = take_ranges(&mut remaining, trial_bytes(buffer_len));
+                let start = Instant::now();
+                let written = progress.total_written;
+                self.write_segment(
+                    &trial,
+                    buffer_len,
+                    random_stream.as_ref(),
+                    hasher.as_mut(),
+                    &mut progress,
+                )?;
+                let bytes_written = progress.total_written - written;
+                trials.push(BufferTrial {
+                    buffer_size_kb: buffer_len / 1024,
+                    bytes_written,
+                    bytes_per_second: bytes_written as f64
+                        / start.elapsed().as_secs_f64().max(f64::EPSILON),
+                });
+                segments.push((trial, buffer_len));
+            }
+        }
+        let buffer_len = self
+            .buffer_candidates
+            .iter()
+            .zip(&trials)
+            .max_by(|(_, a), (_, b)| a.bytes_per_second.total_cmp(&b.bytes_per_second))
+            .map_or(self.write_buffer.len(), |(&buffer_len, _)| buffer_len);
+        let chunk_len = self.chunk_len(buffer_len);
+
+        if self.options.json_mode && !trials.is_empty() {
+            self.emit(&ProgressEvent::BufferTuned {
+                pass,
+                buffer_size_kb: chunk_len / 1024,
+                trials,
+            });
+        } else if let Some(pb) = progress.bar.as_ref().filter(|_| !trials.is_empty()) {
+            pb.println(format!(
+                "{}Pass {}/{}: writing with {} buffers, the fastest of {}",
+                prefix,
+                pass,
+                total_passes,
+                HumanBytes(buffer_len as u64),
+                trials
+                    .iter()
+                    .map(|trial| format!(
+                        "{} ({}/s)",
+                        HumanBytes(trial.buffer_size_kb as u64 * 1024),
+                        HumanBytes(trial.bytes_per_second as u64)
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        #[cfg(target_os = "linux")]
+        let result = if self.uring.is_some() {
+            let mut random_pool = random_stream
+                .as_ref()
+                .filter(|_| self.options.pipelines_random_data())
+                .map(|stream| {
+                    RandomPool::new(
+                        self.options.threads,
+                        stream,
+                        remaining.clone(),
+                        chunk_len,
+                        1,
+                    )
+                });
+            self.write_ranges_queued(
+                &pattern,
+                random_stream.as_ref(),
+                random_pool.as_mut(),
+                hasher.as_mut(),
+                &mut progress,
+            )
+        } else {
+            self.write_segment(
+                &remaining,
+                chunk_len,
+                random_stream.as_ref(),
+                hasher.as_mut(),
+                &mut progress,
+            )
+        };
+        #[cfg(not(target_os = "linux"))]
+        let result = self.write_segment(
+            &remaining,
+            chunk_len,
+            random_stream.as_ref(),
+            hasher.as_mut(),
+            &mut progress,
+        );
+        result?;
+        segments.push((remaining, chunk_len));
+        let pb = progress.bar;
+
+        // Sync only at the end of each pass, not during writes
+        // This provides a good balance between performance and data integrity
+        if self.flushes_passes() {
+            // Flushing a large device can take a while; say so instead of sitting at 100%
+            let sync_start = Instant::now();
+            if let Some(ref pb) = pb {
+                pb.set_position(pass_bytes);
+                pb.set_message("Syncing to disk...");
+                pb.enable_steady_tick(Duration::from_millis(100));
+            }
+            if self.options.json_mode {
+                self.emit(&ProgressEvent::SyncStart { pass });
+            }
+
+            let flushed = if self.options.json_mode {
+                // The OS reports no flush progress (FlushFileBuffers on a large
+                // drive can block for minutes), so send a heartbeat until it returns
+                let (done, heartbeat) = mpsc::channel::<()>();
+                let target = &self.target;
+                thread::scope(|scope| {
+                    scope.spawn(move || {
+                        while let Err(RecvTimeoutError::Timeout) =
+                            heartbeat.recv_timeout(SYNC_HEARTBEAT_INTERVAL)
+                        {
+                            let _ = emit_target_event(
+                                target,
+                                &ProgressEvent::SyncProgress {
+                                    pass,
+                                    elapsed_seconds: sync_start.elapsed().as_secs_f64(),
+                                },
+                            );
+                        }
+                    });
+                    let flushed = self.file.flush_to_disk();
+                    drop(done);
+                    flushed
+                })
+            } else {
+                // The steady tick keeps the spinner turning while the flush blocks
+                self.file.flush_to_disk()
+            };
+
+            // A failed flush (a dying drive, a pulled USB cable) means the pass
+            // may not be on the device, so it must not count as completed
+            if let Err(e) = flushed {
+                if let Some(ref pb) = pb {
+                    pb.abandon_with_message("Sync failed");
+                }
+                if self.options.json_mode {
+                    self.emit(&ProgressEvent::Error {
+                        message: format!("Failed to sync pass {} to disk: {}", pass, e),
+                        code: Some("sync_failed".to_string()),
+                        pass: Some(pass),
+                        errno: e.raw_os_error(),
+                    });
+                }
+                return Err(e).with_context(|| {
+                    format!("Failed to sync pass {} of {} to disk", pass, self.target)
+                });
+            }
+
+            if self.options.json_mode {
+                self.emit(&ProgressEvent::SyncComplete {
+                    pass,
+                    duration_seconds: sync_start.elapsed().as_secs_f64(),
+                });
+            }
+        }
+
+        if let Some(hasher) = hasher {
+            self.final_pass_sha256 = Some(format!("{:x}", hasher.finalize()));
+        }
+        let pass_seconds = pass_start.elapsed().as_secs_f64();
+
+        if verify {
+            if let Some(ref pb) = pb {
+                pb.set_message("Verifying...");
+                pb.enable_steady_tick(Duration::from_millis(100));
+            }
+            self.verify_pass(pass, &pattern, random_stream.as_ref(), &segments)
+                .inspect_err(|_| self.verification_failed = true)?;
+            self.passes_verified += 1;
+        }
+
+        self.pass_records.push(PassRecord {
+            pass,
+            pattern: pattern_name,
+            bytes_written: progress.total_written,
+            bytes_per_second: progress.total_written as f64 / pass_seconds.max(f64::EPSILON),
+            verified: verify.then_some(true),
+        });
+
+        if let Some(pb) = pb {
+            pb.finish_with_message("Completed");
+        }
+
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::PassComplete { pass, total_passes });
+        }
+
+        Ok(())
+    }
+
+    /// Write `ranges` of the pass in chunks of `chunk_len` bytes. Random data is
+    /// generated on producer threads when pipelined, so the next buffer is
+    /// filled while this thread writes the current one.
+    fn write_segment(
+        &mut self,
+        ranges: &[(u64, u64)],
+        chunk_len: usize,
+        random_stream: Option<&RandomStream>,
+        hasher: Option<&mut Sha256>,
+        progress: &mut PassProgress,
+    ) -> Result<()> {
+        let mut random_pool = random_stream
+            .filter(|_| self.options.pipelines_random_data())
+            .map(|stream| {
+                RandomPool::new(
+                    self.options.threads,
+                    stream,
+                    ranges.to_vec(),
+                    chunk_len,
+                    self.write_buffer.alignment(),
+                )
+            });
+
+        // The pool's buffers are swapped in whole, so the full-size write buffer
+        // is set aside while they have another size
+        let full_buffer = random_pool
+            .as_ref()
+            .filter(|_| chunk_len != self.write_buffer.len())
+            .map(|_| {
+                let alignment = self.write_buffer.alignment();
+                std::mem::replace(
+                    &mut self.write_buffer,
+                    avec_rt!([alignment]| 0u8; chunk_len),
+                )
+            });
+        let result = self.write_ranges(
+            ranges,
+            chunk_len,
+            random_stream,
+            random_pool.as_mut(),
+            hasher,
+            progress,
+        );
+        if let Some(full_buffer) = full_buffer {
+            self.write_buffer = full_buffer;
+        }
+        result
+    }
+
+    /// Write `ranges` with one blocking write per chunk of at most `chunk_len`
+    /// bytes. The write buffer holds a fixed pattern already; random passes swap
+    /// in buffers from `random_pool`, or fill it inline from `random_stream`
+    /// without one.
+    fn write_ranges(
+        &mut self,
+        ranges: &[(u64, u64)],
+        chunk_len: usize,
+        random_stream: Option<&RandomStream>,
+        mut random_pool: Option<&mut RandomPool>,
+        mut hasher: Option<&mut Sha256>,
+        progress: &mut PassProgress,
+    ) -> Result<()> {
+        let pass = progress.pass;
+        let releases_cache = self.releases_cache();
+        let sync_interval = self.sync_interval();
+
+        // Main write loop - optimized for performance
+        for &(range_offset, range_length) in ranges {
+            self.file
+                .seek(SeekFrom::Start(range_offset))
+                .with_context(|| format!("Failed to seek to offset {}", range_offset))?;
+
+            let range_end = range_offset + range_length;
+            let mut offset = range_offset;
+            // Start of the written region still in the page cache
+            let mut cached_from = range_offset;
+            // Start of the written region whose write-back has not been started
+            let mut synced_from = range_offset;
+
+            while offset < range_end {
+                let write_size = std::cmp::min(chunk_len as u64, range_end - offset) as usize;
+
+                if cancel::is_cancelled() {
+                    self.report_cancelled(progress);
+                    anyhow::bail!("Wipe cancelled by user at offset {}", offset);
+                }
+
+                // Swap in the next buffer of random data the producers have ready
+                if let Some(pool) = random_pool.as_mut() {
+                    pool.exchange(&mut self.write_buffer);
+                } else if let Some(stream) = random_stream {
+                    stream.fill(offset, &mut self.write_buffer[..write_size]);
+                }
+
+                // Direct write to file without BufWriter to avoid double buffering overhead
+                if let Err(e) = self.write_block(pass, offset, write_size) {
+                    if !self.options.skip_errors {
+                        return Err(e)
+                            .with_context(|| format!("Failed to write data at offset {}", offset));
+                    }
+                    self.recover_failed_block(pass, offset, write_size)?;
+                }
+
+                if let Some(hasher) = hasher.as_mut() {
+                    hasher.update(&self.write_buffer[..write_size]);
+                }
+
+                offset += write_size as u64;
+                self.record_written(progress, write_size as u64);
+
+                if releases_cache
+                    && (offset - cached_from >= CACHE_RELEASE_INTERVAL || offset == range_end)
+                {
+                    self.file.release_cache(cached_from, offset - cached_from);
+                    cached_from = offset;
+                }
+                if sync_interval.is_some_and(|interval| offset - synced_from >= interval) {
+                    self.file.sync_range(synced_from, offset - synced_from);
+                    synced_from = offset;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the pass over every range through io_uring, keeping a write queued
+    /// for each ring buffer. Progress counts completed writes only, and a failed
+    /// write aborts the pass once the writes still in flight have finished.
+    #[cfg(target_os = "linux")]
+    fn write_ranges_queued(
+        &mut self,
+        pattern: &WipePattern,
+        random_stream: Option<&RandomStream>,
+        mut random_pool: Option<&mut RandomPool>,
+        mut hasher: Option<&mut Sha256>,
+        progress: &mut PassProgress,
+    ) -> Result<()> {
+        let pass = progress.pass;
+        let Some(mut uring) = self.uring.take() else {
+            return Ok(());
+        };
+        if !pattern.is_random() {
+            uring.fill_buffers(|buffer| pattern.fill(buffer, pass));
+        }
+        let chunk_len = uring.buffer_len();
+        let mut chunks = range_chunks(self.ranges.clone(), chunk_len);
+
+        // Bytes of each buffer's queued write that count towards the pass
+        let mut queued_lengths = vec![0u64; uring.queue_depth()];
+        // Random pool buffers have the ring buffers' size, unlike the write buffer
+        let mut random_buffer =
+            avec_rt!([1]| 0u8; if random_pool.is_some() { chunk_len } else { 0 });
+        // Completions arrive out of order, so periodic write-backs cover the whole target
+        let sync_interval = self.sync_interval();
+        let mut unsynced = 0u64;
+
+        let mut write_queued = || -> Result<()> {
+            let mut next = chunks.next();
+            while next.is_some() || uring.in_flight() > 0 {
+                // Refill and queue every buffer that is free
+                while let Some((offset, length)) = next {
+                    let Some((index, buffer)) = uring.free_buffer() else {
+                        break;
+                    };
+                    if cancel::is_cancelled() {
+                        self.report_cancelled(progress);
+                        anyhow::bail!("Wipe cancelled by user at offset {}", offset);
+                    }
+
+                    if let Some(pool) = random_pool.as_mut() {
+                        pool.exchange(&mut random_buffer);
+                        buffer[..length].copy_from_slice(&random_buffer[..length]);
+                    } else if let Some(stream) = random_stream {
+                        stream.fill(offset, &mut buffer[..length]);
+                    }
+                    if let Some(hasher) = hasher.as_mut() {
+                        hasher.update(&buffer[..length]);
+                    }
+
+                    // Direct writes must cover whole sectors, like write_from_buffer
+                    uring
+                        .submit(index, offset, sector_padded_len(length, self.sector_size))
+                        .with_context(|| format!("Failed to queue a write at offset {}", offset))?;
+                    queued_lengths[index] = length as u64;
+                    next = chunks.next();
+                }
+
+                let index = uring
+                    .wait_completion()
+                    .with_context(|| format!("A queued write of pass {} failed", pass))?;
+                self.record_written(progress, queued_lengths[index]);
+                unsynced += queued_lengths[index];
+                if sync_interval.is_some_and(|interval| unsynced >= interval) {
+                    self.file.sync_range(0, 0);
+                    unsynced = 0;
+                }
+            }
+            Ok(())
+        };
+        let result = write_queued();
+
+        uring.drain();
+        self.uring = Some(uring);
+        // Completions arrive out of order, so the whole pass is released at once
+        if self.releases_cache() {
+            self.file.release_cache(0, 0);
+        }
+        result
+    }
+
+    /// Count `bytes` as written by the current pass and update its progress bar
+    /// or emit a progress event when the reporting interval has passed
+    fn record_written(&mut self, progress: &mut PassProgress, bytes: u64) {
+        progress.total_written += bytes;
+        self.bytes_written += bytes;
+        // Sleeping before the rate is measured makes the reported rate the throttled one
+        if let Some(throttle) = self.throttle.as_mut() {
+            throttle.throttle(bytes);
+        }
+
+        if progress.estimating && progress.total_written >= PROBE_BYTES {
+            progress.estimating = false;
+            self.report_estimate(progress);
+        }
+
+        // Update progress less frequently to reduce overhead
+        let now = Instant::now();
+        if now.duration_since(progress.last_time) < progress.interval {
+            return;
+        }
+
+        let elapsed = now.duration_since(progress.last_time);
+        let bytes_diff = progress.total_written - progress.last_bytes;
+        let bytes_per_second = if elapsed.as_secs_f64() > 0.0 {
+            bytes_diff as f64 / elapsed.as_secs_f64()
+        } else {
+            0.0
+        };
+        let smoothed_bytes_per_second = progress
+            .throughput
+            .record(bytes_diff, elapsed.as_secs_f64());
+
+        // Update progress bar with the smoothed rate, and the time left for the
+        // whole wipe assuming the remaining passes run at the same rate
+        if let Some(ref pb) = progress.bar {
+            pb.set_position(progress.total_written);
+            pb.set_prefix(format!(
+                "{}/s",
+                HumanBytes(smoothed_bytes_per_second as u64)
+            ));
+            if smoothed_bytes_per_second > 0.0 {
+                pb.set_message(format!(
+                    "overall {}/{} passes, est. total remaining {}",
+                    progress.pass,
+                    progress.total_passes,
+                    format_duration(progress.remaining_bytes() as f64 / smoothed_bytes_per_second)
+                ));
+            }
+        }
+
+        // Emit JSON progress events
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::Progress {
+                pass: progress.pass,
+                total_passes: progress.total_passes,
+                bytes_written: progress.total_written,
+                total_bytes: progress.pass_bytes,
+                percent: (progress.total_written as f64 / progress.pass_bytes as f64) * 100.0,
+                bytes_per_second,
+                smoothed_bytes_per_second,
+            });
+        }
+
+        progress.last_time = now;
+        progress.last_bytes = progress.total_written;
+    }
+
+    /// Extrapolate the duration of the whole wipe from the throughput of the
+    /// start of the first pass, measured with the writes' own retries, throttle
+    /// and sync behaviour
+    fn report_estimate(&self, progress: &PassProgress) {
+        let probe_bytes = progress.total_written;
+        let bytes_per_second =
+            probe_bytes as f64 / progress.started.elapsed().as_secs_f64().max(f64::EPSILON);
+        let estimated_seconds =
+            (progress.pass_bytes * progress.total_passes as u64) as f64 / bytes_per_second;
+
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::Estimate {
+                probe_bytes,
+                bytes_per_second,
+                estimated_seconds,
+            });
+        } else if let Some(pb) = &progress.bar {
+            let prefix = if self.multi_progress.is_some() {
+                format!("{} ", self.target)
+            } else {
+                String::new()
+            };
+            pb.println(format!(
+                "{}Estimated time: {} ({} measured)",
+                prefix,
+                format_duration(estimated_seconds),
+                format_throughput(bytes_per_second)
+            ));
+        }
+    }
+
+    /// Say that the wipe stops at the current point of the pass after a cancel request
+    fn report_cancelled(&self, progress: &PassProgress) {
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::Cancelled {
+                pass: progress.pass,
+                bytes_written: progress.total_written,
+            });
+        } else {
+            let message = format!(
+                "Wipe cancelled at pass {} after {} of the pass",
+                progress.pass,
+                HumanBytes(progress.total_written)
+            );
+            match &progress.bar {
+                Some(pb) => pb.suspend(|| eprintln!("{}", message)),
+                None => eprintln!("{}", message),
+            }
+        }
+    }
+
+    /// Read back the region written by `pass` in the same chunks it was written
+    /// in, given as the `segments` of ranges written with one chunk length.
+    /// A deterministic pattern must read back exactly, and so must random data
+    /// from a generator that can produce it again from `random_stream`. Other
+    /// random data must no longer hold the previous pass's pattern, and chunks of
+    /// at least `UNIFORM_CHECK_MIN_BYTES` must not be a uniform fill. Chunks that
+    /// failed to write with `--skip-errors` are not checked.
+    fn verify_pass(
+        &mut self,
+        pass: usize,
+        pattern: &WipePattern,
+        random_stream: Option<&RandomStream>,
+        segments: &[(Vec<(u64, u64)>, usize)],
+    ) -> Result<()> {
+        let start = Instant::now();
+        let chunk_size = segments
+            .iter()
+            .map(|&(_, chunk_len)| chunk_len)
+            .max()
+            .unwrap_or(0);
+        let previous = (pass > 1)
+            .then(|| self.options.pass_pattern(pass - 1).0)
+            .filter(|previous| !previous.is_random());
+        let has_previous = previous.is_some();
+        let regenerate = random_stream.filter(|stream| stream.reproducible());
+
+        // Every chunk starts the pattern afresh, exactly like the writes did
+        let mut expected = vec![0u8; chunk_size];
+        let method = if !pattern.is_random() {
+            pattern.fill(&mut expected, pass);
+            "pattern"
+        } else if regenerate.is_some() {
+            "regenerated"
+        } else {
+            if let Some(previous) = &previous {
+                previous.fill(&mut expected, pass - 1);
+            }
+            "changed"
+        };
+
+        // Drop cached pages so the read-back comes from the device
+        self.file.drop_cache();
+
+        // Unbuffered handles need sector-aligned reads of whole sectors too
+        let mut read_buffer = avec_rt!(
+            [self.write_buffer.alignment()]| 0u8;
+            sector_padded_len(chunk_size, self.sector_size)
+        );
+
+        let mut verify_ranges = || -> Result<u64> {
+            let mut bytes_verified = 0u64;
+            let chunks = segments
+                .iter()
+                .flat_map(|(ranges, chunk_len)| range_chunks(ranges.clone(), *chunk_len));
+            for (offset, len) in chunks {
+                let chunk_end = offset + len as u64;
+
+                if cancel::is_cancelled() {
+                    anyhow::bail!("Verification cancelled by user at offset {}", offset);
+                }
+
+                let skipped = self.failed_ranges.iter().any(|range| {
+                    range.pass == pass
+                        && range.offset < chunk_end
+                        && offset < range.offset + range.length
+                });
+                if !skipped {
+                    let actual = &mut read_buffer[..sector_padded_len(len, self.sector_size)];
+                    self.file
+                        .seek(SeekFrom::Start(offset))
+                        .and_then(|_| self.file.read_exact(actual))
+                        .with_context(|| {
+                            format!("Failed to read back pass {} at offset {}", pass, offset)
+                        })?;
+                    let actual = &actual[..len];
+
+                    if let Some(stream) = regenerate {
+                        stream.fill(offset, &mut expected[..len]);
+                    }
+                    let matches = if !pattern.is_random() || regenerate.is_some() {
+                        actual == &expected[..len]
+                    } else {
+                        (!has_previous || actual != &expected[..len])
+                            && (len < UNIFORM_CHECK_MIN_BYTES
+                                || actual.iter().any(|&byte| byte != actual[0]))
+                    };
+                    if !matches {
+                        anyhow::bail!(
+                            "Verification of pass {} failed: the {} bytes at offset {} {}",
+                            pass,
+                            len,
+                            offset,
+                            if regenerate.is_some() {
+                                "do not match the random data written"
+                            } else if pattern.is_random() {
+                                "were not overwritten with random data"
+                            } else {
+                                "do not match the written pattern"
+                            }
+                        );
+                    }
+                    bytes_verified += len as u64;
+                }
+            }
+            Ok(bytes_verified)
+        };
+        let bytes_verified = verify_ranges()?;
+
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::PassVerified {
+                pass,
+                method: method.to_string(),
+                bytes_verified,
+                duration_seconds: start.elapsed().as_secs_f64(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Write `len` bytes of the buffer at `offset`, retrying transient errors with backoff
+    fn write_block(&mut self, pass: usize, offset: u64, len: usize) -> io::Result<()> {
+        let mut attempt = 0;
+
+        loop {
+            // Sequential writes need no seek; a retry must rewind to the block start
+            // since a failed write_all may have written part of the block
+            let result = if attempt == 0 {
+                self.write_from_buffer(0, len)
+            } else {
+                self.file
+                    .seek(SeekFrom::Start(offset))
+                    .and_then(|_| self.write_from_buffer(0, len))
+            };
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.options.retries && is_transient_write_error(&e) => {
+                    attempt += 1;
+                    let delay = retry_delay(attempt);
+
+                    if self.options.json_mode {
+                        self.emit(&ProgressEvent::Retry {
+                            pass,
+                            offset,
+                            attempt,
+                            max_retries: self.options.retries,
+                            delay_ms: delay.as_millis() as u64,
+                            message: e.to_string(),
+                        });
+                    } else {
+                        eprintln!(
+                            "Write error on pass {} at offset {}: {} (retry {}/{} in {} ms)",
+                            pass,
+                            offset,
+                            e,
+                            attempt,
+                            self.options.retries,
+                            delay.as_millis()
+                        );
+                    }
+
+                    std::thread::sleep(delay);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Rewrite a block whose write failed in small chunks, recording the chunks
+    /// the device rejects so the rest of the block still gets overwritten
+    fn recover_failed_block(&mut self, pass: usize, offset: u64, len: usize) -> Result<()> {
+        let mut chunk_start = 0;
+
+        while chunk_start < len {
+            let chunk_len = std::cmp::min(ERROR_CHUNK_SIZE, len - chunk_start);
+            let chunk_offset = offset + chunk_start as u64;
+
+            let result = self
+                .file
+                .seek(SeekFrom::Start(chunk_offset))
+                .and_then(|_| self.write_from_buffer(chunk_start, chunk_len));
+
+            if let Err(e) = result {
+                self.record_failed_range(pass, chunk_offset, chunk_len as u64, &e);
+            }
+
+            chunk_start += chunk_len;
+        }
+
+        // A failed write leaves the file position undefined, so reposition explicitly
+        self.file
+            .seek(SeekFrom::Start(offset + len as u64))
+            .with_context(|| format!("Failed to seek past bad block at offset {}", offset))?;
+
+        Ok(())
+    }
+
+    fn record_failed_range(&mut self, pass: usize, offset: u64, length: u64, error: &io::Error) {
+        // Merge with the previous region when the failures are contiguous
+        if let Some(last) = self.failed_ranges.last_mut() {
+            if last.pass == pass && last.offset + last.length == offset {
+                last.length += length;
+                return;
+            }
+        }
+
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::WriteError {
+                pass,
+                offset,
+                message: error.to_string(),
+            });
+        } else {
+            eprintln!(
+                "Write error on pass {} at offset {}: {} (skipping)",
+                pass, offset, error
+            );
+        }
+
+        self.failed_ranges.push(FailedRange {
+            pass,
+            offset,
+            length,
+        });
+    }
+
+    /// Warn once per run that a file on a journaling or copy-on-write
+    /// filesystem may have left copies of its data that overwriting the file
+    /// cannot reach
+    #[cfg(target_os = "linux")]
+    fn warn_journaled_filesystem(&self) {
+        let path = Path::new(&self.target);
+        let Some(filesystem) = crate::platform::linux::journaling_filesystem(path) else {
+            return;
+        };
+        if JOURNAL_WARNING_SHOWN.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        warn_target(
+            path,
+            format!(
+                "{} is on {}, which may keep copies of the old data in its journal or in remapped blocks. The filesystem was synced, but only wiping the whole device guarantees they are gone",
+                path.display(),
+                filesystem
+            ),
+            self.options.json_mode,
+        );
+    }
+
+    fn report_failed_ranges(&self) -> Result<()> {
+        let failed_bytes: u64 = self.failed_ranges.iter().map(|r| r.length).sum();
+
+        if self.options.json_mode {
+            self.emit(&ProgressEvent::FailedRegions {
+                failed_bytes,
+                regions: self.failed_ranges.clone(),
+            });
+        } else {
+            println!();
+            io::stdout().execute(SetForegroundColor(Color::Yellow))?;
+            println!(
+                "WARNING: {} bytes in {} region(s) could not be written:",
+                failed_bytes,
+                self.failed_ranges.len()
+            );
+            io::stdout().execute(ResetColor)?;
+            for range in &self.failed_ranges {
+                println!(
+                    "  pass {}: offset {} length {}",
+                    range.pass, range.offset, range.length
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Release the volume lock however the wipe ends (success, error, or cancellation)
+#[cfg(windows)]
+impl<W: WipeTarget> Drop for WipeContext<W> {
+    fn drop(&mut self) {
+        if self.volume_locked {
+            self.file.unlock_volume();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::parse_passes_spec;
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(0.0), "0.0s");
+        assert_eq!(format_duration(42.54), "42.5s");
+        assert_eq!(format_duration(59.94), "59.9s");
+        assert_eq!(format_duration(59.96), "1m 00s");
+        assert_eq!(format_duration(60.0), "1m 00s");
+        assert_eq!(format_duration(61.0), "1m 01s");
+        assert_eq!(format_duration(3599.4), "59m 59s");
+        assert_eq!(format_duration(3599.6), "1h 00m 00s");
+        assert_eq!(format_duration(5025.0), "1h 23m 45s");
+        assert_eq!(format_duration(100_000.0), "27h 46m 40s");
+    }
+
+    #[test]
+    fn test_preserve_ranges() {
+        let options = |preserve_head, preserve_tail| WipeOptions {
+            preserve_head,
+            preserve_tail,
+            ..Default::default()
+        };
+        let extents = vec![(0, 4096), (8192, 4096), (16384, 8192)];
+
+        assert_eq!(
+            preserve_ranges(extents.clone(), 24576, &options(0, 0), None).unwrap(),
+            extents
+        );
+        // Extents wholly inside the preserved regions are dropped, others clipped
+        assert_eq!(
+            preserve_ranges(extents.clone(), 24576, &options(4096, 4096), None).unwrap(),
+            vec![(8192, 4096), (16384, 4096)]
+        );
+        assert_eq!(
+            preserve_ranges(extents, 24576, &options(9000, 100), None).unwrap(),
+            vec![(9000, 3288), (16384, 8092)]
+        );
+
+        // Block devices are only preserved in whole sectors
+        assert!(
+            preserve_ranges(vec![(0, 1 << 20)], 1 << 20, &options(512, 0), Some(4096)).is_err()
+        );
+        assert!(preserve_ranges(
+            vec![(0, 1 << 20)],
+            1 << 20,
+            &options(8192, 4096),
+            Some(4096)
+        )
+        .is_ok());
+        assert!(preserve_ranges(
+            vec![(0, 1 << 20)],
+            1 << 20,
+            &options(1 << 19, 1 << 19),
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::from_millis(100));
+        assert_eq!(retry_delay(3), Duration::from_millis(400));
+        assert_eq!(retry_delay(6), Duration::from_millis(3200));
+        assert_eq!(retry_delay(7), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(16), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_format_throughput() {
+        assert_eq!(format_throughput(0.0), "0.00 MB/s");
+        assert_eq!(format_throughput(512.0 * 1_048_576.0), "512.00 MB/s");
+        assert_eq!(format_throughput(1023.99 * 1_048_576.0), "1023.99 MB/s");
+        assert_eq!(format_throughput(1024.0 * 1_048_576.0), "1.00 GB/s");
+        assert_eq!(format_throughput(2.5 * 1_073_741_824.0), "2.50 GB/s");
+    }
+
+    #[test]
+    fn test_parse_buffer_size() {
+        assert_eq!(parse_buffer_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_buffer_size("4M"), Ok(4 * 1024 * 1024));
+        assert_eq!(parse_buffer_size("1G"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_buffer_size("1.5G"), Ok(1536 * 1024 * 1024));
+        assert_eq!(parse_buffer_size("8m"), Ok(8 * 1024 * 1024));
+        assert_eq!(parse_buffer_size("16mb"), Ok(16 * 1024 * 1024));
+        assert_eq!(parse_buffer_size("4.5k"), Ok(4608));
+        assert_eq!(parse_buffer_size("8192B"), Ok(8192));
+        // A plain number is still a number of KB
+        assert_eq!(parse_buffer_size("4096"), Ok(4 * 1024 * 1024));
+
+        for invalid in [
+            "", "M", "-4M", "-1", "3K", "0.003M", "8X", "5G", "1e12G", "inf", "NaN",
+        ] {
+            assert!(parse_buffer_size(invalid).is_err(), "{}", invalid);
+        }
+        assert!(parse_buffer_size("8.8.8").unwrap_err().contains("like 8M"));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1T", 1), Ok(1 << 40));
+        assert_eq!(parse_size("500M", 1), Ok(500 << 20));
+        assert_eq!(parse_size("2.5g", 1), Ok(2560 << 20));
+        assert_eq!(parse_size("10", 1 << 20), Ok(10 << 20));
+        assert_eq!(parse_size("10B", 1 << 20), Ok(10));
+        assert_eq!(parse_size("0", 1), Ok(0));
+        for invalid in ["", "-1T", "8P", "9000000T", "1e30", "ten"] {
+            assert!(parse_size(invalid, 1).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_wipe_context_creation() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), b"test data").unwrap();
+
+        let result = WipeContext::new(
+            temp_file.path(),
+            false,
+            WipeOptions {
+                algorithm: WipeAlgorithm::Zero,
+                passes: 1,
+                ..Default::default()
+            },
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_fill_to_size_extends_and_wipes_file() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), vec![0xAB; 100]).unwrap();
+
+        let mut context = WipeContext::new(
+            temp_file.path(),
+            false,
+            WipeOptions {
+                algorithm: WipeAlgorithm::Gutmann,
+                passes: 1,
+                buffer_size_bytes: Some(64 * 1024),
+                json_mode: true,
+                sync_mode: Some(SyncMode::None),
+                last_pass_zero: true,
+                fill_to_size: Some(200 * 1024 + 5),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(context.size(), 200 * 1024 + 5);
+        context.wipe().unwrap();
+
+        let data = std::fs::read(temp_file.path()).unwrap();
+        assert_eq!(data.len(), 200 * 1024 + 5);
+        assert!(data.iter().all(|&byte| byte == 0x00));
+    }
+
+    #[test]
+    fn test_preserve_head_and_tail() {
+        const SIZE: usize = 300 * 1024 + 7;
+        let options = WipeOptions {
+            passes_spec: Some(parse_passes_spec("random,00").unwrap()),
+            buffer_size_bytes: Some(64 * 1024),
+            json_mode: true,
+            verify_each_pass: true,
+            preserve_head: 1000,
+            preserve_tail: 64 * 1024 + 3,
+            ..Default::default()
+        };
+
+        let mut data = vec![0x5A; SIZE];
+        let mut context =
+            WipeContext::from_target(Cursor::new(&mut data), "memory", options.clone()).unwrap();
+        context.wipe().unwrap();
+        let wiped = SIZE - 1000 - (64 * 1024 + 3);
+        assert_eq!(context.bytes_written(), 2 * wiped as u64);
+        drop(context);
+
+        assert!(data[..1000].iter().all(|&byte| byte == 0x5A));
+        assert!(data[1000..1000 + wiped].iter().all(|&byte| byte == 0x00));
+        assert!(data[1000 + wiped..].iter().all(|&byte| byte == 0x5A));
+
+        // Preserving the whole target leaves nothing to wipe
+        let mut data = vec![0x5A; SIZE];
+        let result = WipeContext::from_target(
+            Cursor::new(&mut data),
+            "memory",
+            WipeOptions {
+                preserve_head: SIZE as u64 / 2,
+                preserve_tail: SIZE as u64 / 2 + 1,
+                ..options
+            },
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_pass_records() {
+        const SIZE: usize = 200 * 1024 + 5;
+        let options = WipeOptions {
+            algorithm: WipeAlgorithm::Dod5220,
+            buffer_size_bytes: Some(64 * 1024),
+            json_mode: true,
+            verify_each_pass: true,
+            ..Default::default()
+        };
+
+        let mut data = vec![0x5A; SIZE];
+        let mut context =
+            WipeContext::from_target(Cursor::new(&mut data), "memory", options.clone()).unwrap();
+        context.wipe().unwrap();
+
+        let records = context.pass_records();
+        let patterns: Vec<&str> = records.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, ["0x00", "0xFF", "RAND"]);
+        for (index, record) in records.iter().enumerate() {
+            assert_eq!(record.pass, index + 1);
+            assert_eq!(record.bytes_written, SIZE as u64);
+            assert!(record.bytes_per_second > 0.0);
+            assert_eq!(record.verified, Some(true));
+        }
+
+        // --show-passes prints the plan the wipe just ran
+        let plan = options.pass_plan();
+        assert_eq!(plan.len(), records.len());
+        for (spec, record) in plan.iter().zip(records) {
+            assert_eq!(spec.pass, record.pass);
+            assert_eq!(spec.name, record.pattern);
+            assert_eq!(spec.verify, record.verified == Some(true));
+        }
+    }
+
+    #[test]
+    fn test_final_pass_digest_matches_written_data() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), vec![0xAB; 3 * 1024 * 1024 + 17]).unwrap();
+
+        let mut context = WipeContext::new(
+            temp_file.path(),
+            false,
+            WipeOptions {
+                algorithm: WipeAlgorithm::Dod5220,
+                buffer_size_bytes: Some(64 * 1024),
+                json_mode: true,
+                hash_final_pass: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        context.wipe().unwrap();
+
+        // The final DoD pass is random, so only the data on disk can tell what was written
+        let written = std::fs::read(temp_file.path()).unwrap();
+        let expected = format!("{:x}", Sha256::digest(&written));
+        assert_eq!(context.final_pass_sha256(), Some(expected.as_str()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_uring_backend_wipe() {
+        use crate::args::IoBackend;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), vec![0xAB; 3 * 1024 * 1024 + 17]).unwrap();
+
+        // Random data from worker threads and a fixed pattern both go through the ring buffers
+        let mut context = WipeContext::new(
+            temp_file.path(),
+            false,
+            WipeOptions {
+                algorithm: WipeAlgorithm::Dod5220,
+                buffer_size_bytes: Some(256 * 1024),
+                json_mode: true,
+                threads: 2,
+                hash_final_pass: true,
+                verify_each_pass: true,
+                io_backend: IoBackend::Uring,
+                queue_depth: 4,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        context.wipe().unwrap();
+        assert_eq!(context.bytes_written(), 3 * (3 * 1024 * 1024 + 17));
+
+        let written = std::fs::read(temp_file.path()).unwrap();
+        assert_eq!(written.len(), 3 * 1024 * 1024 + 17);
+        let expected = format!("{:x}", Sha256::digest(&written));
+        assert_eq!(context.final_pass_sha256(), Some(expected.as_str()));
+    }
+
+    #[test]
+    fn test_wipe_into_memory_produces_exact_bytes() {
+        const CHUNK: usize = 64 * 1024;
+        let wipe_into_memory = |options: WipeOptions| {
+            let mut data = vec![0x5A; 3 * CHUNK + 100];
+            let mut context =
+                WipeContext::from_target(Cursor::new(&mut data), "memory", options).unwrap();
+            context.wipe().unwrap();
+            let digest = context.final_pass_sha256().map(str::to_string);
+            drop(context);
+            (data, digest)
+        };
+        let options = |algorithm| WipeOptions {
+            algorithm,
+            passes: 2,
+            buffer_size_bytes: Some(64 * 1024),
+            json_mode: true,
+            hash_final_pass: true,
+            ..Default::default()
+        };
+
+        let (data, _) = wipe_into_memory(options(WipeAlgorithm::Zero));
+        assert!(data.iter().all(|&byte| byte == 0x00));
+
+        let (data, _) = wipe_into_memory(WipeOptions {
+            last_pass_zero: true,
+            ..options(WipeAlgorithm::Dod5220)
+        });
+        assert!(data.iter().all(|&byte| byte == 0x00));
+
+        // A pass spec runs exactly its own passes instead of the algorithm's
+        let spec_options = WipeOptions {
+            passes_spec: Some(parse_passes_spec("00,FF,random,AA").unwrap()),
+            verify_each_pass: true,
+            ..options(WipeAlgorithm::Gutmann)
+        };
+        assert_eq!(spec_options.total_passes(), 4);
+        assert_eq!(spec_options.pass_pattern(3).1, "RAND");
+        let (data, _) = wipe_into_memory(spec_options);
+        assert!(data.iter().all(|&byte| byte == 0xAA));
+
+        // The final Gutmann pass repeats 49 24 92 from the start of every write
+        let (data, _) = wipe_into_memory(options(WipeAlgorithm::Gutmann));
+        for (chunk_index, chunk) in data.chunks(CHUNK).enumerate() {
+            for (i, &byte) in chunk.iter().enumerate() {
+                assert_eq!(
+                    byte,
+                    [0x49, 0x24, 0x92][i % 3],
+                    "chunk {} byte {}",
+                    chunk_index,
+                    i
+                );
+            }
+        }
+
+        // Random final passes can only be checked against what the wipe says it wrote
+        for algorithm in [
+            WipeAlgorithm::Random,
+            WipeAlgorithm::Dod5220,
+            WipeAlgorithm::Custom,
+        ] {
+            let (data, digest) = wipe_into_memory(options(algorithm));
+            assert_eq!(data.len(), 3 * CHUNK + 100);
+            assert!(data.iter().any(|&byte| byte != 0x5A));
+            assert_eq!(digest, Some(format!("{:x}", Sha256::digest(&data))));
+        }
+    }
+
+    /// Wipe 16 MB in memory with one random pass, checking that the buffers
+    /// landed in the order they were hashed, and return how long it took
+    fn wipe_random_pass(pipeline: bool) -> f64 {
+        const SIZE: usize = 16 * 1024 * 1024;
+        let mut data = vec![0x5A; SIZE];
+        let mut context = WipeContext::from_target(
+            Cursor::new(&mut data),
+            "memory",
+            WipeOptions {
+                algorithm: WipeAlgorithm::Random,
+                buffer_size_bytes: Some(1024 * 1024),
+                json_mode: true,
+                hash_final_pass: true,
+                pipeline: Some(pipeline),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let start = Instant::now();
+        context.wipe().unwrap();
+        let elapsed = start.elapsed().as_secs_f64();
+        let digest = context.final_pass_sha256().map(str::to_string);
+        drop(context);
+
+        // Buffers from the worker land in order, exactly as they were hashed
+        assert_eq!(digest, Some(format!("{:x}", Sha256::digest(&data))));
+        elapsed
+    }
+
+    #[test]
+    fn test_pipelined_random_pass() {
+        wipe_random_pass(false);
+        wipe_random_pass(true);
+    }
+
+    #[test]
+    #[ignore = "timing comparison; run with --ignored on an otherwise idle machine"]
+    fn test_pipelined_random_pass_speed() {
+        // Generation only overlaps the writes when another CPU can run the worker
+        if std::thread::available_parallelism().is_ok_and(|cpus| cpus.get() > 1) {
+            let inline = wipe_random_pass(false);
+            let pipelined = wipe_random_pass(true);
+            assert!(
+                pipelined < inline * 1.2,
+                "{:.3}s pipelined vs {:.3}s inline",
+                pipelined,
+                inline
+            );
+        }
+    }
+
+    #[test]
+    fn test_adaptive_buffer_size() {
+        // Not a multiple of the three-byte Gutmann patterns or of any buffer size
+        const SIZE: usize = 4 * 1024 * 1024 + 1000;
+        for pipeline in [false, true] {
+            let mut data = vec![0x5A; SIZE];
+            let mut context = WipeContext::from_target(
+                Cursor::new(&mut data),
+                "memory",
+                WipeOptions {
+                    algorithm: WipeAlgorithm::Gutmann,
+                    json_mode: true,
+                    hash_final_pass: true,
+                    verify_each_pass: true,
+                    pipeline: Some(pipeline),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .with_buffer_candidates(vec![4096, 16 * 1024, 64 * 1024]);
+
+            // Every pass reads back in the chunks it was written in, trials included
+            context.wipe().unwrap();
+            assert_eq!(context.verified(), Some(true));
+            assert_eq!(context.buffer_len(), 64 * 1024);
+            let digest = context.final_pass_sha256().map(str::to_string);
+            drop(context);
+            assert_eq!(digest, Some(format!("{:x}", Sha256::digest(&data))));
+        }
+    }
+
+    #[test]
+    fn test_verify_each_pass() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), vec![0x5A; 256 * 1024 + 100]).unwrap();
+
+        // Gutmann covers fixed bytes, three-byte patterns, and random passes
+        let mut context = WipeContext::new(
+            temp_file.path(),
+            false,
+            WipeOptions {
+                algorithm: WipeAlgorithm::Gutmann,
+                buffer_size_bytes: Some(64 * 1024),
+                json_mode: true,
+                sync_mode: Some(SyncMode::None),
+                verify_each_pass: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        context.wipe().unwrap();
+        assert_eq!(context.passes_completed(), 35);
+    }
+
+    #[test]
+    fn test_verify_random_pass_of_tiny_file() {
+        // OS random data cannot be regenerated, and a file shorter than a sector
+        // is read back as one chunk that may be uniform by chance
+        for size in [1, 2, 511] {
+            let temp_file = NamedTempFile::new().unwrap();
+            std::fs::write(temp_file.path(), vec![b'A'; size]).unwrap();
+            let mut context = WipeContext::new(
+                temp_file.path(),
+                false,
+                WipeOptions {
+                    algorithm: WipeAlgorithm::Random,
+                    verify_each_pass: true,
+                    rng: RngKind::Os,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            context.wipe().unwrap();
+            assert_eq!(context.passes_completed(), 1, "{} bytes", size);
+        }
+    }
+
+    #[test]
+    fn test_verify_regenerates_aes_ctr_stream() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::fs::write(temp_file.path(), vec![0x5A; 1024 * 1024 + 100]).unwrap();
+
+        // The pool, the split buffers and the read-back must all agree on the
+        // data at every offset, or the comparison with the regenerated stream fails
+        let mut context = WipeContext::new(
+            temp_file.path(),
+            false,
+            WipeOptions {
+                algorithm: WipeAlgorithm::Custom,
+                passes: 2,
+                buffer_size_bytes: Some(192 * 1024),
+                json_mode: true,
+                sync_mode: Some(SyncMode::None),
+                verify_each_pass: true,
+                rng: RngKind::AesCtr,
+                threads: 2,
+                rng_threads: 3,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        context.wipe().unwrap();
+        assert_eq!(context.passes_completed(), 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_direct_io_wipe_of_loop_device() {
+        use crate::demo::LoopDevice;
+
+        let backing_file = NamedTempFile::new().unwrap();
+        std::fs::write(backing_file.path(), vec![0x5A; 1024 * 1024]).unwrap();
+        let loop_device = match LoopDevice::attach(backing_file.path(), None, true) {
+            Ok(loop_device) => loop_device,
+            Err(e) => {
+                eprintln!("Skipping O_DIRECT test: {:#}", e);
+                return;
+            }
+        };
+
+        // A 6 KB buffer is not a multiple of 4096-byte pages, only of the block size
+        let mut context = WipeContext::new(
+            loop_device.path(),
+            true,
+            WipeOptions {
+                algorithm: WipeAlgorithm::Dod5220,
+                buffer_size_bytes: Some(6 * 1024),
+                json_mode: true,
+                hash_final_pass: true,
+                verify_each_pass: true,
+                direct_io: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(context.io_mode(), IoMode::Direct);
+        context.wipe().unwrap();
+        let digest = context.final_pass_sha256().map(str::to_string);
+        drop(context);
+
+        let written = std::fs::read(loop_device.path()).unwrap();
+        assert_eq!(written.len(), 1024 * 1024);
+        assert_eq!(digest, Some(format!("{:x}", Sha256::digest(&written))));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_wipe_of_loop_device_with_4k_sectors() {
+        use crate::demo::LoopDevice;
+
+        const SIZE: usize = 1024 * 1024;
+        let backing_file = NamedTempFile::new().unwrap();
+        let loop_device = match std::fs::write(backing_file.path(), vec![0x5A; SIZE])
+            .map_err(anyhow::Error::from)
+            .and_then(|_| LoopDevice::attach(backing_file.path(), Some(4096), true))
+        {
+            Ok(loop_device) => loop_device,
+            Err(e) => {
+                eprintln!("Skipping 4K sector test: {:#}", e);
+                return;
+            }
+        };
+
+        // 13 KB is rounded down to three sectors, so the pass ends with a single-sector write
+        for direct_io in [false, true] {
+            let mut context = WipeContext::new(
+                loop_device.path(),
+                true,
+                WipeOptions {
+                    passes_spec: Some(parse_passes_spec("random,C3").unwrap()),
+                    buffer_size_bytes: Some(13 * 1024),
+                    json_mode: true,
+                    verify_each_pass: true,
+                    direct_io,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(context.buffer_len(), 3 * 4096);
+            assert_eq!(context.io_mode() == IoMode::Direct, direct_io);
+            context.wipe().unwrap();
+            drop(context);
+
+            let written = std::fs::read(loop_device.path()).unwrap();
+            assert_eq!(written.len(), SIZE);
+            assert!(written.iter().all(|&byte| byte == 0xC3));
+        }
+    }
+
+    /// In-memory wipe target whose device hooks tests can watch or fail
+    struct MemoryTarget {
+        data: Cursor<Vec<u8>>,
+        /// Regions the target was asked to write back, shared with the test
+        syncs: Rc<RefCell<Vec<(u64, u64)>>>,
+        /// OS error code every flush to disk fails with, as if the device went away
+        flush_error: Option<i32>,
+    }
+
+    impl MemoryTarget {
+        fn new(data: Vec<u8>) -> Self {
+            MemoryTarget {
+                data: Cursor::new(data),
+                syncs: Rc::default(),
+                flush_error: None,
+            }
+        }
+    }
+
+    impl Read for MemoryTarget {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.data.read(buf)
+        }
+    }
+
+    impl Write for MemoryTarget {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for MemoryTarget {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl WipeTarget for MemoryTarget {
+        fn flush_to_disk(&self) -> io::Result<()> {
+            self.flush_error
+                .map_or(Ok(()), |code| Err(io::Error::from_raw_os_error(code)))
+        }
+
+        fn sync_range(&self, offset: u64, len: u64) {
+            self.syncs.borrow_mut().push((offset, len));
+        }
+    }
+
+    #[test]
+    fn test_periodic_sync_of_written_ranges() {
+        const MB: u64 = 1024 * 1024;
+
+        let target = MemoryTarget::new(vec![0x5A; 5 * MB as usize + 100]);
+        let syncs = Rc::clone(&target.syncs);
+        let mut context = WipeContext::from_target(
+            target,
+            "memory",
+            WipeOptions {
+                algorithm: WipeAlgorithm::Zero,
+                buffer_size_bytes: Some(256 * 1024),
+                sync_interval_mb: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        context.wipe().unwrap();
+
+        // The tail is left to the end-of-pass flush
+        assert_eq!(*syncs.borrow(), [(0, 2 * MB), (2 * MB, 2 * MB)]);
+    }
+
+    #[test]
+    fn test_failed_flush_aborts_the_wipe() {
+        // EIO on Unix; any OS error code will do
+        const IO_ERROR: i32 = 5;
+
+        // The device went away before the end-of-pass flush
+        let failing_flush = || MemoryTarget {
+            flush_error: Some(IO_ERROR),
+            ..MemoryTarget::new(vec![0x5A; 64 * 1024])
+        };
+
+        let mut context = WipeContext::from_target(
+            failing_flush(),
+            "memory",
+            WipeOptions {
+                algorithm: WipeAlgorithm::Dod5220,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let error = context.wipe().unwrap_err();
+
+        assert!(format!("{:#}", error).contains("Failed to sync pass 1 of memory to disk"));
+        assert_eq!(
+            error
+                .root_cause()
+                .downcast_ref::<io::Error>()
+                .unwrap()
+                .raw_os_error(),
+            Some(IO_ERROR)
+        );
+        assert_eq!(context.passes_completed(), 0);
+
+        // Without flushes during the passes, the final flush still fails the wipe
+        let mut context = WipeContext::from_target(
+            failing_flush(),
+            "memory",
+            WipeOptions {
+                algorithm: WipeAlgorithm::Zero,
+                sync_mode: Some(SyncMode::None),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(context.wipe().is_err());
+        assert_eq!(context.passes_completed(), 1);
+
+        // --no-sync skips the end-of-pass flushes but keeps the final one
+        let mut context = WipeContext::from_target(
+            failing_flush(),
+            "memory",
+            WipeOptions {
+                algorithm: WipeAlgorithm::Dod5220,
+                no_sync: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(context.wipe().is_err());
+        assert_eq!(context.passes_completed(), 3);
+    }
+
+    #[test]
+    fn test_buffer_size_limits() {
+        let buffer_len = |buffer_size_kb: usize, jobs| {
+            let mut data = vec![0u8; 4096];
+            let options = WipeOptions {
+                buffer_size_bytes: Some(buffer_size_kb * 1024),
+                jobs,
+                ..Default::default()
+            };
+            WipeContext::from_target(Cursor::new(&mut data), "memory", options)
+                .map(|context| context.buffer_len())
+        };
+        assert_eq!(buffer_len(4, 1).unwrap(), 4096);
+        assert_eq!(buffer_len(64, 1).unwrap(), 64 * 1024);
+
+        // A terabyte buffer is refused instead of failing in the allocator
+        let error = buffer_len(1 << 30, 1).unwrap_err().to_string();
+        assert!(error.contains("--max-buffer-memory"), "{}", error);
+
+        // Every parallel job allocates its own buffer
+        assert!(buffer_len(64, 1 << 24).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_no_cache_keeps_wiped_file_out_of_page_cache() {
+        const SIZE: u64 = 64 * 1024 * 1024;
+
+        // Bytes of the file resident in the page cache, from mincore on a
+        // mapping of it, so that other activity on the machine does not count
+        let resident = |path: &std::path::Path| -> u64 {
+            use std::os::unix::io::AsRawFd;
+            let file = std::fs::File::open(path).unwrap();
+            let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+            let mut pages = vec![0u8; (SIZE as usize).div_ceil(page)];
+            unsafe {
+                let map = libc::mmap(
+                    std::ptr::null_mut(),
+                    SIZE as usize,
+                    libc::PROT_READ,
+                    libc::MAP_SHARED,
+                    file.as_raw_fd(),
+                    0,
+                );
+                assert_ne!(map, libc::MAP_FAILED);
+                let result = libc::mincore(map, SIZE as usize, pages.as_mut_ptr());
+                libc::munmap(map, SIZE as usize);
+                assert_eq!(result, 0, "{}", std::io::Error::last_os_error());
+            }
+            pages.iter().filter(|&&page| page & 1 != 0).count() as u64 * page as u64
+        };
+        let wipe = |no_cache: bool| {
+            // Sparse, so that creating the file caches nothing
+            let temp_file = NamedTempFile::new().unwrap();
+            temp_file.as_file().set_len(SIZE).unwrap();
+
+            let mut context = WipeContext::new(
+                temp_file.path(),
+                false,
+                WipeOptions {
+                    algorithm: WipeAlgorithm::Zero,
+                    no_cache: Some(no_cache),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            context.wipe().unwrap();
+            resident(temp_file.path())
+        };
+
+        assert!(wipe(true) < SIZE / 2);
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_wipe_dmg_backed_raw_disk() {
+        use std::process::Command;
+
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("wipe.dmg");
+        let status = Command::new("hdiutil")
+            .args(["create", "-size", "1m", "-layout", "NONE", "-type", "UDIF"])
+            .arg(&image)
+            .status()
+            .unwrap();
+        assert!(status.success(), "hdiutil create failed");
+
+        let output = Command::new("hdiutil")
+            .args(["attach", "-nomount"])
+            .arg(&image)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "hdiutil attach failed");
+        let disk = String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .to_string();
+        let raw_disk = platform::macos::raw_disk_path(std::path::Path::new(&disk)).unwrap();
+
+        // A 6 KB buffer leaves a short, still block-aligned write at the end of the disk
+        let result = WipeContext::new(
+            std::path::Path::new(&raw_disk),
+            true,
+            WipeOptions {
+                algorithm: WipeAlgorithm::Dod5220,
+                buffer_size_bytes: Some(6 * 1024),
+                json_mode: true,
+                hash_final_pass: true,
+                ..Default::default()
+            },
+        )
+        .and_then(|mut context| {
+            context.wipe()?;
+            Ok(context.final_pass_sha256().map(str::to_string))
+        });
+        let written = std::fs::read(&raw_disk);
+        let _ = Command::new("hdiutil").args(["detach", &disk]).status();
+
+        let written = written.unwrap();
+        assert_eq!(written.len(), 1024 * 1024);
+        assert_eq!(
+            result.unwrap(),
+            Some(format!("{:x}", Sha256::digest(&written)))
+        );
+    }
 }